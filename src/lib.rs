@@ -0,0 +1,22 @@
+//! A simulator for the LEGv8 subset of ARM taught in CS251.
+//!
+//! Instructions are parsed from text with `str::parse`, and a `Simulator`
+//! runs them one at a time with `tick`. Simulators serialize with serde, in
+//! the same format the `cs251simulator` binary saves and loads.
+//!
+//! ```
+//! use cs251simulator::simulator::{RunningState, Simulator};
+//!
+//! let mut sim = Simulator::new();
+//!
+//! sim.instructions.push("ADDI X1, XZR, #5".parse()?);
+//! sim.instructions.push("ADD X2, X1, X1".parse()?);
+//!
+//! while let RunningState::KeepRunning = sim.tick()? {}
+//!
+//! assert_eq!(sim.registers.get(2)?, 10);
+//! # Ok::<(), color_eyre::Report>(())
+//! ```
+
+pub mod simulator;
+pub mod theme;
@@ -1,70 +1,443 @@
+use std::{io::Read, sync::mpsc, thread, time::Duration};
+
 use clap::{Parser, Subcommand};
 
-mod simulator;
+use cs251simulator::simulator;
+
+// The UI and its settings are only for the binary, everything else is in
+// the library.
 mod ui;
 mod util;
 
-use color_eyre::Report;
-use simulator::{RunningState, Simulator};
+use color_eyre::{eyre::bail, Report};
+use ratatui::text::Span;
+use simulator::{Expectation, Instruction, Profile, RunningState, Simulator};
 use ui::setup_and_run_tui;
+use util::{remember_file, Keymap, Preferences, Theme};
 
 #[derive(Parser)]
 struct Args {
     #[command(subcommand)]
     specific: Option<Specific>,
+
+    /// JSON file of UI colors, see the readme
+    #[arg(long, global = true)]
+    theme: Option<String>,
+
+    /// TOML file of key bindings, see the readme
+    #[arg(long, global = true)]
+    keymap: Option<String>,
+
+    /// JSON file of key presses to replay in the UI, which is saved to on
+    /// exit, see the readme
+    #[arg(long = "macro", global = true)]
+    macro_file: Option<String>,
+
+    /// Start with garbage from this seed in every register and low memory
+    /// slot which would otherwise be zero
+    #[arg(long, global = true)]
+    seed: Option<u64>,
+
+    /// Name registers and warn about reserved ones by these conventions,
+    /// rather than whatever the file was saved with, see the readme
+    #[arg(long, global = true)]
+    profile: Option<Profile>,
+}
+
+/// Where to get the starting state from. Any of these can be `-` to read
+/// from stdin instead.
+#[derive(clap::Args)]
+struct Source {
+    /// A file saved by this program, or `-` for stdin
+    #[arg(short, long, required_unless_present_any = ["listing", "from_hex"])]
+    file: Option<String>,
+
+    /// A plain assembly listing, like the ones in the textbook, or `-` for
+    /// stdin
+    #[arg(long, visible_alias = "asm", conflicts_with = "file")]
+    listing: Option<String>,
+
+    /// Machine code, one word of 8 hex digits per line, or `-` for stdin
+    #[arg(long, conflicts_with_all = ["file", "listing"])]
+    from_hex: Option<String>,
+}
+
+impl Source {
+    fn load(&self) -> Result<Simulator, Report> {
+        if let Some(words) = &self.from_hex {
+            return Simulator::from_hex(&read_input(words)?);
+        }
+
+        match (&self.file, &self.listing) {
+            (_, Some(listing)) => {
+                let listing = read_input(listing)?;
+                let (sim, warnings) = Simulator::from_listing(&listing)?;
+
+                for warning in warnings {
+                    eprintln!("Warning: {warning}");
+                }
+
+                Ok(sim)
+            }
+            (Some(file), None) => load_file(file),
+            (None, None) => bail!("No file given!"),
+        }
+    }
 }
 
 #[derive(Subcommand)]
 enum Specific {
     Run {
-        #[arg(short, long)]
-        file: String,
+        #[command(flatten)]
+        source: Source,
 
         #[arg(long, default_value_t = 1000)]
         max_iters: usize,
 
+        /// Where to save the final state, even when the program was read
+        /// from stdin, like `cat prog.s | cs251simulator run --asm - --out
+        /// result.json`
         #[arg(short, long)]
         out: String,
+
+        /// JSON file listing the expected final registers/memory
+        #[arg(long)]
+        expect: Option<String>,
+
+        /// Give up if the program hasn't finished after this many milliseconds
+        #[arg(long)]
+        timeout_ms: Option<u64>,
+
+        /// Stop when a store writes to this byte address, may be repeated
+        #[arg(long)]
+        watch: Vec<u64>,
+
+        /// Write `--out` as compact JSON rather than pretty-printed
+        #[arg(long)]
+        compact: bool,
+
+        /// Fail any store at or past this byte address
+        #[arg(long)]
+        mem_limit: Option<u64>,
+
+        /// Also write the final registers, then the memory slots which
+        /// aren't zero, as CSV for a spreadsheet
+        #[arg(long)]
+        csv_out: Option<String>,
     },
-    
+
     Load {
-        #[arg(short, long)]
-        file: String,
+        #[command(flatten)]
+        source: Source,
+    },
+
+    /// Print every difference between two saved states
+    Diff { a: String, b: String },
+
+    /// Print the program along with anything found by static analysis
+    Dump {
+        #[command(flatten)]
+        source: Source,
+
+        /// Also write the program as GNU assembler (aarch64) source
+        #[arg(long)]
+        gas_out: Option<String>,
+
+        /// Also write the program's control-flow graph as Graphviz DOT
+        #[arg(long)]
+        cfg_out: Option<String>,
+    },
+
+    /// Print what a single instruction does, like the UI's explanation
+    Explain {
+        /// The instruction, like "ADD X1, X2, X3"
+        instr: String,
+
+        /// A file saved by this program, to take registers and memory from
+        /// rather than all zeros
+        #[arg(long)]
+        regs: Option<String>,
     },
 }
 
 fn main() -> Result<(), Report> {
+    color_eyre::install()?;
+
     let args = Args::parse();
 
+    let mut prefs = Preferences::load();
+
+    let theme = match &args.theme {
+        Some(path) => Theme::from_config(&std::fs::read_to_string(path)?)?,
+        None if prefs.light_theme => Theme::light(),
+        None => Theme::dark(),
+    };
+
+    let keymap = match &args.keymap {
+        Some(path) => Keymap::from_config(&std::fs::read_to_string(path)?)?,
+        None => Keymap::default(),
+    };
+
+    let seeded = |mut sim: Simulator| {
+        if let Some(seed) = args.seed {
+            sim.fill_garbage(seed);
+        }
+
+        if let Some(profile) = args.profile {
+            sim.profile = profile;
+        }
+
+        sim
+    };
+
     match args.specific {
-        None => setup_and_run_tui(Simulator::new())?,
+        None => setup_and_run_tui(
+            seeded(Simulator::new()),
+            theme,
+            keymap,
+            &prefs,
+            args.macro_file.as_deref(),
+        )?,
         Some(Specific::Run {
-            file,
+            source,
             max_iters,
             out,
+            expect,
+            timeout_ms,
+            watch,
+            compact,
+            mem_limit,
+            csv_out,
         }) => {
-            let file = std::fs::read_to_string(&file)?;
-            let mut sim = serde_json::from_str::<Simulator>(&file)?;
+            let mut sim = seeded(source.load()?);
+            sim.reset_pc();
 
-            for i in 0..max_iters {
-                if let RunningState::ShouldStop = sim.tick()? {
-                    eprintln!("Successfully exited after {i} iterations");
-                    break;
+            if let Err(problems) = sim.validate_program() {
+                for (idx, problem) in &problems {
+                    eprintln!("{idx}: {problem}");
                 }
+
+                bail!(
+                    "Not running, as the program has {} problem(s)",
+                    problems.len()
+                );
+            }
+
+            if mem_limit.is_some() {
+                sim.memory.max_addr = mem_limit;
             }
 
-            let to_write = serde_json::to_string_pretty(&sim)?;
+            for addr in watch {
+                if !addr.is_multiple_of(8) {
+                    bail!("Byte address {addr} is not a multiple of 8!");
+                }
+
+                sim.watchpoints.insert(addr);
+            }
+
+            let (sender, receiver) = mpsc::channel();
+
+            thread::spawn(move || {
+                let _ = sender.send(run(sim, max_iters));
+            });
+
+            let sim = match timeout_ms {
+                Some(ms) => match receiver.recv_timeout(Duration::from_millis(ms)) {
+                    Ok(result) => result?,
+                    Err(_) => {
+                        eprintln!("Error: execution timed out after {ms}ms");
+                        std::process::exit(1);
+                    }
+                },
+                None => receiver.recv()??,
+            };
+
+            let to_write = if compact {
+                serde_json::to_string(&sim)?
+            } else {
+                serde_json::to_string_pretty(&sim)?
+            };
 
             std::fs::write(out, to_write)?;
+
+            if let Some(csv_out) = csv_out {
+                let csv = format!("{}\n{}", sim.registers_csv(), sim.memory_csv());
+
+                std::fs::write(csv_out, csv)?;
+            }
+
+            if let Some(expect) = expect {
+                let expect = std::fs::read_to_string(&expect)?;
+                let expect = serde_json::from_str::<Expectation>(&expect)?;
+
+                let mismatches = expect.mismatches(&sim)?;
+
+                for mismatch in &mismatches {
+                    eprintln!("{mismatch}");
+                }
+
+                if !mismatches.is_empty() {
+                    std::process::exit(1);
+                }
+            }
         }
 
-        Some(Specific::Load { file }) => {
-            let file = std::fs::read_to_string(&file)?;
-            let sim = serde_json::from_str::<Simulator>(&file)?;
+        Some(Specific::Load { source }) => {
+            let mut sim = seeded(source.load()?);
+            sim.reset_pc();
 
-            setup_and_run_tui(sim)?;
+            if let Some(file) = source.file.as_deref().filter(|&x| x != "-") {
+                remember_file(&mut prefs.recent_files, file);
+            }
+
+            setup_and_run_tui(sim, theme, keymap, &prefs, args.macro_file.as_deref())?;
+        }
+
+        Some(Specific::Diff { a, b }) => {
+            let a = load_file(&a)?;
+            let b = load_file(&b)?;
+
+            let diff = a.diff(&b);
+
+            if diff.is_empty() {
+                println!("No differences");
+            } else {
+                print!("{diff}");
+            }
+        }
+
+        Some(Specific::Dump {
+            source,
+            gas_out,
+            cfg_out,
+        }) => {
+            let sim = source.load()?;
+
+            if let Some(gas_out) = gas_out {
+                std::fs::write(gas_out, sim.to_gas_asm())?;
+            }
+
+            if let Some(cfg_out) = cfg_out {
+                std::fs::write(cfg_out, sim.to_dot())?;
+            }
+
+            for (idx, instr) in sim.instructions.iter().enumerate() {
+                println!("{idx:>4}: {instr}");
+            }
+
+            let hazards = sim.detect_hazards();
+
+            println!();
+            println!("Load-use hazards: {}", hazards.len());
+
+            for (load, usage) in hazards {
+                println!("  {load} -> {usage}: {}", sim.instructions[usage]);
+            }
+
+            let reachable = sim.reachable_instructions();
+            let unreachable = sim
+                .instructions
+                .iter()
+                .enumerate()
+                .filter(|&(idx, instr)| {
+                    !reachable.contains(&idx)
+                        && !matches!(instr, Instruction::None | Instruction::Comment(_))
+                })
+                .collect::<Vec<_>>();
+
+            println!();
+            println!("Unreachable instructions: {}", unreachable.len());
+
+            for (idx, instr) in unreachable {
+                println!("  {idx}: {instr}");
+            }
+
+            let problems = sim.validate_program().err().unwrap_or_default();
+
+            println!();
+            println!("Problems: {}", problems.len());
+
+            for (idx, problem) in problems {
+                println!("  {idx}: {problem}");
+            }
+        }
+
+        Some(Specific::Explain { instr, regs }) => {
+            let sim = match regs {
+                Some(path) => load_file(&path)?,
+                None => Simulator::new(),
+            };
+
+            let instr = match instr.parse::<Instruction>() {
+                Ok(x) => x,
+                Err(e) => {
+                    eprintln!("{e}");
+                    std::process::exit(1);
+                }
+            };
+
+            println!("{}", plain(&instr.explain_unsub(sim.word_bits, &theme)));
+            println!(
+                "{}",
+                plain(&instr.explain_sub(
+                    &sim.registers,
+                    &sim.memory,
+                    sim.word_bits,
+                    false,
+                    &theme
+                ))
+            );
         }
     }
 
     Ok(())
 }
+
+/// The text of some spans, without any of their styling.
+fn plain(spans: &[Span]) -> String {
+    spans.iter().map(|x| &*x.content).collect()
+}
+
+/// The contents of the file at `path`, or everything on stdin for `-`.
+fn read_input(path: &str) -> Result<String, Report> {
+    if path == "-" {
+        let mut text = String::new();
+        std::io::stdin().read_to_string(&mut text)?;
+
+        return Ok(text);
+    }
+
+    Ok(std::fs::read_to_string(path)?)
+}
+
+fn load_file(path: &str) -> Result<Simulator, Report> {
+    let file = read_input(path)?;
+    let mut sim = serde_json::from_str::<Simulator>(&file)?;
+
+    sim.migrate()?;
+
+    Ok(sim)
+}
+
+fn run(mut sim: Simulator, max_iters: usize) -> Result<Simulator, Report> {
+    for i in 0..max_iters {
+        let pc = sim.registers.pc;
+
+        match sim.tick()? {
+            RunningState::KeepRunning => {}
+            RunningState::ShouldStop => {
+                eprintln!(
+                    "Successfully exited after {i} iterations (~{} cycles)",
+                    sim.cycles
+                );
+                break;
+            }
+            RunningState::Watchpoint { addr, old, new } => {
+                eprintln!("Watchpoint hit: instruction {pc} changed M[{addr}] from {old} to {new}");
+                break;
+            }
+        }
+    }
+
+    Ok(sim)
+}
@@ -0,0 +1,70 @@
+use std::fmt::Write;
+
+use super::{register_name, Simulator};
+
+impl Simulator {
+    /// Every register as CSV, one `reg,value` row each, named and sized by
+    /// the current word size. PC comes last, as a byte address like the
+    /// register view shows it.
+    pub fn registers_csv(&self) -> String {
+        let mut out = String::from("reg,value\n");
+
+        for idx in 0..31 {
+            let value = self.registers.get_word(idx, self.word_bits).unwrap();
+
+            writeln!(out, "{},{value}", register_name(idx, self.word_bits)).unwrap();
+        }
+
+        writeln!(out, "PC,{}", self.registers.pc * 4).unwrap();
+
+        out
+    }
+
+    /// Every memory slot which isn't zero as CSV, one `addr,value` row each
+    /// by byte address, in order.
+    pub fn memory_csv(&self) -> String {
+        let mut out = String::from("addr,value\n");
+
+        for slot in self.memory.get_used() {
+            let addr = slot.wrapping_mul(8);
+            let value = self.memory.get(addr).unwrap();
+
+            if value != 0 {
+                writeln!(out, "{addr},{value}").unwrap();
+            }
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exports_a_known_state() {
+        let mut sim = Simulator::new();
+        sim.registers.set(1, 42).unwrap();
+        sim.registers.set(30, u64::MAX).unwrap();
+        sim.registers.pc = 3;
+
+        // Out of order, and with a slot written back to zero
+        sim.memory.set(24, 7).unwrap();
+        sim.memory.set(8, 5).unwrap();
+        sim.memory.set(16, 9).unwrap();
+        sim.memory.set(16, 0).unwrap();
+
+        let registers = sim.registers_csv();
+        let rows = registers.lines().collect::<Vec<_>>();
+
+        assert_eq!(rows.len(), 33);
+        assert_eq!(rows[..3], ["reg,value", "X0,0", "X1,42"]);
+        assert_eq!(rows[31..], ["X30,18446744073709551615", "PC,12"]);
+
+        assert_eq!(sim.memory_csv(), "addr,value\n8,5\n24,7\n");
+
+        sim.word_bits = 32;
+        assert!(sim.registers_csv().contains("\nW30,4294967295\n"));
+    }
+}
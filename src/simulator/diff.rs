@@ -0,0 +1,108 @@
+use std::fmt::Display;
+
+use super::{Instruction, Simulator};
+
+/// Everything which differs between two simulator states, see
+/// `Simulator::diff`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SimulatorDiff {
+    /// register index, old value, new value
+    pub registers: Vec<(u8, u64, u64)>,
+    /// old and new byte address
+    pub pc: Option<(u64, u64)>,
+    /// byte address, old value, new value, where 0 means the slot is unused
+    pub memory: Vec<(u64, u64, u64)>,
+    /// instruction index, old, new, with `None` past the end of a program
+    pub instructions: Vec<(usize, Option<Instruction>, Option<Instruction>)>,
+}
+
+impl SimulatorDiff {
+    pub fn new(a: &Simulator, b: &Simulator) -> Self {
+        let registers = (0..31)
+            .filter_map(|idx| {
+                let old = a.registers.get(idx).unwrap();
+                let new = b.registers.get(idx).unwrap();
+
+                (old != new).then_some((idx, old, new))
+            })
+            .collect();
+
+        let pc =
+            (a.registers.pc != b.registers.pc).then_some((a.registers.pc * 4, b.registers.pc * 4));
+
+        let mut slots = a
+            .memory
+            .get_used()
+            .chain(b.memory.get_used())
+            .collect::<Vec<_>>();
+        slots.sort();
+        slots.dedup();
+
+        let memory = slots
+            .into_iter()
+            .filter_map(|slot| {
+                let addr = slot.wrapping_mul(8);
+                let old = a.memory.get(addr).unwrap();
+                let new = b.memory.get(addr).unwrap();
+
+                (old != new).then_some((addr, old, new))
+            })
+            .collect();
+
+        let len = a.instructions.len().max(b.instructions.len());
+
+        let instructions = (0..len)
+            .filter_map(|idx| {
+                let old = a.instructions.get(idx);
+                let new = b.instructions.get(idx);
+
+                (old != new).then(|| (idx, old.cloned(), new.cloned()))
+            })
+            .collect();
+
+        Self {
+            registers,
+            pc,
+            memory,
+            instructions,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.registers.is_empty()
+            && self.pc.is_none()
+            && self.memory.is_empty()
+            && self.instructions.is_empty()
+    }
+}
+
+impl Display for SimulatorDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (idx, old, new) in &self.registers {
+            writeln!(f, "X{idx}: {old} -> {new}")?;
+        }
+
+        if let Some((old, new)) = self.pc {
+            writeln!(f, "PC: {old} -> {new}")?;
+        }
+
+        for (addr, old, new) in &self.memory {
+            match (old, new) {
+                (0, new) => writeln!(f, "M[{addr}]: added {new}")?,
+                (old, 0) => writeln!(f, "M[{addr}]: removed {old}")?,
+                (old, new) => writeln!(f, "M[{addr}]: {old} -> {new}")?,
+            }
+        }
+
+        for (idx, old, new) in &self.instructions {
+            match (old, new) {
+                (Some(old), Some(new)) => writeln!(f, "Instruction {idx}: {old} -> {new}")?,
+                (None, Some(new)) => writeln!(f, "Instruction {idx}: added {new}")?,
+                (Some(old), None) => writeln!(f, "Instruction {idx}: removed {old}")?,
+                (None, None) => {}
+            }
+        }
+
+        Ok(())
+    }
+}
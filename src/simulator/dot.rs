@@ -0,0 +1,152 @@
+use std::{collections::BTreeSet, fmt::Write};
+
+use super::{register_name, Instruction, Simulator};
+
+/// Escapes `text` for use inside a quoted DOT string.
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+impl Simulator {
+    /// The program's control-flow graph in Graphviz DOT, with a node for
+    /// each basic block and an `END` node for running off the end.
+    ///
+    /// Blocks start at the beginning, at every branch target, and after
+    /// every branch or `halt`. Edges out of a conditional branch are
+    /// labelled with the condition they're taken on. Branches which land
+    /// outside the program have no edge.
+    pub fn to_dot(&self) -> String {
+        use Instruction::*;
+
+        let len = self.instructions.len();
+
+        let target = |idx: usize, off: i128| {
+            usize::try_from(idx as i128 + off)
+                .ok()
+                .filter(|&to| to <= len)
+        };
+
+        let mut leaders = BTreeSet::from([0]);
+
+        for (idx, instr) in self.instructions.iter().enumerate() {
+            match *instr {
+                Branch(off) | BranchZero(_, off) | BranchNotZero(_, off) => {
+                    leaders.extend(target(idx, off));
+                    leaders.insert(idx + 1);
+                }
+                Halt => {
+                    leaders.insert(idx + 1);
+                }
+                _ => {}
+            }
+        }
+
+        // The end isn't a block, it gets its own node
+        leaders.retain(|&idx| idx < len);
+
+        let leaders = leaders.into_iter().collect::<Vec<_>>();
+
+        let node = |idx: usize| {
+            if idx == len {
+                "end".to_string()
+            } else {
+                format!("b{idx}")
+            }
+        };
+
+        let mut out = String::new();
+
+        writeln!(out, "digraph cfg {{").unwrap();
+        writeln!(out, "    node [shape=box, fontname=monospace];").unwrap();
+
+        let mut reaches_end = len == 0;
+
+        for (i, &start) in leaders.iter().enumerate() {
+            let end = leaders.get(i + 1).copied().unwrap_or(len);
+
+            let label = (start..end)
+                .map(|idx| format!("{idx}: {}\\l", escape(&self.instructions[idx].to_string())))
+                .collect::<String>();
+
+            writeln!(out, "    {} [label=\"{label}\"];", node(start)).unwrap();
+
+            let last = end - 1;
+
+            let mut edges = Vec::new();
+
+            match self.instructions[last] {
+                Branch(off) => edges.extend(target(last, off).map(|to| (to, String::new()))),
+                BranchZero(reg, off) | BranchNotZero(reg, off) => {
+                    let reg = register_name(reg, self.word_bits);
+                    let (taken, not_taken) = if let BranchZero(..) = self.instructions[last] {
+                        ("==", "!=")
+                    } else {
+                        ("!=", "==")
+                    };
+
+                    edges.extend(target(last, off).map(|to| (to, format!("{reg} {taken} 0"))));
+                    edges.push((end, format!("{reg} {not_taken} 0")));
+                }
+                Halt => {}
+                _ => edges.push((end, String::new())),
+            }
+
+            for (to, condition) in edges {
+                reaches_end |= to == len;
+
+                if condition.is_empty() {
+                    writeln!(out, "    {} -> {};", node(start), node(to)).unwrap();
+                } else {
+                    writeln!(
+                        out,
+                        "    {} -> {} [label=\"{condition}\"];",
+                        node(start),
+                        node(to)
+                    )
+                    .unwrap();
+                }
+            }
+        }
+
+        if reaches_end {
+            writeln!(out, "    end [label=\"END\", shape=oval];").unwrap();
+        }
+
+        writeln!(out, "}}").unwrap();
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loops_make_a_cycle() {
+        let (sim, _) = Simulator::from_listing(
+            "ADDI X0, XZR, #3\nSUBI X0, X0, #1\nCBNZ X0, #-1\nADD X1, X0, X0",
+        )
+        .unwrap();
+
+        let dot = sim.to_dot();
+        let lines = dot.lines().map(str::trim).collect::<Vec<_>>();
+
+        let nodes = lines
+            .iter()
+            .filter(|x| !x.contains("->") && x.contains("[label="));
+        let edges = lines.iter().filter(|x| x.contains("->"));
+
+        assert_eq!(nodes.count(), 4);
+        assert_eq!(edges.count(), 4);
+
+        for edge in [
+            "b0 -> b1;",
+            "b1 -> b1 [label=\"X0 != 0\"];",
+            "b1 -> b3 [label=\"X0 == 0\"];",
+            "b3 -> end;",
+        ] {
+            assert!(lines.contains(&edge), "{edge} in {dot}");
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use std::collections::BTreeMap;
+
+use color_eyre::eyre::Result;
+use serde::{Deserialize, Serialize};
+
+use super::Simulator;
+
+/// A partial description of the final state of a program. Only the
+/// registers and memory slots mentioned are checked.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Expectation {
+    /// register index -> value
+    #[serde(default)]
+    pub registers: BTreeMap<u8, u64>,
+    /// byte address, like the register view shows it
+    #[serde(default)]
+    pub pc: Option<u64>,
+    /// byte address -> value
+    #[serde(default)]
+    pub memory: BTreeMap<u64, u64>,
+}
+
+impl Expectation {
+    /// Returns one line per value that differs from `sim`, formatted like
+    /// `X3: expected 5, got 7`.
+    pub fn mismatches(&self, sim: &Simulator) -> Result<Vec<String>> {
+        let mut result = Vec::new();
+
+        for (&reg, &expected) in &self.registers {
+            let got = sim.registers.get(reg)?;
+
+            if got != expected {
+                result.push(format!("X{reg}: expected {expected}, got {got}"));
+            }
+        }
+
+        if let Some(expected) = self.pc {
+            let got = sim.registers.pc * 4;
+
+            if got != expected {
+                result.push(format!("PC: expected {expected}, got {got}"));
+            }
+        }
+
+        for (&addr, &expected) in &self.memory {
+            let got = sim.memory.get(addr)?;
+
+            if got != expected {
+                result.push(format!("M[{addr}]: expected {expected}, got {got}"));
+            }
+        }
+
+        Ok(result)
+    }
+}
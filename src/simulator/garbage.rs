@@ -0,0 +1,51 @@
+use super::Simulator;
+
+/// How many memory slots, starting from address 0, get filled with garbage.
+pub const GARBAGE_SLOTS: u64 = 64;
+
+/// Knuth's MMIX linear congruential generator. Only the high half of each
+/// state is used, as the low bits of an LCG repeat quickly.
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self) -> u64 {
+        let mut half = || {
+            self.0 = self
+                .0
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+
+            self.0 >> 32
+        };
+
+        (half() << 32) | half()
+    }
+}
+
+impl Simulator {
+    /// Fills every register, and the first `GARBAGE_SLOTS` memory slots,
+    /// which are still zero with values that depend only on `seed`. This
+    /// shows up programs which assume everything starts out as zero.
+    pub fn fill_garbage(&mut self, seed: u64) {
+        let mut lcg = Lcg(seed);
+
+        // Values are drawn whether or not they're used, so the garbage in
+        // one place doesn't depend on what's in another.
+        for reg in 0..31 {
+            let val = lcg.next();
+
+            if self.registers.get(reg).unwrap() == 0 {
+                self.registers.set(reg, val).unwrap();
+            }
+        }
+
+        for addr in (0..GARBAGE_SLOTS).map(|x| x * 8) {
+            let val = lcg.next();
+
+            if self.memory.get(addr).unwrap() == 0 {
+                // Only fails past `max_addr`, which can stay zero
+                let _ = self.memory.set(addr, val);
+            }
+        }
+    }
+}
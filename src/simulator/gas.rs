@@ -0,0 +1,132 @@
+use std::{collections::BTreeSet, fmt::Write};
+
+use super::{instruction::Offset, Instruction, Simulator};
+
+/// `xN`/`wN`, or the zero register for 31.
+fn reg(idx: u8, word_bits: u8) -> String {
+    let prefix = if word_bits == 32 { 'w' } else { 'x' };
+
+    if idx == 31 {
+        format!("{prefix}zr")
+    } else {
+        format!("{prefix}{idx}")
+    }
+}
+
+impl Simulator {
+    /// The program in the syntax GNU `as` accepts for aarch64, with branch
+    /// targets turned into `.L<index>` labels.
+    ///
+    /// A couple of things don't carry over, and are left as comments:
+    /// - Register 31 as the base of a load or store is the stack pointer on
+    ///   real hardware, not zero.
+    /// - There's no halt instruction, so `halt` branches past the end.
+    pub fn to_gas_asm(&self) -> String {
+        use Instruction::*;
+
+        // Instruction's `None` would overshadow Option's.
+        use Option::None;
+
+        let len = self.instructions.len() as i128;
+
+        let target = |idx: usize, off: i128| {
+            let to = idx as i128 + off;
+
+            if (0..=len).contains(&to) {
+                Some(to as usize)
+            } else {
+                None
+            }
+        };
+
+        let labels = self
+            .instructions
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, instr)| match *instr {
+                Branch(off) | BranchZero(_, off) | BranchNotZero(_, off) => target(idx, off),
+                Halt => Some(self.instructions.len()),
+                _ => None,
+            })
+            .collect::<BTreeSet<_>>();
+
+        let label = |idx: usize, off: i128| match target(idx, off) {
+            Some(to) => format!(".L{to}"),
+            // Nowhere to put a label, so branch relative to here instead
+            None => format!(". + {}", off * 4),
+        };
+
+        let w = self.word_bits;
+        let x = 64;
+
+        let mut out = String::from("\t.text\n\t.global _start\n_start:\n");
+
+        for (idx, instr) in self.instructions.iter().enumerate() {
+            if labels.contains(&idx) {
+                writeln!(out, ".L{idx}:").unwrap();
+            }
+
+            let line = match *instr {
+                Add(r0, r1, r2) => format!("add {}, {}, {}", reg(r0, w), reg(r1, w), reg(r2, w)),
+                Sub(r0, r1, r2) => format!("sub {}, {}, {}", reg(r0, w), reg(r1, w), reg(r2, w)),
+                AddShifted(r0, r1, r2, sh) => format!(
+                    "add {}, {}, {}, lsl #{sh}",
+                    reg(r0, w),
+                    reg(r1, w),
+                    reg(r2, w)
+                ),
+                SubShifted(r0, r1, r2, sh) => format!(
+                    "sub {}, {}, {}, lsl #{sh}",
+                    reg(r0, w),
+                    reg(r1, w),
+                    reg(r2, w)
+                ),
+
+                // Register 31 means SP for immediate arithmetic, so the
+                // usual `addi X0, XZR, #5` has to become a move.
+                AddI(31, _, _) | SubI(31, _, _) => "nop".to_string(),
+                AddI(r0, 31, lit) => format!("mov {}, #{lit}", reg(r0, w)),
+                SubI(r0, 31, lit) => format!("mov {}, #{}", reg(r0, w), -lit),
+                AddI(r0, r1, lit) => format!("add {}, {}, #{lit}", reg(r0, w), reg(r1, w)),
+                SubI(r0, r1, lit) => format!("sub {}, {}, #{lit}", reg(r0, w), reg(r1, w)),
+
+                Mov(r0, r1) => format!("mov {}, {}", reg(r0, w), reg(r1, w)),
+                MovZ(r0, lit) => format!("mov {}, #{lit}", reg(r0, w)),
+
+                Load(_, Offset(31, _)) | Store(_, Offset(31, _)) => {
+                    format!("// {instr} (XZR can't be used as an address)")
+                }
+                Load(r0, Offset(r1, lit)) => {
+                    format!("ldur {}, [{}, #{lit}]", reg(r0, w), reg(r1, x))
+                }
+                // The upper half of a register is always zero when working
+                // with `W` registers, so storing all of it matches the
+                // simulator's 64 bit slots.
+                Store(r0, Offset(r1, lit)) => {
+                    format!("stur {}, [{}, #{lit}]", reg(r0, x), reg(r1, x))
+                }
+
+                Branch(off) => format!("b {}", label(idx, off)),
+                BranchZero(r0, off) => format!("cbz {}, {}", reg(r0, w), label(idx, off)),
+                BranchNotZero(r0, off) => format!("cbnz {}, {}", reg(r0, w), label(idx, off)),
+
+                Halt => format!("b .L{}", self.instructions.len()),
+                Nop => "nop".to_string(),
+                Instruction::None => String::new(),
+                Comment(ref text) => format!("//{text}"),
+            };
+
+            if line.is_empty() {
+                writeln!(out).unwrap();
+            } else {
+                writeln!(out, "\t{line}").unwrap();
+            }
+        }
+
+        if labels.contains(&self.instructions.len()) {
+            writeln!(out, ".L{}:", self.instructions.len()).unwrap();
+        }
+
+        out
+    }
+}
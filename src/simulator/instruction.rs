@@ -2,13 +2,24 @@ use color_eyre::{
     eyre::{bail, OptionExt},
     Result,
 };
-use pest::{iterators::Pair, Parser};
+use pest::{
+    error::{Error as PestError, InputLocation},
+    iterators::Pair,
+    Parser,
+};
 use ratatui::{style::Stylize, text::Span};
 use serde::{Deserialize, Serialize};
 
-use std::fmt::{Debug, Display};
+use std::{
+    error::Error,
+    fmt::{Debug, Display},
+};
 
-use super::{Memory, Registers};
+use super::{
+    registers::{add_word, format_word, mask_word, register_name, sub_word, SP},
+    Memory, Registers,
+};
+use crate::theme::Theme;
 
 #[derive(pest_derive::Parser)]
 #[grammar = "simulator/grammar.pest"]
@@ -16,15 +27,24 @@ struct InstructionParser;
 
 fn parse_reg(x: Pair<Rule>) -> Result<u8> {
     assert_eq!(x.as_rule(), Rule::register);
-    if x.as_span().as_str() == "XZR" {
+    if matches!(x.as_span().as_str(), "XZR" | "WZR") {
         return Ok(31);
     }
 
+    if x.as_span().as_str() == "SP" {
+        return Ok(SP);
+    }
+
+    let token = x.as_str();
+
     let num = x.into_inner().next().unwrap();
     assert_eq!(num.as_rule(), Rule::pos_number);
 
-    let num = num.as_span().as_str().parse::<u8>()?;
-    Ok(num)
+    // Caught here, rather than by `Registers` once it runs
+    match num.as_span().as_str().parse::<u8>() {
+        Ok(num) if num <= 31 => Ok(num),
+        _ => bail!("Register {token} is out of range!"),
+    }
 }
 
 fn parse_literal(x: Pair<Rule>) -> Result<i128> {
@@ -38,6 +58,17 @@ fn parse_literal(x: Pair<Rule>) -> Result<i128> {
     Ok(num)
 }
 
+fn parse_shift(x: Pair<Rule>) -> Result<u8> {
+    assert_eq!(x.as_rule(), Rule::shift);
+
+    let amount = parse_literal(x.into_inner().next().unwrap())?;
+
+    match u8::try_from(amount) {
+        Ok(x) => Ok(x),
+        Err(_) => bail!("Shift #{amount} is out of range!"),
+    }
+}
+
 fn parse_offset(x: Pair<Rule>) -> Result<Offset> {
     assert_eq!(x.as_rule(), Rule::offset);
 
@@ -49,20 +80,159 @@ fn parse_offset(x: Pair<Rule>) -> Result<Offset> {
     Ok(Offset(reg, offset))
 }
 
+/// A syntax error, pointing at where in the (original) line it happened.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: String,
+    /// In characters, not bytes
+    pub column: usize,
+    pub message: String,
+}
+
+impl ParseError {
+    /// `line` is what the user typed, which pest saw uppercased.
+    fn new(line: &str, error: PestError<Rule>) -> Self {
+        // The instructions are written using atomic rules, which pest doesn't
+        // track in its normal error reporting, so we dig into the detailed
+        // attempts instead.
+        if let Some(attempts) = error.parse_attempts() {
+            let mut expected = attempts
+                .expected_tokens()
+                .iter()
+                .map(|x| x.to_string())
+                .map(|x| {
+                    if x.trim().is_empty() {
+                        "whitespace".to_string()
+                    } else {
+                        format!("`{x}`")
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            expected.sort();
+            expected.dedup();
+
+            if !expected.is_empty() {
+                return Self {
+                    line: line.to_string(),
+                    column: original_column(line, attempts.max_position),
+                    message: format!("Expected {}", expected.join(" or ")),
+                };
+            }
+        }
+
+        let (InputLocation::Pos(position) | InputLocation::Span((position, _))) = error.location;
+
+        Self {
+            line: line.to_string(),
+            column: original_column(line, position),
+            message: error.variant.message().into_owned(),
+        }
+    }
+}
+
+/// The column in `line` of a byte offset into its uppercased copy, which
+/// needn't line up byte for byte (`ß` uppercases to `SS`).
+fn original_column(line: &str, position: usize) -> usize {
+    let mut end = 0;
+
+    line.chars()
+        .take_while(|c| {
+            end += c.to_uppercase().map(char::len_utf8).sum::<usize>();
+            end <= position
+        })
+        .count()
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Underline the whole token we failed on
+        let width = self
+            .line
+            .chars()
+            .skip(self.column)
+            .take_while(|&c| !c.is_whitespace() && c != ',')
+            .count()
+            .max(1);
+
+        writeln!(f, "{}", self.message)?;
+        writeln!(f, "{}", self.line)?;
+        write!(f, "{}{}", " ".repeat(self.column), "^".repeat(width))
+    }
+}
+
+impl Error for ParseError {}
+
+/// Starts the body of a comment made by `Instruction::toggle_comment`, so
+/// commented out code shows as `//~ addi X1, X1, #1`.
+const COMMENTED_OUT: &str = "~ ";
+
+/// `[Xn, #offset]`, a base register and a signed offset from it in bytes.
 #[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Offset(pub u8, pub i128);
 
+/// How many cycles each kind of instruction is taken to cost. These are
+/// made up to be easy to reason about, not measured from any real CPU.
+pub struct CycleCosts {
+    pub arithmetic: u32,
+    pub memory: u32,
+    pub branch: u32,
+    /// Extra cost on top of `branch` when the branch is taken
+    pub taken_branch: u32,
+}
+
+pub const CYCLE_COSTS: CycleCosts = CycleCosts {
+    arithmetic: 1,
+    memory: 4,
+    branch: 1,
+    taken_branch: 2,
+};
+
+/// The longest mnemonic and a space, which operands are shown after.
+const MNEMONIC_WIDTH: usize = 5;
+
+/// `val << amount`, shifting everything out for amounts of 64 or more.
+pub fn shift_left(val: u64, amount: u8) -> u64 {
+    val.checked_shl(amount as u32).unwrap_or(0)
+}
+
+/// Moves `pc` by `off` instructions (not bytes), wrapping around.
+pub fn offset_pc(pc: u64, off: i128) -> u64 {
+    let new_pc = pc as i128 + off;
+
+    (new_pc & u64::MAX as i128) as u64
+}
+
 #[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Instruction {
     Add(u8, u8, u8),
     Sub(u8, u8, u8),
+    /// Like `Add`, with the last register shifted left first
+    AddShifted(u8, u8, u8, u8),
+    /// Like `Sub`, with the last register shifted left first
+    SubShifted(u8, u8, u8, u8),
+    /// The immediate is unsigned, from 0 to 4095
     AddI(u8, u8, i128),
+    /// The immediate is unsigned, from 0 to 4095
     SubI(u8, u8, i128),
+    /// Copies the second register into the first
+    Mov(u8, u8),
+    /// Sets the register to a constant, from 0 to 65535, like ARM's `MOVZ`
+    /// with no shift
+    MovZ(u8, i128),
     Load(u8, Offset),
     Store(u8, Offset),
+    /// Signed, in instructions
     Branch(i128),
+    /// Signed, in instructions
     BranchZero(u8, i128),
+    /// Signed, in instructions
     BranchNotZero(u8, i128),
+    /// Stops the program, which otherwise only stops by running off the end
+    Halt,
+    /// Does nothing, but takes a cycle like any other instruction
+    Nop,
+    /// An empty line, skipped over like a comment
     None,
     Comment(String),
 }
@@ -82,12 +252,24 @@ impl Display for Instruction {
             Instruction::Sub(r0, r1, r2) => {
                 write!(f, "sub  X{r0}, X{r1}, X{r2}")
             }
+            Instruction::AddShifted(r0, r1, r2, sh) => {
+                write!(f, "add  X{r0}, X{r1}, X{r2}, lsl #{sh}")
+            }
+            Instruction::SubShifted(r0, r1, r2, sh) => {
+                write!(f, "sub  X{r0}, X{r1}, X{r2}, lsl #{sh}")
+            }
             Instruction::AddI(r0, r1, lit) => {
                 write!(f, "addi X{r0}, X{r1}, #{lit}")
             }
             Instruction::SubI(r0, r1, lit) => {
                 write!(f, "subi X{r0}, X{r1}, #{lit}")
             }
+            Instruction::Mov(r0, r1) => {
+                write!(f, "mov  X{r0}, X{r1}")
+            }
+            Instruction::MovZ(r0, lit) => {
+                write!(f, "mov  X{r0}, #{lit}")
+            }
             Instruction::Load(r0, Offset(r1, lit)) => {
                 write!(f, "ldur X{r0}, [X{r1}, #{lit}]")
             }
@@ -103,6 +285,12 @@ impl Display for Instruction {
             Instruction::BranchNotZero(r0, lit) => {
                 write!(f, "cbnz X{r0}, #{lit}")
             }
+            Instruction::Halt => {
+                write!(f, "halt")
+            }
+            Instruction::Nop => {
+                write!(f, "nop")
+            }
             Instruction::None => {
                 write!(f, "")
             }
@@ -130,6 +318,25 @@ where
     Ok(result(f0(v0)?, f1(v1)?, f2(v2)?))
 }
 
+fn make4<'a, T0, T1, T2, T3, F, U>(
+    mut iter: impl Iterator<Item = Pair<'a, Rule>>,
+    f0: impl FnOnce(Pair<'a, Rule>) -> Result<T0>,
+    f1: impl FnOnce(Pair<'a, Rule>) -> Result<T1>,
+    f2: impl FnOnce(Pair<'a, Rule>) -> Result<T2>,
+    f3: impl FnOnce(Pair<'a, Rule>) -> Result<T3>,
+    result: F,
+) -> Result<U>
+where
+    F: FnOnce(T0, T1, T2, T3) -> U,
+{
+    let v0 = iter.next().ok_or_eyre("Insufficent arguments!")?;
+    let v1 = iter.next().ok_or_eyre("Insufficent arguments!")?;
+    let v2 = iter.next().ok_or_eyre("Insufficent arguments!")?;
+    let v3 = iter.next().ok_or_eyre("Insufficent arguments!")?;
+
+    Ok(result(f0(v0)?, f1(v1)?, f2(v2)?, f3(v3)?))
+}
+
 fn make2<'a, T0, T1, F, U>(
     mut iter: impl Iterator<Item = Pair<'a, Rule>>,
     f0: impl FnOnce(Pair<'a, Rule>) -> Result<T0>,
@@ -164,13 +371,19 @@ impl std::str::FromStr for Instruction {
     fn from_str(mut s: &str) -> Result<Self, Self::Err> {
         s = s.trim();
 
+        let original = s;
         let s = s.to_uppercase();
 
         if s.len() == 0 {
             return Ok(Instruction::None);
         }
 
-        let result = InstructionParser::parse(Rule::line, &s)?.next().unwrap();
+        pest::set_error_detail(true);
+
+        let result = InstructionParser::parse(Rule::line, &s)
+            .map_err(|e| ParseError::new(original, e))?
+            .next()
+            .unwrap();
 
         let full_line = result
             .into_inner()
@@ -178,32 +391,60 @@ impl std::str::FromStr for Instruction {
             .unwrap() // full_line
             .into_inner()
             .next()
-            .unwrap() // comment | instruction
+            .unwrap() // comment | instruction, ignoring any comment after it
             .into_inner()
             .next()
             .unwrap() // specific instruction or comment_rest
         ;
 
         if full_line.as_rule() == Rule::comment_rest {
-            return Ok(Instruction::Comment(full_line.as_span().as_str().into()));
+            // Only whitespace and `//` come before the comment body, so its
+            // offset in the uppercased copy is the same as in the original.
+            let start = full_line.as_span().start();
+
+            return Ok(Instruction::Comment(original[start..].into()));
         }
 
         let rule = full_line.as_rule();
         let iter = full_line.into_inner();
 
+        let has_shift = iter.clone().any(|x| x.as_rule() == Rule::shift);
+        let has_literal = iter.clone().any(|x| x.as_rule() == Rule::literal);
+
         let result = match rule {
+            Rule::add if has_shift => make4(
+                iter,
+                parse_reg,
+                parse_reg,
+                parse_reg,
+                parse_shift,
+                Instruction::AddShifted,
+            ),
+            Rule::sub if has_shift => make4(
+                iter,
+                parse_reg,
+                parse_reg,
+                parse_reg,
+                parse_shift,
+                Instruction::SubShifted,
+            ),
             Rule::add => make3(iter, parse_reg, parse_reg, parse_reg, Instruction::Add),
             Rule::sub => make3(iter, parse_reg, parse_reg, parse_reg, Instruction::Sub),
 
             Rule::addi => make3(iter, parse_reg, parse_reg, parse_literal, Instruction::AddI),
             Rule::subi => make3(iter, parse_reg, parse_reg, parse_literal, Instruction::SubI),
 
+            Rule::mov if has_literal => make2(iter, parse_reg, parse_literal, Instruction::MovZ),
+            Rule::mov => make2(iter, parse_reg, parse_reg, Instruction::Mov),
+
             Rule::ldur => make2(iter, parse_reg, parse_offset, Instruction::Load),
             Rule::stur => make2(iter, parse_reg, parse_offset, Instruction::Store),
 
             Rule::branch => make1(iter, parse_literal, Instruction::Branch),
             Rule::cbz => make2(iter, parse_reg, parse_literal, Instruction::BranchZero),
             Rule::cbnz => make2(iter, parse_reg, parse_literal, Instruction::BranchNotZero),
+            Rule::halt => Ok(Instruction::Halt),
+            Rule::nop => Ok(Instruction::Nop),
 
             _ => panic!("{:?}", rule),
         };
@@ -213,15 +454,57 @@ impl std::str::FromStr for Instruction {
 }
 
 impl Instruction {
+    /// Checks immediates, shifts and offsets fit in their encodings. Parsing
+    /// already does this, so it's only needed for instructions made by hand.
     pub fn validate(self) -> Result<Self> {
         use Instruction::*;
 
+        // Parsing already rules these out, but a saved file might not
+        let registers = match self {
+            Add(a, b, c) | Sub(a, b, c) | AddShifted(a, b, c, _) | SubShifted(a, b, c, _) => {
+                vec![a, b, c]
+            }
+            AddI(a, b, _) | SubI(a, b, _) | Load(a, Offset(b, _)) | Store(a, Offset(b, _)) => {
+                vec![a, b]
+            }
+            Mov(a, b) => vec![a, b],
+            MovZ(a, _) | BranchZero(a, _) | BranchNotZero(a, _) => vec![a],
+            _ => vec![],
+        };
+
+        if let Some(reg) = registers.into_iter().find(|&x| x > 31) {
+            bail!("Register X{reg} does not exist!");
+        }
+
         match self {
+            AddShifted(.., sh) | SubShifted(.., sh) => {
+                if sh >= 64 {
+                    bail!("Shift #{sh} is too large!");
+                }
+            }
             AddI(.., lit) | SubI(.., lit) => {
-                if lit < 0 || lit >= 4096 {
+                if lit < 0 {
+                    let other = if let AddI(..) = self { "SUBI" } else { "ADDI" };
+
+                    bail!(
+                        "Constant: #{lit} can't be negative, use {other} with #{} instead!",
+                        -lit
+                    );
+                }
+
+                if lit >= 4096 {
                     bail!("Constant: #{lit} is too large!");
                 }
             }
+            MovZ(_, lit) => {
+                if lit < 0 {
+                    bail!("Constant: #{lit} can't be negative, MOV only sets 0 to 65535!");
+                }
+
+                if lit >= 65536 {
+                    bail!("Constant: #{lit} is too large, MOV only sets 0 to 65535!");
+                }
+            }
             Load(_, Offset(_, off)) | Store(_, Offset(_, off)) => {
                 if off < -256 || off > 255 {
                     bail!("Offset #{off} is too large!");
@@ -243,319 +526,526 @@ impl Instruction {
         Ok(self)
     }
 
-    pub fn get_line(&self) -> Vec<Span> {
+    /// Turns the instruction into a comment holding it, or a comment made
+    /// this way back into the instruction. Comments and empty lines which
+    /// weren't made this way stay as they are.
+    pub fn toggle_comment(&self) -> Instruction {
+        match self {
+            Instruction::Comment(body) => body
+                .strip_prefix(COMMENTED_OUT)
+                .and_then(|x| x.parse().ok())
+                .unwrap_or_else(|| self.clone()),
+            Instruction::None => Instruction::None,
+            instr => Instruction::Comment(format!("{COMMENTED_OUT}{instr}")),
+        }
+    }
+
+    /// The 32 bit machine code for the instruction, in the R, I, D, B and CB
+    /// formats from the textbook. `HALT` isn't part of LEGv8, so like
+    /// comments and empty lines it has none.
+    pub fn encode(&self) -> Option<u32> {
+        use Instruction::*;
+
+        let reg = |x: u8| x as u32 & 0x1f;
+
+        // opcode | Rm | shamt | Rn | Rd
+        let r_format = |opcode: u32, rd: u8, rn: u8, rm: u8, shamt: u8| {
+            opcode << 21 | reg(rm) << 16 | (shamt as u32 & 0x3f) << 10 | reg(rn) << 5 | reg(rd)
+        };
+        // opcode | ALU_immediate | Rn | Rd
+        let i_format = |opcode: u32, rd: u8, rn: u8, imm: i128| {
+            opcode << 22 | (imm as u32 & 0xfff) << 10 | reg(rn) << 5 | reg(rd)
+        };
+        // opcode | DT_address | op | Rn | Rt
+        let d_format = |opcode: u32, rt: u8, Offset(rn, off): Offset| {
+            opcode << 21 | (off as u32 & 0x1ff) << 12 | reg(rn) << 5 | reg(rt)
+        };
+        // opcode | COND_BR_address | Rt
+        let cb_format =
+            |opcode: u32, rt: u8, off: i128| opcode << 24 | (off as u32 & 0x7ffff) << 5 | reg(rt);
+
+        let encoding = match *self {
+            Add(rd, rn, rm) => r_format(0b10001011000, rd, rn, rm, 0),
+            Sub(rd, rn, rm) => r_format(0b11001011000, rd, rn, rm, 0),
+            AddShifted(rd, rn, rm, sh) => r_format(0b10001011000, rd, rn, rm, sh),
+            SubShifted(rd, rn, rm, sh) => r_format(0b11001011000, rd, rn, rm, sh),
+            AddI(rd, rn, imm) => i_format(0b1001000100, rd, rn, imm),
+            SubI(rd, rn, imm) => i_format(0b1101000100, rd, rn, imm),
+            // The real ARM alias, `orr Xd, XZR, Xm`
+            Mov(rd, rm) => r_format(0b10101010000, rd, 31, rm, 0),
+            // opcode | shift | MOV_immediate | Rd, with no shift
+            MovZ(rd, imm) => 0b11010010100 << 21 | (imm as u32 & 0xffff) << 5 | reg(rd),
+            Load(rt, off) => d_format(0b11111000010, rt, off),
+            Store(rt, off) => d_format(0b11111000000, rt, off),
+            // opcode | BR_address
+            Branch(off) => 0b000101 << 26 | (off as u32 & 0x3ffffff),
+            BranchZero(rt, off) => cb_format(0b10110100, rt, off),
+            BranchNotZero(rt, off) => cb_format(0b10110101, rt, off),
+            // The real ARM encoding
+            Nop => 0xd503201f,
+            Halt | None | Comment(_) => return Option::None,
+        };
+
+        Some(encoding)
+    }
+
+    /// The reverse of `encode`. A shift of zero decodes to a plain `add` or
+    /// `sub`, since they're encoded the same way.
+    pub fn decode(word: u32) -> Result<Instruction> {
+        use Instruction::*;
+
+        let reg = |shift: u32| (word >> shift & 0x1f) as u8;
+        // The low `bits` bits of the word, shifted down by `shift` and sign
+        // extended
+        let signed = |shift: u32, bits: u32| {
+            let field = (word >> shift) as i32 & ((1 << bits) - 1);
+
+            (field << (32 - bits) >> (32 - bits)) as i128
+        };
+
+        let (rd, rn, rm) = (reg(0), reg(5), reg(16));
+        let shamt = (word >> 10 & 0x3f) as u8;
+        let imm = (word >> 10 & 0xfff) as i128;
+        let offset = Offset(rn, signed(12, 9));
+
+        let instr = match (word >> 26, word >> 24, word >> 22, word >> 21) {
+            _ if word == 0xd503201f => Nop,
+            (0b000101, ..) => Branch(signed(0, 26)),
+            (_, 0b10110100, ..) => BranchZero(rd, signed(5, 19)),
+            (_, 0b10110101, ..) => BranchNotZero(rd, signed(5, 19)),
+            (_, _, 0b1001000100, _) => AddI(rd, rn, imm),
+            (_, _, 0b1101000100, _) => SubI(rd, rn, imm),
+            (.., 0b10001011000) if shamt == 0 => Add(rd, rn, rm),
+            (.., 0b11001011000) if shamt == 0 => Sub(rd, rn, rm),
+            (.., 0b10101010000) if rn == 31 && shamt == 0 => Mov(rd, rm),
+            (.., 0b11010010100) => MovZ(rd, (word >> 5 & 0xffff) as i128),
+            (.., 0b10001011000) => AddShifted(rd, rn, rm, shamt),
+            (.., 0b11001011000) => SubShifted(rd, rn, rm, shamt),
+            (.., 0b11111000010) => Load(rd, offset),
+            (.., 0b11111000000) => Store(rd, offset),
+            _ => bail!("{word:08X} isn't the machine code of any instruction here!"),
+        };
+
+        Ok(instr)
+    }
+
+    /// Cycles taken by this instruction, not counting the penalty for a
+    /// taken branch.
+    pub fn cost(&self) -> u32 {
         use Instruction::*;
 
-        let lines = match self {
+        match self {
+            Add(..) | Sub(..) | AddShifted(..) | SubShifted(..) | AddI(..) | SubI(..) | Mov(..)
+            | MovZ(..) | Nop => CYCLE_COSTS.arithmetic,
+            Load(..) | Store(..) => CYCLE_COSTS.memory,
+            Branch(_) | BranchZero(..) | BranchNotZero(..) => CYCLE_COSTS.branch,
+            Halt | None | Comment(_) => 0,
+        }
+    }
+
+    /// The instruction as it's shown in the instructions view.
+    pub fn get_line(&self, word_bits: u8, theme: &Theme) -> Vec<Span> {
+        use Instruction::*;
+
+        let mut lines = match self {
             Add(x0, x1, x2) => vec![
-                "add  ".blue(),
-                format!("X{x0}").red(),
+                "add".fg(theme.keyword),
+                register_name(*x0, word_bits).fg(theme.register),
                 ", ".into(),
-                format!("X{x1}").red(),
+                register_name(*x1, word_bits).fg(theme.register),
                 ", ".into(),
-                format!("X{x2}").red(),
+                register_name(*x2, word_bits).fg(theme.register),
             ],
             Sub(x0, x1, x2) => vec![
-                "sub  ".blue(),
-                format!("X{x0}").red(),
+                "sub".fg(theme.keyword),
+                register_name(*x0, word_bits).fg(theme.register),
+                ", ".into(),
+                register_name(*x1, word_bits).fg(theme.register),
+                ", ".into(),
+                register_name(*x2, word_bits).fg(theme.register),
+            ],
+            AddShifted(x0, x1, x2, sh) | SubShifted(x0, x1, x2, sh) => vec![
+                if let AddShifted(..) = self {
+                    "add".fg(theme.keyword)
+                } else {
+                    "sub".fg(theme.keyword)
+                },
+                register_name(*x0, word_bits).fg(theme.register),
+                ", ".into(),
+                register_name(*x1, word_bits).fg(theme.register),
                 ", ".into(),
-                format!("X{x1}").red(),
+                register_name(*x2, word_bits).fg(theme.register),
                 ", ".into(),
-                format!("X{x2}").red(),
+                "lsl ".fg(theme.keyword),
+                format!("#{sh}").fg(theme.literal),
             ],
 
             AddI(x0, x1, lit) => vec![
-                "addi ".blue(),
-                format!("X{x0}").red(),
+                "addi".fg(theme.keyword),
+                register_name(*x0, word_bits).fg(theme.register),
                 ", ".into(),
-                format!("X{x1}").red(),
+                register_name(*x1, word_bits).fg(theme.register),
                 ", ".into(),
-                format!("#{lit}").yellow(),
+                format!("#{lit}").fg(theme.literal),
             ],
             SubI(x0, x1, lit) => vec![
-                "subi ".blue(),
-                format!("X{x0}").red(),
+                "subi".fg(theme.keyword),
+                register_name(*x0, word_bits).fg(theme.register),
                 ", ".into(),
-                format!("X{x1}").red(),
+                register_name(*x1, word_bits).fg(theme.register),
                 ", ".into(),
-                format!("#{lit}").yellow(),
+                format!("#{lit}").fg(theme.literal),
+            ],
+
+            Mov(x0, x1) => vec![
+                "mov".fg(theme.keyword),
+                register_name(*x0, word_bits).fg(theme.register),
+                ", ".into(),
+                register_name(*x1, word_bits).fg(theme.register),
+            ],
+            MovZ(x0, lit) => vec![
+                "mov".fg(theme.keyword),
+                register_name(*x0, word_bits).fg(theme.register),
+                ", ".into(),
+                format!("#{lit}").fg(theme.literal),
             ],
 
             Load(x0, Offset(x1, off)) => vec![
-                "ldur ".blue(),
-                format!("X{x0}").red(),
+                "ldur".fg(theme.keyword),
+                register_name(*x0, word_bits).fg(theme.register),
                 ", [".into(),
-                format!("X{x1}").red(),
+                format!("X{x1}").fg(theme.register),
                 ", ".into(),
-                format!("#{off}").yellow(),
+                format!("#{off}").fg(theme.literal),
                 "]".into(),
             ],
 
             Store(x0, Offset(x1, off)) => vec![
-                "stur ".blue(),
-                format!("X{x0}").red(),
+                "stur".fg(theme.keyword),
+                register_name(*x0, word_bits).fg(theme.register),
                 ", [".into(),
-                format!("X{x1}").red(),
+                format!("X{x1}").fg(theme.register),
                 ", ".into(),
-                format!("#{off}").yellow(),
+                format!("#{off}").fg(theme.literal),
                 "]".into(),
             ],
 
-            Branch(off) => vec!["b    ".blue(), format!("#{off}").yellow()],
+            Branch(off) => vec!["b".fg(theme.keyword), format!("#{off}").fg(theme.literal)],
             BranchZero(x0, off) => vec![
-                "cbz  ".blue(),
-                format!("X{x0}").red(),
+                "cbz".fg(theme.keyword),
+                register_name(*x0, word_bits).fg(theme.register),
                 ", ".into(),
-                format!("#{off}").yellow(),
+                format!("#{off}").fg(theme.literal),
             ],
             BranchNotZero(x0, off) => vec![
-                "cbnz ".blue(),
-                format!("X{x0}").red(),
+                "cbnz".fg(theme.keyword),
+                register_name(*x0, word_bits).fg(theme.register),
                 ", ".into(),
-                format!("#{off}").yellow(),
+                format!("#{off}").fg(theme.literal),
             ],
+            Halt => vec!["halt".fg(theme.keyword)],
+            Nop => vec!["nop".fg(theme.keyword)],
             None => vec![],
             Comment(s) => vec![
-                "//".light_green().italic(),
-                s.clone().light_green().italic(),
+                "//".fg(theme.comment).italic(),
+                s.clone().fg(theme.comment).italic(),
             ],
         };
 
+        // Operands start in the same column whatever the mnemonic
+        if !matches!(self, Comment(_)) && lines.len() > 1 {
+            lines[0].content = format!("{:<MNEMONIC_WIDTH$}", lines[0].content).into();
+        }
+
         lines
     }
 
-    pub fn explain_unsub(&self) -> Vec<Span> {
+    /// What the instruction does in terms of registers, like `X0 = X1 + X2`.
+    pub fn explain_unsub(&self, word_bits: u8, theme: &Theme) -> Vec<Span> {
         use Instruction::*;
 
         match self {
             Add(x0, x1, x2) => vec![
-                format!("X{x0}").red().bold(),
+                register_name(*x0, word_bits).fg(theme.register).bold(),
                 " = ".into(),
-                format!("X{x1}").red().bold(),
+                register_name(*x1, word_bits).fg(theme.register).bold(),
                 " + ".into(),
-                format!("X{x2}").red().bold(),
+                register_name(*x2, word_bits).fg(theme.register).bold(),
             ],
             Sub(x0, x1, x2) => vec![
-                format!("X{x0}").red().bold(),
+                register_name(*x0, word_bits).fg(theme.register).bold(),
                 " = ".into(),
-                format!("X{x1}").red().bold(),
+                register_name(*x1, word_bits).fg(theme.register).bold(),
                 " - ".into(),
-                format!("X{x2}").red().bold(),
+                register_name(*x2, word_bits).fg(theme.register).bold(),
+            ],
+            AddShifted(x0, x1, x2, sh) | SubShifted(x0, x1, x2, sh) => vec![
+                register_name(*x0, word_bits).fg(theme.register).bold(),
+                " = ".into(),
+                register_name(*x1, word_bits).fg(theme.register).bold(),
+                if let AddShifted(..) = self {
+                    " + (".into()
+                } else {
+                    " - (".into()
+                },
+                register_name(*x2, word_bits).fg(theme.register).bold(),
+                " << ".into(),
+                format!("{sh}").fg(theme.literal),
+                ")".into(),
             ],
 
             AddI(x0, x1, lit) => vec![
-                format!("X{x0}").red().bold(),
+                register_name(*x0, word_bits).fg(theme.register).bold(),
                 " = ".into(),
-                format!("X{x1}").red().bold(),
+                register_name(*x1, word_bits).fg(theme.register).bold(),
                 " + ".into(),
-                format!("{lit}").yellow(),
+                format!("{lit}").fg(theme.literal),
             ],
             SubI(x0, x1, lit) => vec![
-                format!("X{x0}").red().bold(),
+                register_name(*x0, word_bits).fg(theme.register).bold(),
                 " = ".into(),
-                format!("X{x1}").red().bold(),
+                register_name(*x1, word_bits).fg(theme.register).bold(),
                 " - ".into(),
-                format!("{lit}").yellow(),
+                format!("{lit}").fg(theme.literal),
+            ],
+
+            Mov(x0, x1) => vec![
+                register_name(*x0, word_bits).fg(theme.register).bold(),
+                " = ".into(),
+                register_name(*x1, word_bits).fg(theme.register).bold(),
+            ],
+            MovZ(x0, lit) => vec![
+                register_name(*x0, word_bits).fg(theme.register).bold(),
+                " = ".into(),
+                format!("{lit}").fg(theme.literal),
             ],
 
             Load(x0, Offset(x1, lit)) => vec![
-                format!("X{x0}").red().bold(),
+                register_name(*x0, word_bits).fg(theme.register).bold(),
                 " = ".into(),
-                "M".light_magenta().bold(),
+                "M".fg(theme.memory).bold(),
                 "[".into(),
-                format!("X{x1}").red().bold(),
+                format!("X{x1}").fg(theme.register).bold(),
                 " + ".into(),
-                format!("{lit}").yellow(),
+                format!("{lit}").fg(theme.literal),
                 "]".into(),
             ],
             Store(x0, Offset(x1, lit)) => vec![
-                "M".light_magenta().bold(),
+                "M".fg(theme.memory).bold(),
                 "[".into(),
-                format!("X{x1}").red().bold(),
+                format!("X{x1}").fg(theme.register).bold(),
                 " + ".into(),
-                format!("{lit}").yellow(),
+                format!("{lit}").fg(theme.literal),
                 "]".into(),
                 " = ".into(),
-                format!("X{x0}").red().bold(),
+                register_name(*x0, word_bits).fg(theme.register).bold(),
             ],
 
             Branch(lit) => vec![
-                "PC".green().bold(),
+                "PC".fg(theme.pc).bold(),
                 " = ".into(),
-                "PC".green().bold(),
+                "PC".fg(theme.pc).bold(),
                 " + ".into(),
-                format!("{lit}").yellow(),
+                format!("{lit}").fg(theme.literal),
                 " * 4".into(),
             ],
             BranchZero(x0, lit) => vec![
                 "if ".into(),
-                format!("X{x0}").red().bold(),
+                register_name(*x0, word_bits).fg(theme.register).bold(),
                 " == 0: ".into(),
-                "PC".green().bold(),
+                "PC".fg(theme.pc).bold(),
                 " = ".into(),
-                "PC".green().bold(),
+                "PC".fg(theme.pc).bold(),
                 " + ".into(),
-                format!("{lit}").yellow(),
+                format!("{lit}").fg(theme.literal),
                 " * 4".into(),
             ],
             BranchNotZero(x0, lit) => vec![
                 "if ".into(),
-                format!("X{x0}").red().bold(),
+                register_name(*x0, word_bits).fg(theme.register).bold(),
                 " != 0: ".into(),
-                "PC".green().bold(),
+                "PC".fg(theme.pc).bold(),
                 " = ".into(),
-                "PC".green().bold(),
+                "PC".fg(theme.pc).bold(),
                 " + ".into(),
-                format!("{lit}").yellow(),
+                format!("{lit}").fg(theme.literal),
                 " * 4".into(),
             ],
 
-            None | Comment(_) => vec!["Stop Program".magenta().bold()],
+            Halt => vec!["Stop Program".fg(theme.stop).bold()],
+            Nop | None | Comment(_) => vec!["Nothing to do".fg(theme.muted)],
         }
     }
 
-    pub fn explain_sub(&self, registers: &Registers, memory: &Memory) -> Vec<Span> {
+    /// `explain_unsub` with the current values substituted in, also shown as
+    /// signed numbers if `signed` is set.
+    pub fn explain_sub(
+        &self,
+        registers: &Registers,
+        memory: &Memory,
+        word_bits: u8,
+        signed: bool,
+        theme: &Theme,
+    ) -> Vec<Span> {
         use Instruction::*;
 
+        // Values as the instruction sees them. Addresses always use the full
+        // 64 bits of the base register.
+        let get = |x| mask_word(registers.get(x).unwrap(), word_bits);
+        let show = |x| format_word(x, word_bits, signed);
+        let show_addr = |x| format_word(x, 64, signed);
+
         match *self {
-            Add(x0, x1, x2) => vec![
-                format!("X{x0}").red().bold(),
-                " = ".into(),
-                format!("{}", registers.get(x1).unwrap()).red().bold(),
-                " + ".into(),
-                format!("{}", registers.get(x2).unwrap()).red().bold(),
-                " = ".into(),
-                format!(
-                    "{}",
-                    registers
-                        .get(x1)
-                        .unwrap()
-                        .wrapping_add(registers.get(x2).unwrap())
-                )
-                .yellow(),
-            ],
-            Sub(x0, x1, x2) => vec![
-                format!("X{x0}").red().bold(),
-                " = ".into(),
-                format!("{}", registers.get(x1).unwrap()).red().bold(),
-                " - ".into(),
-                format!("{}", registers.get(x2).unwrap()).red().bold(),
-                " = ".into(),
-                format!(
-                    "{}",
-                    registers
-                        .get(x1)
-                        .unwrap()
-                        .wrapping_sub(registers.get(x2).unwrap())
-                )
-                .yellow(),
-            ],
+            Add(..) | Sub(..) | AddShifted(..) | SubShifted(..) | AddI(..) | SubI(..) => {
+                let (x0, x1, b, operand) = match *self {
+                    Add(x0, x1, x2) | Sub(x0, x1, x2) => {
+                        (x0, x1, get(x2), show(get(x2)).fg(theme.register).bold())
+                    }
+                    AddShifted(x0, x1, x2, sh) | SubShifted(x0, x1, x2, sh) => {
+                        let shifted = mask_word(shift_left(get(x2), sh), word_bits);
 
-            AddI(x0, x1, lit) => vec![
-                format!("X{x0}").red().bold(),
-                " = ".into(),
-                format!("{}", registers.get(x1).unwrap()).red().bold(),
-                " + ".into(),
-                format!("{lit}").yellow(),
+                        (x0, x1, shifted, show(shifted).fg(theme.register).bold())
+                    }
+                    AddI(x0, x1, lit) | SubI(x0, x1, lit) => {
+                        (x0, x1, lit as u64, format!("{lit}").fg(theme.literal))
+                    }
+                    _ => unreachable!(),
+                };
+
+                // The same arithmetic `Simulator::tick` does
+                let (op, (result, wrapped), note) = match self {
+                    Add(..) | AddShifted(..) | AddI(..) => {
+                        (" + ", add_word(get(x1), b, word_bits), " (carry)")
+                    }
+                    _ => (" - ", sub_word(get(x1), b, word_bits), " (borrow)"),
+                };
+
+                let mut line = vec![
+                    register_name(x0, word_bits).fg(theme.register).bold(),
+                    " = ".into(),
+                    show(get(x1)).fg(theme.register).bold(),
+                    op.into(),
+                    operand,
+                    " = ".into(),
+                    show(result).fg(theme.literal),
+                ];
+
+                if wrapped {
+                    line.push(note.fg(theme.warning));
+                }
+
+                line
+            }
+
+            Mov(x0, x1) => vec![
+                register_name(x0, word_bits).fg(theme.register).bold(),
                 " = ".into(),
-                format!("{}", registers.get(x1).unwrap() as i128 + lit).yellow(),
+                show(get(x1)).fg(theme.register).bold(),
             ],
-            SubI(x0, x1, lit) => vec![
-                format!("X{x0}").red().bold(),
-                " = ".into(),
-                format!("{}", registers.get(x1).unwrap()).red().bold(),
-                " - ".into(),
-                format!("{lit}").yellow(),
+            MovZ(x0, lit) => vec![
+                register_name(x0, word_bits).fg(theme.register).bold(),
                 " = ".into(),
-                format!("{}", registers.get(x1).unwrap() as i128 + lit).yellow(),
+                format!("{lit}").fg(theme.literal),
             ],
 
             Load(x0, Offset(x1, lit)) => {
                 let addr = (registers.get(x1).unwrap() as i128 + lit) as u64;
-                let valid = addr % 8 == 0;
+                let valid = addr.is_multiple_of(8);
 
                 vec![
-                    format!("X{x0}").red().bold(),
+                    register_name(x0, word_bits).fg(theme.register).bold(),
                     " = ".into(),
-                    "M".light_magenta().bold(),
+                    "M".fg(theme.memory).bold(),
                     "[".into(),
-                    format!("{}", registers.get(x1).unwrap()).red().bold(),
+                    show_addr(registers.get(x1).unwrap())
+                        .fg(theme.register)
+                        .bold(),
                     " + ".into(),
-                    format!("{lit}").yellow(),
+                    format!("{lit}").fg(theme.literal),
                     " = ".into(),
                     if valid {
-                        format!("{}", addr).yellow()
+                        format!("{}", addr).fg(theme.literal)
                     } else {
-                        format!("{}", addr).red().underlined().bold()
+                        format!("{}", addr).fg(theme.register).underlined().bold()
                     },
                     "]".into(),
                     " = ".into(),
                     memory
                         .get(addr)
-                        .map(|x| format!("{x}").yellow())
-                        .unwrap_or("ERROR".red().underlined().bold().slow_blink()),
+                        .map(|x| show(x).fg(theme.literal))
+                        .unwrap_or("ERROR".fg(theme.register).underlined().bold().slow_blink()),
                 ]
             }
             Store(x0, Offset(x1, lit)) => {
                 let addr = (registers.get(x1).unwrap() as i128 + lit) as u64;
-                let valid = addr % 8 == 0;
+                let valid = addr.is_multiple_of(8);
 
                 vec![
-                    "M".light_magenta().bold(),
+                    "M".fg(theme.memory).bold(),
                     "[".into(),
-                    format!("{}", registers.get(x1).unwrap()).red().bold(),
+                    show_addr(registers.get(x1).unwrap())
+                        .fg(theme.register)
+                        .bold(),
                     " + ".into(),
-                    format!("{lit}").yellow(),
+                    format!("{lit}").fg(theme.literal),
                     " = ".into(),
                     if valid {
-                        format!("{}", addr).yellow()
+                        format!("{}", addr).fg(theme.literal)
                     } else {
-                        format!("{}", addr).red().underlined().bold()
+                        format!("{}", addr).fg(theme.register).underlined().bold()
                     },
                     "]".into(),
                     " = ".into(),
-                    format!("{}", registers.get(x0).unwrap()).red().bold(),
+                    show(get(x0)).fg(theme.register).bold(),
                 ]
             }
 
             Branch(lit) => vec![
-                "PC".green().bold(),
+                "PC".fg(theme.pc).bold(),
                 " = ".into(),
-                format!("{}", registers.pc * 4).green().bold(),
+                format!("{}", registers.pc.wrapping_mul(4))
+                    .fg(theme.pc)
+                    .bold(),
                 " + ".into(),
-                format!("{lit}").yellow(),
+                format!("{lit}").fg(theme.literal),
                 " * 4 = ".into(),
-                format!("{}", (registers.pc as i128 + lit) * 4).yellow(),
+                format!("{}", offset_pc(registers.pc, lit).wrapping_mul(4)).fg(theme.literal),
             ],
             BranchZero(x0, lit) => vec![
                 "if ".into(),
-                format!("{}", registers.get(x0).unwrap()).red().bold(),
+                show(get(x0)).fg(theme.register).bold(),
                 " == 0: ".into(),
-                "PC".green().bold(),
+                "PC".fg(theme.pc).bold(),
                 " = ".into(),
-                format!("{}", registers.pc * 4).green().bold(),
+                format!("{}", registers.pc.wrapping_mul(4))
+                    .fg(theme.pc)
+                    .bold(),
                 " + ".into(),
-                format!("{lit}").yellow(),
+                format!("{lit}").fg(theme.literal),
                 " * 4 = ".into(),
-                format!("{}", (registers.pc as i128 + lit) * 4).yellow(),
+                format!("{}", offset_pc(registers.pc, lit).wrapping_mul(4)).fg(theme.literal),
             ],
             BranchNotZero(x0, lit) => vec![
                 "if ".into(),
-                format!("{}", registers.get(x0).unwrap()).red().bold(),
+                show(get(x0)).fg(theme.register).bold(),
                 " != 0: ".into(),
-                "PC".green().bold(),
+                "PC".fg(theme.pc).bold(),
                 " = ".into(),
-                format!("{}", registers.pc * 4).green().bold(),
+                format!("{}", registers.pc.wrapping_mul(4))
+                    .fg(theme.pc)
+                    .bold(),
                 " + ".into(),
-                format!("{lit}").yellow(),
+                format!("{lit}").fg(theme.literal),
                 " * 4 = ".into(),
-                format!("{}", (registers.pc as i128 + lit) * 4).yellow(),
+                format!("{}", offset_pc(registers.pc, lit).wrapping_mul(4)).fg(theme.literal),
             ],
 
-            None | Comment(_) => vec!["Stop Program".magenta().bold()],
+            Halt => vec!["Stop Program".fg(theme.stop).bold()],
+            Nop | None | Comment(_) => vec!["Nothing to do".fg(theme.muted)],
         }
     }
 
+    /// How the instruction uses `register`, if at all.
     pub fn is_reg_highlighted(&self, register: u8) -> Option<Highlight> {
         use Instruction::*;
 
@@ -565,7 +1055,10 @@ impl Instruction {
         use Option::None;
 
         match *self {
-            Add(x0, x1, x2) | Sub(x0, x1, x2) => {
+            Add(x0, x1, x2)
+            | Sub(x0, x1, x2)
+            | AddShifted(x0, x1, x2, _)
+            | SubShifted(x0, x1, x2, _) => {
                 if register == x0 {
                     Some(Highlight::Dest)
                 } else if register == x1 || register == x2 {
@@ -574,7 +1067,7 @@ impl Instruction {
                     None
                 }
             }
-            AddI(x0, x1, _) | SubI(x0, x1, _) => {
+            AddI(x0, x1, _) | SubI(x0, x1, _) | Mov(x0, x1) => {
                 if register == x0 {
                     Some(Highlight::Dest)
                 } else if register == x1 {
@@ -585,19 +1078,31 @@ impl Instruction {
             }
 
             Load(x0, Offset(x1, _)) => {
-                if register == x0 {
+                if register == x0 && register == x1 {
+                    Some(Highlight::DestAddress)
+                } else if register == x0 {
                     Some(Highlight::Dest)
                 } else if register == x1 {
-                    Some(Highlight::Source)
+                    Some(Highlight::Address)
                 } else {
                     None
                 }
             }
             Store(x0, Offset(x1, _)) => {
-                if register == x0 {
+                if register == x0 && register == x1 {
+                    Some(Highlight::SourceAddress)
+                } else if register == x0 {
                     Some(Highlight::Source)
                 } else if register == x1 {
-                    Some(Highlight::Source)
+                    Some(Highlight::Address)
+                } else {
+                    None
+                }
+            }
+
+            MovZ(x0, _) => {
+                if register == x0 {
+                    Some(Highlight::Dest)
                 } else {
                     None
                 }
@@ -611,17 +1116,57 @@ impl Instruction {
                 }
             }
 
-            Branch(_) | Instruction::None | Comment(_) => None,
+            Branch(_) | Halt | Nop | Instruction::None | Comment(_) => None,
         }
     }
 
+    /// Whether this reads the register `prev` writes to, other than `XZR`.
+    pub fn depends_on(&self, prev: &Instruction) -> bool {
+        let Some(dest) =
+            (0..31).find(|&x| prev.is_reg_highlighted(x).is_some_and(Highlight::writes))
+        else {
+            return false;
+        };
+
+        // Not `is_reg_highlighted`, which only says `Dest` for a register
+        // that's read and then written, like `X3` in `ADDI X3, X3, #1`
+        self.read_registers().contains(&dest)
+    }
+
+    /// Every register whose value this uses, other than `XZR`.
+    pub fn read_registers(&self) -> Vec<u8> {
+        use Instruction::*;
+
+        let regs = match *self {
+            Add(_, x1, x2)
+            | Sub(_, x1, x2)
+            | AddShifted(_, x1, x2, _)
+            | SubShifted(_, x1, x2, _) => {
+                vec![x1, x2]
+            }
+            AddI(_, x1, _) | SubI(_, x1, _) | Mov(_, x1) | Load(_, Offset(x1, _)) => vec![x1],
+            Store(x0, Offset(x1, _)) => vec![x0, x1],
+            BranchZero(x0, _) | BranchNotZero(x0, _) => vec![x0],
+            MovZ(..) | Branch(_) | Halt | Nop | Instruction::None | Comment(_) => vec![],
+        };
+
+        regs.into_iter().filter(|&x| x < 31).collect()
+    }
+
+    /// Whether `next` reads the register this instruction loads into, so
+    /// running them back to back would stall a pipeline.
+    pub fn load_use_hazard(&self, next: &Instruction) -> bool {
+        matches!(self, Instruction::Load(..)) && next.depends_on(self)
+    }
+
+    /// The byte address a load or store uses, and which way.
     pub fn highlighted_mem(&self, registers: &Registers) -> Option<(u64, Highlight)> {
         match *self {
             Instruction::Load(_, Offset(x0, off)) | Instruction::Store(_, Offset(x0, off)) => {
                 let value = registers.get(x0).unwrap();
                 let addr = (value as i128 + off) as u64;
 
-                if addr % 8 != 0 {
+                if !addr.is_multiple_of(8) {
                     None
                 } else {
                     let addr = addr / 8;
@@ -636,20 +1181,483 @@ impl Instruction {
         }
     }
 
+    /// Where a branch at `pc` would go, taken or not.
     pub fn highlighted_instr(&self, pc: u64) -> Option<u64> {
         if let Instruction::Branch(off)
         | Instruction::BranchZero(_, off)
         | Instruction::BranchNotZero(_, off) = self
         {
-            Some((pc as i128 + off) as u64)
+            Some(offset_pc(pc, *off))
         } else {
             None
         }
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Highlight {
     Source,
     Dest,
+    /// The base register of a load/store offset
+    Address,
+    /// Both the value a store writes and its base register, like
+    /// `STUR X1, [X1, #0]`
+    SourceAddress,
+    /// Both where a load goes and its base register, like
+    /// `LDUR X1, [X1, #0]`
+    DestAddress,
+}
+
+impl Highlight {
+    pub fn marker(self, theme: &Theme) -> Span<'static> {
+        match self {
+            Highlight::Source => "<".fg(theme.source).bold(),
+            Highlight::Dest => ">".fg(theme.dest).bold(),
+            Highlight::Address => "@".fg(theme.memory).bold(),
+            Highlight::SourceAddress => "<@".fg(theme.source).bold(),
+            Highlight::DestAddress => ">@".fg(theme.dest).bold(),
+        }
+    }
+
+    /// Whether the register's value is used, before anything is written.
+    pub fn reads(self) -> bool {
+        !matches!(self, Highlight::Dest)
+    }
+
+    /// Whether the register is written to.
+    pub fn writes(self) -> bool {
+        matches!(self, Highlight::Dest | Highlight::DestAddress)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    fn reg() -> impl Strategy<Value = u8> {
+        0..=31u8
+    }
+
+    /// Any instruction `validate` accepts.
+    fn instruction() -> impl Strategy<Value = Instruction> {
+        use Instruction::*;
+
+        prop_oneof![
+            (reg(), reg(), reg()).prop_map(|(a, b, c)| Add(a, b, c)),
+            (reg(), reg(), reg()).prop_map(|(a, b, c)| Sub(a, b, c)),
+            (reg(), reg(), reg(), 0..64u8).prop_map(|(a, b, c, sh)| AddShifted(a, b, c, sh)),
+            (reg(), reg(), reg(), 0..64u8).prop_map(|(a, b, c, sh)| SubShifted(a, b, c, sh)),
+            (reg(), reg(), 0..4096i128).prop_map(|(a, b, lit)| AddI(a, b, lit)),
+            (reg(), reg(), 0..4096i128).prop_map(|(a, b, lit)| SubI(a, b, lit)),
+            (reg(), reg()).prop_map(|(a, b)| Mov(a, b)),
+            (reg(), 0..65536i128).prop_map(|(a, lit)| MovZ(a, lit)),
+            (reg(), reg(), -256..=255i128).prop_map(|(a, b, off)| Load(a, Offset(b, off))),
+            (reg(), reg(), -256..=255i128).prop_map(|(a, b, off)| Store(a, Offset(b, off))),
+            (-33554432..=33554431i128).prop_map(Branch),
+            (reg(), -262144..=262143i128).prop_map(|(a, off)| BranchZero(a, off)),
+            (reg(), -262144..=262143i128).prop_map(|(a, off)| BranchNotZero(a, off)),
+            Just(Halt),
+            Just(Nop),
+            Just(None),
+            // Lines are trimmed before parsing, so a comment can't end in
+            // whitespace
+            "([ -~]*[!-~])?".prop_map(Comment),
+        ]
+    }
+
+    /// The text of an explanation, without its colors.
+    fn plain(spans: &[Span]) -> String {
+        spans.iter().map(|x| &*x.content).collect()
+    }
+
+    proptest! {
+        #[test]
+        fn toggling_twice_restores(instr in instruction()) {
+            if let Instruction::None | Instruction::Comment(_) = instr {
+                return Ok(());
+            }
+
+            let commented = instr.toggle_comment();
+
+            prop_assert!(matches!(commented, Instruction::Comment(_)));
+            prop_assert_eq!(commented.toggle_comment(), instr);
+        }
+
+        #[test]
+        fn display_parses_back(instr in instruction()) {
+            let parsed = instr.to_string().parse::<Instruction>().unwrap();
+
+            prop_assert_eq!(&parsed, &instr);
+            prop_assert_eq!(parsed.to_string(), instr.to_string());
+        }
+
+        #[test]
+        fn encoding_decodes_back(instr in instruction()) {
+            use Instruction::*;
+
+            let Some(word) = instr.encode() else {
+                return Ok(());
+            };
+
+            let expected = match instr {
+                AddShifted(a, b, c, 0) => Add(a, b, c),
+                SubShifted(a, b, c, 0) => Sub(a, b, c),
+                x => x,
+            };
+
+            prop_assert_eq!(Instruction::decode(word).unwrap(), expected);
+        }
+
+        #[test]
+        fn trailing_comment_is_ignored(instr in instruction(), note in "[ -~]*") {
+            if let Instruction::None | Instruction::Comment(_) = instr {
+                return Ok(());
+            }
+
+            let parsed = format!("{instr} //{note}").parse::<Instruction>().unwrap();
+
+            prop_assert_eq!(parsed, instr);
+        }
+    }
+
+    #[test]
+    fn parses_trailing_comments() {
+        let examples = [
+            ("ADD X0, X1, X2 // sum", "ADD X0, X1, X2"),
+            ("LDUR X9, [X22, #64]// A[8]", "LDUR X9, [X22, #64]"),
+            ("CBNZ X1, #-1 //", "CBNZ X1, #-1"),
+            ("HALT // ADD X0, X1, X2", "HALT"),
+        ];
+
+        for (line, expected) in examples {
+            let expected = expected.parse::<Instruction>().unwrap();
+
+            assert_eq!(line.parse::<Instruction>().unwrap(), expected, "{line}");
+        }
+    }
+
+    #[test]
+    fn toggling_comments() {
+        let add = "ADD X1, X2, X3".parse::<Instruction>().unwrap();
+        let commented = add.toggle_comment();
+
+        assert_eq!(commented.to_string(), "//~ add  X1, X2, X3");
+        assert_eq!(
+            commented.to_string().parse::<Instruction>().unwrap(),
+            commented
+        );
+        assert_eq!(commented.toggle_comment(), add);
+
+        // Comments which weren't code, and empty lines, stay as they are
+        let note = "// a note".parse::<Instruction>().unwrap();
+        assert_eq!(note.toggle_comment(), note);
+        assert_eq!(Instruction::None.toggle_comment(), Instruction::None);
+    }
+
+    #[test]
+    fn comments_keep_their_case() {
+        let line = "// Load the Counter, then ADD x1";
+        let parsed = line.parse::<Instruction>().unwrap();
+
+        assert_eq!(
+            parsed,
+            Instruction::Comment(" Load the Counter, then ADD x1".into())
+        );
+        assert_eq!(parsed.to_string(), line);
+        assert_eq!(parsed.to_string().parse::<Instruction>().unwrap(), parsed);
+    }
+
+    #[test]
+    fn registers_are_checked_when_parsed() {
+        let error = |line: &str| line.parse::<Instruction>().unwrap_err().to_string();
+
+        assert_eq!(error("ADD X0, X1, X45"), "Register X45 is out of range!");
+        assert_eq!(error("ADD X0, X1, X256"), "Register X256 is out of range!");
+        assert_eq!(error("add x32, x1, x2"), "Register X32 is out of range!");
+        assert!("ADD X0, X1, X31".parse::<Instruction>().is_ok());
+
+        // A saved file isn't parsed, but is still checked
+        assert!(Instruction::Add(0, 1, 45).validate().is_err());
+    }
+
+    #[test]
+    fn immediates_round_trip() {
+        let lines = [
+            "addi X1, X2, #0",
+            "addi X1, X2, #4095",
+            "subi X1, X2, #4095",
+            "add  X1, X2, X3, lsl #63",
+            "sub  X1, X2, X3, lsl #1",
+            "ldur X1, [X2, #-256]",
+            "stur X1, [X2, #255]",
+            "b    #-33554432",
+            "cbz  X1, #262143",
+            "cbnz X1, #-262144",
+        ];
+
+        for line in lines {
+            let instr = line.parse::<Instruction>().unwrap();
+
+            assert_eq!(instr.to_string(), line);
+            assert_eq!(plain(&instr.get_line(64, &Theme::dark())), line);
+        }
+
+        // Only offsets are signed
+        for line in [
+            "ADDI X1, X2, #-5",
+            "SUBI X1, X2, #-5",
+            "ADD X1, X2, X3, LSL #-1",
+        ] {
+            assert!(line.parse::<Instruction>().is_err(), "{line}");
+        }
+    }
+
+    #[test]
+    fn encodes_textbook_examples() {
+        let examples = [
+            ("ADD X9, X21, X9", 0x8b0902a9),
+            ("LDUR X9, [X22, #64]", 0xf84402c9),
+            ("STUR X9, [X22, #64]", 0xf80402c9),
+            ("SUBI X1, X2, #4", 0xd1001041),
+            ("CBNZ X1, #-1", 0xb5ffffe1),
+            ("B #-1", 0x17ffffff),
+            ("MOV X1, X2", 0xaa0203e1),
+        ];
+
+        for (line, encoding) in examples {
+            let instr = line.parse::<Instruction>().unwrap();
+
+            assert_eq!(instr.encode(), Some(encoding), "{line}");
+        }
+    }
+
+    #[test]
+    fn mov_parses_registers_and_constants() {
+        let constant = "MOV X1, #42".parse::<Instruction>().unwrap();
+        assert_eq!(constant, Instruction::MovZ(1, 42));
+        assert_eq!(constant.to_string(), "mov  X1, #42");
+        assert_eq!(constant.encode(), Some(0xd2800541));
+
+        // Past what ADDI could add, but still in 16 bits
+        assert_eq!(
+            "MOV X0, #4096".parse::<Instruction>().unwrap(),
+            Instruction::MovZ(0, 4096)
+        );
+
+        let error = |line: &str| line.parse::<Instruction>().unwrap_err().to_string();
+        assert_eq!(
+            error("MOV X0, #65536"),
+            "Constant: #65536 is too large, MOV only sets 0 to 65535!"
+        );
+        assert_eq!(
+            error("MOV X0, #-1"),
+            "Constant: #-1 can't be negative, MOV only sets 0 to 65535!"
+        );
+    }
+
+    #[test]
+    fn hash_is_optional_on_literals() {
+        for (with, without) in [
+            ("ADDI X0, X1, #5", "ADDI X0, X1, 5"),
+            ("SUBI X0, X1, #5", "SUBI X0, X1, 5"),
+            ("ADD X0, X1, X2, LSL #3", "ADD X0, X1, X2, LSL 3"),
+            ("LDUR X0, [X1, #8]", "LDUR X0, [X1, 8]"),
+            ("STUR X0, [SP, #-16]", "STUR X0, [SP, -16]"),
+            ("MOV X0, #7", "MOV X0, 7"),
+            ("B #-2", "B -2"),
+            ("CBZ X0, #4", "CBZ X0, 4"),
+        ] {
+            let parsed = without.parse::<Instruction>().unwrap();
+
+            assert_eq!(parsed, with.parse::<Instruction>().unwrap(), "{without}");
+            assert!(parsed.to_string().contains('#'), "{parsed}");
+        }
+    }
+
+    #[test]
+    fn whitespace_around_operands_is_optional() {
+        for (canonical, loose) in [
+            ("ADD X0, X1, X2", "add X0 ,  X1,X2"),
+            ("ADD X0, X1, X2, LSL #2", "ADD\tX0,X1,X2,LSL  #2"),
+            ("LDUR X0, [X1, #8]", "ldur x0,[ x1 ,#8 ]"),
+            ("STUR X0, [SP, #-8]", "STUR X0 ,[SP,#-8]"),
+            ("CBNZ X0, #-2", "CBNZ X0,#-2"),
+        ] {
+            assert_eq!(
+                loose.parse::<Instruction>().unwrap(),
+                canonical.parse::<Instruction>().unwrap(),
+                "{loose}"
+            );
+        }
+    }
+
+    #[test]
+    fn operands_line_up() {
+        let theme = Theme::dark();
+
+        for line in [
+            "ADD X1, X2, X3",
+            "B #1",
+            "CBNZ X1, #-1",
+            "LDUR X1, [X2, #8]",
+        ] {
+            let instr = line.parse::<Instruction>().unwrap();
+            let spans = instr.get_line(64, &theme);
+
+            assert_eq!(spans[0].width(), MNEMONIC_WIDTH, "{line}");
+        }
+    }
+
+    #[test]
+    fn wrapping_is_annotated() {
+        let explain = |line: &str, a: u64, b: u64| {
+            let mut registers = Registers::new();
+            registers.set(1, a).unwrap();
+            registers.set(2, b).unwrap();
+
+            plain(&line.parse::<Instruction>().unwrap().explain_sub(
+                &registers,
+                &Memory::new(),
+                64,
+                false,
+                &Theme::dark(),
+            ))
+        };
+
+        assert_eq!(
+            explain("ADD X0, X1, X2", u64::MAX, 1),
+            format!("X0 = {} + 1 = 0 (carry)", u64::MAX)
+        );
+        assert_eq!(explain("ADD X0, X1, X2", 1, 1), "X0 = 1 + 1 = 2");
+        assert_eq!(
+            explain("ADDI X0, X1, #1", u64::MAX, 0),
+            format!("X0 = {} + 1 = 0 (carry)", u64::MAX)
+        );
+        assert_eq!(
+            explain("SUBI X0, X1, #1", 0, 0),
+            format!("X0 = 0 - 1 = {} (borrow)", u64::MAX)
+        );
+        assert_eq!(explain("SUB X0, X1, X2", 2, 1), "X0 = 2 - 1 = 1");
+    }
+
+    #[test]
+    fn branch_explanations_wrap_like_pc() {
+        // PC * 4 is past 64 bits, which used to overflow
+        let mut registers = Registers::new();
+        registers.pc = 1 << 62;
+
+        for (line, expected) in [
+            ("B #1", "PC = 0 + 1 * 4 = 4"),
+            ("CBZ XZR, #1", "if 0 == 0: PC = 0 + 1 * 4 = 4"),
+            ("CBNZ XZR, #1", "if 0 != 0: PC = 0 + 1 * 4 = 4"),
+        ] {
+            let explained = plain(&line.parse::<Instruction>().unwrap().explain_sub(
+                &registers,
+                &Memory::new(),
+                64,
+                false,
+                &Theme::dark(),
+            ));
+
+            assert_eq!(explained, expected);
+        }
+    }
+
+    #[test]
+    fn base_registers_show_their_sign() {
+        let mut registers = Registers::new();
+        registers.set(1, -8i64 as u64).unwrap();
+
+        for (line, expected) in [
+            (
+                "LDUR X0, [X1, #16]",
+                "X0 = M[18446744073709551608 (-8) + 16 = 8] = 0",
+            ),
+            (
+                "STUR X0, [X1, #16]",
+                "M[18446744073709551608 (-8) + 16 = 8] = 0",
+            ),
+        ] {
+            let instr = line.parse::<Instruction>().unwrap();
+
+            // A W register still uses all 64 bits of the base
+            for word_bits in [64, 32] {
+                let explained = plain(&instr.explain_sub(
+                    &registers,
+                    &Memory::new(),
+                    word_bits,
+                    true,
+                    &Theme::dark(),
+                ));
+
+                assert_eq!(
+                    explained,
+                    expected.replace("X0", &register_name(0, word_bits))
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn same_register_for_value_and_address() {
+        let store = "STUR X1, [X1, #0]".parse::<Instruction>().unwrap();
+        let load = "LDUR X1, [X1, #0]".parse::<Instruction>().unwrap();
+
+        assert_eq!(store.is_reg_highlighted(1), Some(Highlight::SourceAddress));
+        assert_eq!(load.is_reg_highlighted(1), Some(Highlight::DestAddress));
+
+        // Still told apart when they're different
+        let store = "STUR X1, [X2, #0]".parse::<Instruction>().unwrap();
+
+        assert_eq!(store.is_reg_highlighted(1), Some(Highlight::Source));
+        assert_eq!(store.is_reg_highlighted(2), Some(Highlight::Address));
+
+        // Loading over the base is still a use of the old value
+        let add = "ADD X3, X1, X1".parse::<Instruction>().unwrap();
+
+        assert!(add.depends_on(&load));
+        assert!(load.depends_on(&load));
+
+        // Reading the register it then writes over is still a use
+        let load = "LDUR X3, [X0, #0]".parse::<Instruction>().unwrap();
+
+        for next in ["ADDI X3, X3, #1", "ADD X3, X3, X4", "SUB X3, X3, X3"] {
+            let next = next.parse::<Instruction>().unwrap();
+
+            assert!(load.load_use_hazard(&next), "{next}");
+        }
+        assert!(!load.load_use_hazard(&"ADDI X3, X4, #1".parse().unwrap()));
+    }
+
+    #[test]
+    fn parse_errors_point_at_the_original_line() {
+        let error = |line: &str| {
+            line.parse::<Instruction>()
+                .unwrap_err()
+                .downcast::<ParseError>()
+                .unwrap()
+        };
+
+        let misspelled = error("ADDD X1, X2, #3");
+        assert_eq!(misspelled.column, 3);
+        assert_eq!(misspelled.to_string().lines().last(), Some("   ^"));
+
+        let missing_comma = error("ADDI X1 X2, #3");
+        assert_eq!(missing_comma.column, 8);
+        assert!(missing_comma.message.contains("`,`"));
+
+        // The caret lines up with what was typed, not the uppercased copy
+        let mixed = error("addI x1, Xa2, #3");
+        assert_eq!(mixed.line, "addI x1, Xa2, #3");
+        assert_eq!(mixed.column, 10);
+        assert_eq!(
+            mixed.to_string(),
+            format!("{}\naddI x1, Xa2, #3\n          ^^", mixed.message)
+        );
+
+        assert_eq!(original_column("ßx", 2), 1);
+        assert_eq!(original_column("ßx", 3), 2);
+    }
 }
@@ -0,0 +1,317 @@
+use std::collections::HashMap;
+
+use color_eyre::eyre::{eyre, Result};
+
+use super::{Instruction, Simulator};
+
+/// Addresses (`0x400000`, `0040:`) and encodings (`8B020020`) put in front of
+/// an instruction. Mnemonics like `ADD` and `B` are valid hex too, so only
+/// tokens with a digit in them count.
+fn is_address(token: &str) -> bool {
+    let token = token.strip_suffix(':').unwrap_or(token);
+    let digits = token
+        .strip_prefix("0x")
+        .or_else(|| token.strip_prefix("0X"))
+        .unwrap_or(token);
+
+    !digits.is_empty()
+        && digits.chars().all(|x| x.is_ascii_hexdigit())
+        && (digits.len() != token.len() || digits.chars().any(|x| x.is_ascii_digit()))
+}
+
+/// `loop:` and the like.
+fn is_label(token: &str) -> bool {
+    token.strip_suffix(':').is_some_and(|x| {
+        !x.is_empty()
+            && x.chars()
+                .all(|x| x.is_ascii_alphanumeric() || x == '_' || x == '.')
+    })
+}
+
+/// The label a branch like `B done` or `CBZ X0, done` goes to, if it's
+/// written with one.
+fn branch_label(line: &str) -> Option<&str> {
+    let mnemonic = line.split_whitespace().next()?.to_uppercase();
+    let operand = line.rsplit([',', ' ', '\t']).next()?;
+
+    (["B", "CBZ", "CBNZ"].contains(&mnemonic.as_str()) && is_label(&format!("{operand}:")))
+        .then_some(operand)
+}
+
+/// A number in a directive, in decimal or `0x` hex. Negative numbers are
+/// stored in two's complement.
+fn parse_value(token: &str) -> Option<u64> {
+    let hex = token
+        .strip_prefix("0x")
+        .or_else(|| token.strip_prefix("0X"));
+
+    match hex {
+        Some(digits) => u64::from_str_radix(digits, 16).ok(),
+        None => token
+            .parse::<i128>()
+            .ok()
+            .filter(|&x| x >= i64::MIN as i128 && x <= u64::MAX as i128)
+            .map(|x| x as u64),
+    }
+}
+
+impl Simulator {
+    /// Reads the instructions out of a listing like the ones in the
+    /// textbook, ignoring addresses, encodings and comments.
+    ///
+    /// Labels are kept in `labels`, and a branch can be written to one, like
+    /// `CBZ X0, done`, which is turned into the offset to it.
+    ///
+    /// After `.data`, or `.data 0x100` to start somewhere other than 0,
+    /// each `.word 1, 2, 3` fills the next slots of memory, until `.text`.
+    /// Any other assembler directive is skipped, and returned as a warning.
+    pub fn from_listing(listing: &str) -> Result<(Self, Vec<String>)> {
+        let mut sim = Simulator::new();
+        let mut warnings = Vec::new();
+        // The byte address the next `.word` goes in, while in `.data`
+        let mut data = None;
+        // Every label, and the instruction it's on
+        let mut labels = HashMap::new();
+        // Instructions which only parse once the labels after them are known
+        let mut pending = Vec::new();
+
+        for (line_number, line) in listing.lines().enumerate() {
+            let line_number = line_number + 1;
+
+            let mut rest = match line.find("//") {
+                Some(idx) => &line[..idx],
+                None => line,
+            }
+            .trim();
+
+            let mut line_labels = Vec::new();
+
+            while let Some(token) = rest.split_whitespace().next() {
+                // `0040:` is an address, though it looks like a label too
+                if is_label(token) && !is_address(token) {
+                    line_labels.push(token.strip_suffix(':').unwrap());
+                } else if !is_address(token) {
+                    break;
+                }
+
+                rest = rest[token.len()..].trim_start();
+            }
+
+            // Labels in `.data` name memory, which branches can't go to
+            if data.is_none() && (rest.is_empty() || !rest.starts_with('.')) {
+                let idx = sim.instructions.len();
+
+                for label in line_labels {
+                    if labels.insert(label.to_string(), idx).is_some() {
+                        return Err(eyre!(
+                            "Line {line_number}: label {label:?} is defined twice"
+                        ));
+                    }
+
+                    sim.labels.entry(idx).or_insert_with(|| label.to_string());
+                }
+            }
+
+            if rest.is_empty() {
+                continue;
+            }
+
+            let (directive, args) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            let args = args.trim();
+
+            match directive {
+                ".data" => {
+                    let base = if args.is_empty() {
+                        0
+                    } else {
+                        parse_value(args)
+                            .filter(|x| x.is_multiple_of(8))
+                            .ok_or_else(|| {
+                                eyre!("Line {line_number}: {args:?} is not a multiple of 8!")
+                            })?
+                    };
+
+                    data = Some(base);
+                    continue;
+                }
+                ".text" => {
+                    data = None;
+                    continue;
+                }
+                ".word" if data.is_some() => {
+                    for token in args.split(',').map(str::trim) {
+                        let val = parse_value(token).ok_or_else(|| {
+                            eyre!("Line {line_number}: {token:?} is not a number!")
+                        })?;
+                        let addr = data.unwrap();
+
+                        sim.memory.set(addr, val)?;
+                        data = Some(addr.wrapping_add(8));
+                    }
+
+                    continue;
+                }
+                _ => {}
+            }
+
+            if rest.starts_with('.') {
+                warnings.push(format!("Line {line_number}: skipping directive {rest:?}"));
+                continue;
+            }
+
+            if data.is_some() {
+                return Err(eyre!(
+                    "Line {line_number}: {rest:?} is in `.data`, put `.text` before it"
+                ));
+            }
+
+            let instr = match rest.parse::<Instruction>() {
+                Ok(instr) => instr,
+                Err(_) if branch_label(rest).is_some() => {
+                    // Filled in once every label is known
+                    pending.push((sim.instructions.len(), rest, line_number));
+                    Instruction::None
+                }
+                Err(e) => return Err(eyre!("Line {line_number}: {e}")),
+            };
+
+            sim.instructions.push(instr);
+        }
+
+        for (idx, line, line_number) in pending {
+            let label = branch_label(line).unwrap();
+
+            let Some(&target) = labels.get(label) else {
+                return Err(eyre!("Line {line_number}: there's no label {label:?}"));
+            };
+
+            // The offset goes where the label was
+            let head = &line[..line.len() - label.len()];
+            let resolved = format!("{head}#{}", target as i128 - idx as i128);
+
+            sim.instructions[idx] = resolved
+                .parse::<Instruction>()
+                .map_err(|e| eyre!("Line {line_number}: {e}"))?;
+        }
+
+        Ok((sim, warnings))
+    }
+
+    /// Builds a program out of machine code, one word of 8 hex digits per
+    /// line. Blank lines and `//` comments are skipped.
+    pub fn from_hex(words: &str) -> Result<Self> {
+        let mut sim = Simulator::new();
+
+        for (line_number, line) in words.lines().enumerate() {
+            let line_number = line_number + 1;
+
+            let word = match line.find("//") {
+                Some(idx) => &line[..idx],
+                None => line,
+            }
+            .trim();
+
+            if word.is_empty() {
+                continue;
+            }
+
+            if word.len() != 8 || !word.chars().all(|x| x.is_ascii_hexdigit()) {
+                return Err(eyre!("Line {line_number}: {word:?} is not 8 hex digits!"));
+            }
+
+            let word = u32::from_str_radix(word, 16)?;
+            let instr = Instruction::decode(word).map_err(|e| eyre!("Line {line_number}: {e}"))?;
+
+            sim.instructions.push(instr);
+        }
+
+        Ok(sim)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_a_textbook_listing() {
+        let listing = "\t.text\n\
+            \t.global main\n\
+            main:\n\
+            0x400000\tf84402c9\tLDUR X9, [X22, #64]\t// temp reg X9 gets A[8]\n\
+            0x400004\t8b0902a9\tADD  X9, X21, X9\n\
+            0x400008:\tf80402c9\tSTUR X9, [X22, #64]\n";
+
+        let (sim, warnings) = Simulator::from_listing(listing).unwrap();
+
+        let expected = [
+            "LDUR X9, [X22, #64]",
+            "ADD X9, X21, X9",
+            "STUR X9, [X22, #64]",
+        ]
+        .map(|x| x.parse::<Instruction>().unwrap());
+
+        assert_eq!(sim.instructions, expected);
+        assert_eq!(sim.labels, [(0, "main".to_string())].into());
+        assert_eq!(warnings, ["Line 2: skipping directive \".global main\""]);
+    }
+
+    #[test]
+    fn branch_labels_resolve_to_offsets() {
+        let (labelled, _) = Simulator::from_listing(
+            "loop:   CBZ  X0, done\n        SUBI X0, X0, #1\n        CBNZ X0, loop\n        B    loop\ndone:   HALT",
+        )
+        .unwrap();
+        let (numeric, _) =
+            Simulator::from_listing("CBZ X0, #4\nSUBI X0, X0, #1\nCBNZ X0, #-2\nB #-3\nHALT")
+                .unwrap();
+
+        assert_eq!(labelled.instructions, numeric.instructions);
+        assert_eq!(
+            labelled.labels,
+            [(0, "loop".to_string()), (4, "done".to_string())].into()
+        );
+
+        let Err(missing) = Simulator::from_listing("CBZ X0, nowhere") else {
+            panic!("an unknown label should be an error");
+        };
+        assert_eq!(missing.to_string(), "Line 1: there's no label \"nowhere\"");
+    }
+
+    #[test]
+    fn words_fill_consecutive_slots() {
+        let (sim, warnings) =
+            Simulator::from_listing(".data\n.word 1, 2, 3\n.text\nLDUR X1, [XZR, #8]").unwrap();
+
+        assert!(warnings.is_empty());
+        assert_eq!(sim.instructions.len(), 1);
+        assert_eq!(sim.memory.get(0).unwrap(), 1);
+        assert_eq!(sim.memory.get(8).unwrap(), 2);
+        assert_eq!(sim.memory.get(16).unwrap(), 3);
+        assert_eq!(sim.memory.get(24).unwrap(), 0);
+
+        // Another `.word` carries on where the last one stopped
+        let (sim, _) = Simulator::from_listing(".data 0x100\n.word 1, 2\n.word 3\n.text").unwrap();
+
+        assert_eq!(sim.memory.get(0).unwrap(), 0);
+        assert_eq!(sim.memory.get(0x100).unwrap(), 1);
+        assert_eq!(sim.memory.get(0x108).unwrap(), 2);
+        assert_eq!(sim.memory.get(0x110).unwrap(), 3);
+
+        let Err(unaligned) = Simulator::from_listing(".data 4") else {
+            panic!("a base which isn't a multiple of 8 should be an error");
+        };
+        assert_eq!(
+            unaligned.to_string(),
+            "Line 1: \"4\" is not a multiple of 8!"
+        );
+
+        let Err(code) = Simulator::from_listing(".data\nNOP") else {
+            panic!("an instruction in `.data` should be an error");
+        };
+        assert_eq!(
+            code.to_string(),
+            "Line 2: \"NOP\" is in `.data`, put `.text` before it"
+        );
+    }
+}
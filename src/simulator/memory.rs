@@ -1,22 +1,35 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
 use color_eyre::eyre::{eyre, Result};
 use serde::{Deserialize, Serialize};
 
+/// 64 bit slots, addressed by byte. Slots which have never been written to
+/// read as zero. Kept in order, so saved files come out the same each time.
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Memory {
-    memory: HashMap<u64, u64>,
+    memory: BTreeMap<u64, u64>,
+    /// Writes at or past this byte address fail, if given
+    #[serde(default)]
+    pub max_addr: Option<u64>,
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Memory {
     pub fn new() -> Self {
         Self {
-            memory: HashMap::new(),
+            memory: BTreeMap::new(),
+            max_addr: None,
         }
     }
 
+    /// The slot at `byte_addr`, which must be a multiple of 8.
     pub fn get(&self, byte_addr: u64) -> Result<u64> {
-        if byte_addr % 8 != 0 {
+        if !byte_addr.is_multiple_of(8) {
             Err(eyre!("Byte address {byte_addr} is not a multiple of 8!"))?;
         }
 
@@ -27,11 +40,19 @@ impl Memory {
         Ok(val)
     }
 
+    /// Sets the slot at `byte_addr`, which must be a multiple of 8 and
+    /// below `max_addr`.
     pub fn set(&mut self, byte_addr: u64, val: u64) -> Result<()> {
-        if byte_addr % 8 != 0 {
+        if !byte_addr.is_multiple_of(8) {
             Err(eyre!("Byte address {byte_addr} is not a mutiple of 8!"))?;
         }
 
+        if let Some(max_addr) = self.max_addr.filter(|&x| byte_addr >= x) {
+            Err(eyre!(
+                "Byte address {byte_addr} is past the end of memory, which is {max_addr} bytes!"
+            ))?;
+        }
+
         let idx = byte_addr / 8;
 
         if val == 0 {
@@ -43,8 +64,26 @@ impl Memory {
         Ok(())
     }
 
-    /// returns slots, not memory addresses
+    /// Sets every slot back to zero.
+    pub fn clear(&mut self) {
+        self.memory.clear();
+    }
+
+    /// returns slots, not memory addresses, in increasing order
     pub fn get_used<'a>(&'a self) -> impl Iterator<Item = u64> + 'a {
         self.memory.keys().copied()
     }
 }
+
+/// How many times a slot has been loaded from and stored to.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Accesses {
+    pub reads: u64,
+    pub writes: u64,
+}
+
+impl Accesses {
+    pub fn total(&self) -> u64 {
+        self.reads + self.writes
+    }
+}
@@ -1,75 +1,370 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
 mod registers;
-use color_eyre::eyre::Result;
-use instruction::Offset;
-pub use registers::Registers;
+use color_eyre::eyre::{bail, Result};
+use instruction::{offset_pc, shift_left, CYCLE_COSTS};
+use registers::{add_word, sub_word};
+pub use registers::{format_word, register_name, Profile, Registers, SP};
 
 mod memory;
-pub use memory::Memory;
+pub use memory::{Accesses, Memory};
 
 mod instruction;
-pub use instruction::{Highlight, Instruction};
+pub use instruction::{Highlight, Instruction, Offset, ParseError};
+
+mod expect;
+pub use expect::Expectation;
+
+mod pipeline;
+pub use pipeline::{Pipeline, STAGE_NAMES};
+
+mod diff;
+pub use diff::SimulatorDiff;
+
+mod garbage;
+pub use garbage::GARBAGE_SLOTS;
+
+mod dot;
+
+mod csv;
+
+mod gas;
+
+mod listing;
 use serde::{Deserialize, Serialize};
 
+/// Bumped whenever saved files need more than serde defaults to load.
+pub const CURRENT_VERSION: u32 = 1;
+
 #[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct Simulator {
+    /// Which `CURRENT_VERSION` this was saved with, 0 being before versions
+    /// were recorded. See `migrate`.
+    #[serde(default)]
+    pub version: u32,
     pub registers: Registers,
     pub memory: Memory,
     pub instructions: Vec<Instruction>,
+    /// 64 to work with `X` registers, or 32 to work with `W` registers.
+    #[serde(default = "default_word_bits")]
+    pub word_bits: u8,
+    /// How registers are named and which ones warn when written to.
+    #[serde(default)]
+    pub profile: Profile,
+    /// Estimated cycles spent so far, see `Instruction::cost`.
+    #[serde(default)]
+    pub cycles: u64,
+    /// Byte addresses which pause execution when stored to. Kept in order,
+    /// like memory, so saved files come out the same each time.
+    #[serde(default)]
+    pub watchpoints: BTreeSet<u64>,
+    /// Free text shown beside instructions, by index. Kept with the same
+    /// instruction as lines are added and removed, see `insert_line`.
+    #[serde(default)]
+    pub notes: BTreeMap<usize, String>,
+    /// Names from a listing's labels, by instruction index, shown in place
+    /// of the offsets of branches to them. Moved like `notes`.
+    #[serde(default)]
+    pub labels: BTreeMap<usize, String>,
+    /// The instruction PC starts at when the program is loaded or run, so
+    /// anything before it can be skipped without moving it.
+    #[serde(default)]
+    pub entry: u64,
+    /// How often each slot has been used by running, by slot. Only kept
+    /// until the program starts over, and never saved.
+    #[serde(skip)]
+    pub accesses: BTreeMap<u64, Accesses>,
+    /// Registers written by running or by hand since the program last
+    /// started over. Never saved, like `accesses`.
+    #[serde(skip)]
+    pub written: [bool; 31],
+    /// Registers read by running before anything was written to them.
+    #[serde(skip)]
+    pub read_unwritten: BTreeSet<u8>,
+}
+
+fn default_word_bits() -> u8 {
+    64
+}
+
+/// Memory is only ever accessed a whole slot at a time, so a load or store
+/// whose address isn't a multiple of 8 stops the program, blaming the
+/// instruction and its base register.
+fn check_aligned(instr: &Instruction, base: u8, off: i128, addr: u64) -> Result<()> {
+    if !addr.is_multiple_of(8) {
+        bail!(
+            "`{instr}` can't use M[{addr}], as {} + {off} is not a multiple of 8!",
+            register_name(base, 64)
+        );
+    }
+
+    Ok(())
+}
+
+impl Default for Simulator {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Simulator {
+    /// An empty program, with every register and memory slot zeroed.
     pub fn new() -> Self {
         Self {
+            version: CURRENT_VERSION,
             registers: Registers::new(),
             memory: Memory::new(),
             instructions: Vec::new(),
+            word_bits: default_word_bits(),
+            profile: Profile::default(),
+            cycles: 0,
+            watchpoints: BTreeSet::new(),
+            notes: BTreeMap::new(),
+            labels: BTreeMap::new(),
+            entry: 0,
+            accesses: BTreeMap::new(),
+            written: [false; 31],
+            read_unwritten: BTreeSet::new(),
+        }
+    }
+
+    /// Upgrades a freshly deserialized state to `CURRENT_VERSION`.
+    pub fn migrate(&mut self) -> Result<()> {
+        if self.version > CURRENT_VERSION {
+            bail!(
+                "This file is from a newer version of the simulator (v{}, expected at most v{CURRENT_VERSION})",
+                self.version
+            );
+        }
+
+        // v0 -> v1: only new fields, which serde already defaults.
+
+        self.version = CURRENT_VERSION;
+
+        Ok(())
+    }
+
+    /// Inserts `instr` at `idx`, changing branch offsets so that every
+    /// branch still lands on the same instruction (or the end) as before.
+    pub fn insert_instruction(&mut self, idx: usize, instr: Instruction) {
+        let target = idx as i128;
+
+        self.remap_branches(|i| if i >= target { i + 1 } else { i });
+        self.insert_line(idx, instr);
+    }
+
+    /// Removes the instruction at `idx`, changing branch offsets so that
+    /// every other branch still lands on the same instruction as before.
+    /// Branches to the removed instruction land on the one after it.
+    pub fn remove_instruction(&mut self, idx: usize) -> Instruction {
+        let target = idx as i128;
+
+        self.remap_branches(|i| if i > target { i - 1 } else { i });
+        self.remove_line(idx)
+    }
+
+    /// Inserts `instr` at `idx`, leaving branch offsets as they are. Notes,
+    /// labels and the entry move down with the instructions they're on.
+    pub fn insert_line(&mut self, idx: usize, instr: Instruction) {
+        self.notes = std::mem::take(&mut self.notes)
+            .into_iter()
+            .map(|(i, note)| (if i >= idx { i + 1 } else { i }, note))
+            .collect();
+
+        self.labels = std::mem::take(&mut self.labels)
+            .into_iter()
+            .map(|(i, label)| (if i >= idx { i + 1 } else { i }, label))
+            .collect();
+
+        if self.entry >= idx as u64 {
+            self.entry += 1;
+        }
+
+        self.instructions.insert(idx, instr);
+    }
+
+    /// Removes the instruction at `idx`, and its note, leaving branch
+    /// offsets as they are. Notes below it move up with their instructions.
+    /// Its label goes to the instruction after it, unless that has one.
+    pub fn remove_line(&mut self, idx: usize) -> Instruction {
+        self.notes = std::mem::take(&mut self.notes)
+            .into_iter()
+            .filter(|&(i, _)| i != idx)
+            .map(|(i, note)| (if i > idx { i - 1 } else { i }, note))
+            .collect();
+
+        // Collecting keeps the last of two labels on the same index
+        self.labels = std::mem::take(&mut self.labels)
+            .into_iter()
+            .map(|(i, label)| (if i > idx { i - 1 } else { i }, label))
+            .collect();
+
+        if self.entry > idx as u64 {
+            self.entry -= 1;
+        }
+
+        self.instructions.remove(idx)
+    }
+
+    /// Rewrites every branch offset for instructions (and targets) moving
+    /// from index `i` to `map(i)`.
+    fn remap_branches(&mut self, map: impl Fn(i128) -> i128) {
+        for (source, instr) in self.instructions.iter_mut().enumerate() {
+            if let Instruction::Branch(off)
+            | Instruction::BranchZero(_, off)
+            | Instruction::BranchNotZero(_, off) = instr
+            {
+                let source = source as i128;
+                let target = source + *off;
+
+                *off = map(target) - map(source);
+            }
         }
     }
 
+    /// Whether PC has run off the end of the program. This is the only way
+    /// a program finishes, other than stopping at a `HALT`.
+    pub fn is_finished(&self) -> bool {
+        self.registers.pc >= self.instructions.len() as u64
+    }
+
+    /// Puts PC back at the entry, or the end if that's past it, and forgets
+    /// what running did before.
+    pub fn reset_pc(&mut self) {
+        self.registers.pc = self.entry.min(self.instructions.len() as u64);
+        self.forget_run();
+    }
+
+    /// Forgets what running has done so far, which is all that `accesses`,
+    /// `written` and `read_unwritten` describe. Registers and memory are
+    /// left alone.
+    pub fn forget_run(&mut self) {
+        self.accesses.clear();
+        self.written = [false; 31];
+        self.read_unwritten.clear();
+    }
+
+    /// Runs one instruction, for callers which don't care what it did.
     pub fn tick(&mut self) -> Result<RunningState> {
-        let pc = self.registers.pc as usize;
+        Ok(self.step()?.running)
+    }
+
+    /// Runs up to `n` instructions, stopping early at a halt, a watchpoint or
+    /// the end of the program. Returns how many ran, and whether to keep
+    /// going.
+    pub fn tick_n(&mut self, n: u64) -> Result<(u64, RunningState)> {
+        for ran in 0..n {
+            if self.is_finished() {
+                return Ok((ran, RunningState::ShouldStop));
+            }
+
+            match self.tick()? {
+                RunningState::KeepRunning => {}
+                state => return Ok((ran + 1, state)),
+            }
+        }
+
+        let state = if self.is_finished() {
+            RunningState::ShouldStop
+        } else {
+            RunningState::KeepRunning
+        };
+
+        Ok((n, state))
+    }
 
-        if pc >= self.instructions.len() {
-            return Ok(RunningState::ShouldStop);
+    /// Runs one instruction, saying what it changed along with whether to
+    /// keep going. Each instruction finishes before the next starts, so a
+    /// load always sees the stores before it, even one right before it.
+    pub fn step(&mut self) -> Result<StepOutcome> {
+        if self.is_finished() {
+            return Ok(StepOutcome {
+                running: RunningState::ShouldStop,
+                effect: TickEffect::None,
+                unwritten_read: None,
+            });
         }
 
+        let pc = self.registers.pc as usize;
+
         let instr = &self.instructions[pc];
 
+        // Only the first read of each register is reported, so a loop over
+        // one doesn't report it every time around
+        let mut unwritten_read = None;
+
+        for reg in instr.read_registers() {
+            if !self.written[reg as usize] && self.read_unwritten.insert(reg) {
+                unwritten_read = unwritten_read.or(Some(reg));
+            }
+        }
+
         let mut pc_diff = 1;
+        let mut cycles = instr.cost();
+        let mut state = RunningState::KeepRunning;
+        let mut effect = TickEffect::None;
 
         match *instr {
             Instruction::Add(r0, r1, r2) => {
-                let vr1 = self.registers.get(r1)?;
-                let vr2 = self.registers.get(r2)?;
+                let vr1 = self.registers.get_word(r1, self.word_bits)?;
+                let vr2 = self.registers.get_word(r2, self.word_bits)?;
 
-                let (result, _) = vr1.overflowing_add(vr2);
+                let (result, _) = add_word(vr1, vr2, self.word_bits);
 
-                self.registers.set(r0, result)?;
+                effect = self.write_reg(r0, result)?;
             }
 
             Instruction::Sub(r0, r1, r2) => {
-                let vr1 = self.registers.get(r1)?;
-                let vr2 = self.registers.get(r2)?;
+                let vr1 = self.registers.get_word(r1, self.word_bits)?;
+                let vr2 = self.registers.get_word(r2, self.word_bits)?;
+
+                let (result, _) = sub_word(vr1, vr2, self.word_bits);
+
+                effect = self.write_reg(r0, result)?;
+            }
+
+            Instruction::AddShifted(r0, r1, r2, sh) => {
+                let vr1 = self.registers.get_word(r1, self.word_bits)?;
+                let vr2 = self.registers.get_word(r2, self.word_bits)?;
+
+                let (result, _) = add_word(vr1, shift_left(vr2, sh), self.word_bits);
 
-                let (result, _) = vr1.overflowing_sub(vr2);
+                effect = self.write_reg(r0, result)?;
+            }
+
+            Instruction::SubShifted(r0, r1, r2, sh) => {
+                let vr1 = self.registers.get_word(r1, self.word_bits)?;
+                let vr2 = self.registers.get_word(r2, self.word_bits)?;
 
-                self.registers.set(r0, result)?;
+                let (result, _) = sub_word(vr1, shift_left(vr2, sh), self.word_bits);
+
+                effect = self.write_reg(r0, result)?;
             }
 
             Instruction::AddI(r0, r1, lit) => {
-                let vr1 = self.registers.get(r1)?;
+                let vr1 = self.registers.get_word(r1, self.word_bits)?;
 
-                let (result, _) = vr1.overflowing_add(lit as u64);
+                let (result, _) = add_word(vr1, lit as u64, self.word_bits);
 
-                self.registers.set(r0, result)?;
+                effect = self.write_reg(r0, result)?;
             }
 
             Instruction::SubI(r0, r1, lit) => {
-                let vr1 = self.registers.get(r1)?;
+                let vr1 = self.registers.get_word(r1, self.word_bits)?;
+
+                let (result, _) = sub_word(vr1, lit as u64, self.word_bits);
 
-                let (result, _) = vr1.overflowing_sub(lit as u64);
+                effect = self.write_reg(r0, result)?;
+            }
+
+            Instruction::Mov(r0, r1) => {
+                let vr1 = self.registers.get_word(r1, self.word_bits)?;
+
+                effect = self.write_reg(r0, vr1)?;
+            }
 
-                self.registers.set(r0, result)?;
+            Instruction::MovZ(r0, lit) => {
+                effect = self.write_reg(r0, lit as u64)?;
             }
 
             Instruction::Load(r0, Offset(r1, off)) => {
@@ -78,9 +373,13 @@ impl Simulator {
                 let truncated = new_addr & (u64::MAX as i128);
                 let truncated = truncated as u64;
 
+                check_aligned(instr, r1, off, truncated)?;
+
                 let val = self.memory.get(truncated)?;
 
-                self.registers.set(r0, val)?;
+                self.accesses.entry(truncated / 8).or_default().reads += 1;
+
+                effect = self.write_reg(r0, val)?;
             }
 
             Instruction::Store(r0, Offset(r1, off)) => {
@@ -89,45 +388,663 @@ impl Simulator {
                 let truncated = new_addr & (u64::MAX as i128);
                 let truncated = truncated as u64;
 
-                let val = self.registers.get(r0)?;
+                check_aligned(instr, r1, off, truncated)?;
+
+                let val = self.registers.get_word(r0, self.word_bits)?;
+                let old = self.memory.get(truncated)?;
+
+                if self.watchpoints.contains(&truncated) {
+                    state = RunningState::Watchpoint {
+                        addr: truncated,
+                        old,
+                        new: val,
+                    };
+                }
 
                 self.memory.set(truncated, val)?;
+
+                self.accesses.entry(truncated / 8).or_default().writes += 1;
+
+                effect = TickEffect::MemWrite {
+                    addr: truncated,
+                    old,
+                    new: val,
+                };
             }
 
             Instruction::Branch(off) => {
                 pc_diff = off;
+                cycles += CYCLE_COSTS.taken_branch;
             }
 
             Instruction::BranchZero(r0, off) => {
-                let val = self.registers.get(r0)?;
+                let val = self.registers.get_word(r0, self.word_bits)?;
 
                 if val == 0 {
                     pc_diff = off;
+                    cycles += CYCLE_COSTS.taken_branch;
                 }
             }
 
             Instruction::BranchNotZero(r0, off) => {
-                let val = self.registers.get(r0)?;
+                let val = self.registers.get_word(r0, self.word_bits)?;
 
                 if val != 0 {
                     pc_diff = off;
+                    cycles += CYCLE_COSTS.taken_branch;
                 }
             }
 
-            Instruction::None | Instruction::Comment(_) => return Ok(RunningState::ShouldStop),
+            Instruction::Halt => {
+                return Ok(StepOutcome {
+                    running: RunningState::ShouldStop,
+                    effect,
+                    unwritten_read,
+                })
+            }
+
+            Instruction::Nop => {}
+
+            // Skipped over, like an assembler would
+            Instruction::None | Instruction::Comment(_) => {}
+        }
+
+        self.registers.pc = offset_pc(self.registers.pc, pc_diff);
+        self.cycles += cycles as u64;
+
+        if pc_diff != 1 {
+            effect = TickEffect::Branch {
+                from: pc as u64,
+                to: self.registers.pc,
+            };
+        }
+
+        Ok(StepOutcome {
+            running: state,
+            effect,
+            unwritten_read,
+        })
+    }
+
+    /// Writes `val` to a register as a word, returning what changed.
+    fn write_reg(&mut self, reg: u8, val: u64) -> Result<TickEffect> {
+        let old = self.registers.get(reg)?;
+
+        self.registers.set_word(reg, val, self.word_bits)?;
+
+        if let Some(written) = self.written.get_mut(reg as usize) {
+            *written = true;
+        }
+
+        Ok(TickEffect::RegWrite {
+            reg,
+            old,
+            new: self.registers.get(reg)?,
+        })
+    }
+
+    /// Compares this state against `other`, treating this one as the old one.
+    pub fn diff(&self, other: &Self) -> SimulatorDiff {
+        SimulatorDiff::new(self, other)
+    }
+
+    /// Checks every instruction up front, returning every problem found as
+    /// `(index, message)` rather than stopping at the first like running
+    /// would. Branches may land on the end, but no further.
+    pub fn validate_program(&self) -> Result<(), Vec<(usize, String)>> {
+        let mut problems = Vec::new();
+
+        for (idx, instr) in self.instructions.iter().enumerate() {
+            if let Err(e) = instr.clone().validate() {
+                problems.push((idx, e.to_string()));
+                continue;
+            }
+
+            if let Instruction::Branch(off)
+            | Instruction::BranchZero(_, off)
+            | Instruction::BranchNotZero(_, off) = *instr
+            {
+                let target = idx as i128 + off;
+
+                if target < 0 || target > self.instructions.len() as i128 {
+                    problems.push((
+                        idx,
+                        format!("Jump #{off} lands on line {target}, outside the program!"),
+                    ));
+                }
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+
+    /// Best-effort warnings to go with `validate_program`, as `(index,
+    /// message)`: branches from before the first write to a register which
+    /// jump past it, to somewhere that reads it before writing it. That's
+    /// usually setup being skipped, but a branch around an `if` can trip it
+    /// too, so these are never errors.
+    pub fn lint_skipped_setup(&self) -> Vec<(usize, String)> {
+        let mut warnings = Vec::new();
+
+        for reg in 0..31 {
+            let writes =
+                |instr: &Instruction| instr.is_reg_highlighted(reg).is_some_and(Highlight::writes);
+
+            let Some(first_write) = self.instructions.iter().position(writes) else {
+                continue;
+            };
+
+            for (idx, instr) in self.instructions[..first_write].iter().enumerate() {
+                let Some(target) = instr.highlighted_instr(idx as u64) else {
+                    continue;
+                };
+
+                let target = target as usize;
+
+                if target <= first_write {
+                    continue;
+                }
+
+                // Whatever reads it first after landing, unless it's set again
+                let read =
+                    self.instructions
+                        .iter()
+                        .enumerate()
+                        .skip(target)
+                        .find_map(|(i, instr)| {
+                            if instr.read_registers().contains(&reg) {
+                                Some(Some(i))
+                            } else if writes(instr) {
+                                Some(None)
+                            } else {
+                                None
+                            }
+                        });
+
+                if let Some(Some(read)) = read {
+                    warnings.push((
+                        idx,
+                        format!(
+                            "Jumps to line {target}, past line {first_write} which first sets {}, but line {read} reads it",
+                            self.profile.name(reg, self.word_bits)
+                        ),
+                    ));
+                }
+            }
         }
 
-        let new_pc = self.registers.pc as i128 + pc_diff;
+        warnings.sort_by_key(|&(idx, _)| idx);
+        warnings
+    }
+
+    /// Every instruction index which can be reached from `entry`, by
+    /// falling through or branching. Conditional branches are assumed to go
+    /// both ways, and `HALT` goes nowhere.
+    pub fn reachable_instructions(&self) -> HashSet<usize> {
+        let len = self.instructions.len();
+
+        let mut reachable = HashSet::new();
+        let mut to_visit = vec![self.entry as usize];
+
+        while let Some(idx) = to_visit.pop() {
+            if idx >= len || !reachable.insert(idx) {
+                continue;
+            }
+
+            let branch_to = |off: i128| usize::try_from(idx as i128 + off).ok();
 
-        let new_pc = (new_pc & u64::MAX as i128) as u64;
+            match self.instructions[idx] {
+                Instruction::Halt => {}
+                Instruction::Branch(off) => to_visit.extend(branch_to(off)),
+                Instruction::BranchZero(_, off) | Instruction::BranchNotZero(_, off) => {
+                    to_visit.push(idx + 1);
+                    to_visit.extend(branch_to(off));
+                }
+                _ => to_visit.push(idx + 1),
+            }
+        }
+
+        reachable
+    }
 
-        self.registers.pc = new_pc;
+    /// Finds every load which is immediately followed by an instruction
+    /// reading the loaded register, as `(load, use)` instruction indices.
+    pub fn detect_hazards(&self) -> Vec<(usize, usize)> {
+        self.instructions
+            .windows(2)
+            .enumerate()
+            .filter(|(_, pair)| pair[0].load_use_hazard(&pair[1]))
+            .map(|(idx, _)| (idx, idx + 1))
+            .collect()
+    }
 
-        Ok(RunningState::KeepRunning)
+    /// Every branch which could jump to instruction `target`, taken or not,
+    /// in order. It's one pass over the program, so fine to do every frame.
+    pub fn branches_to(&self, target: u64) -> Vec<u64> {
+        (0..self.instructions.len() as u64)
+            .filter(|&idx| self.instructions[idx as usize].highlighted_instr(idx) == Some(target))
+            .collect()
     }
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum RunningState {
     KeepRunning,
     ShouldStop,
+    /// A store to a watched address, which has already gone through.
+    Watchpoint {
+        addr: u64,
+        old: u64,
+        new: u64,
+    },
+}
+
+/// The one thing an instruction changed, besides moving PC along.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TickEffect {
+    RegWrite {
+        reg: u8,
+        old: u64,
+        new: u64,
+    },
+    MemWrite {
+        addr: u64,
+        old: u64,
+        new: u64,
+    },
+    /// A taken branch, between instruction indices
+    Branch {
+        from: u64,
+        to: u64,
+    },
+    None,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct StepOutcome {
+    pub running: RunningState,
+    pub effect: TickEffect,
+    /// A register read before anything wrote to it, the first time it is
+    pub unwritten_read: Option<u8>,
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::text::Span;
+
+    use super::*;
+    use crate::theme::Theme;
+
+    /// The text of an explanation, without its colors.
+    fn plain(spans: &[Span]) -> String {
+        spans.iter().map(|x| &*x.content).collect()
+    }
+
+    #[test]
+    fn migrates_v0_saves() {
+        let mut sim = Simulator::new();
+        sim.registers.set(1, 42).unwrap();
+        sim.memory.set(8, 7).unwrap();
+        sim.instructions = vec![Instruction::Nop, Instruction::Halt];
+
+        // Before versions, only these three were saved
+        let serde_json::Value::Object(mut fields) = serde_json::to_value(&sim).unwrap() else {
+            unreachable!();
+        };
+        fields.retain(|k, _| matches!(k.as_str(), "registers" | "memory" | "instructions"));
+
+        let mut loaded: Simulator = serde_json::from_value(fields.into()).unwrap();
+        assert_eq!(loaded.version, 0);
+
+        loaded.migrate().unwrap();
+
+        assert_eq!(loaded.version, CURRENT_VERSION);
+        assert!(loaded == sim);
+
+        loaded.version = CURRENT_VERSION + 1;
+        assert!(loaded.migrate().is_err());
+    }
+
+    /// A program from one instruction per line, keeping comments and blank
+    /// lines, which `from_listing` drops.
+    fn lines(program: &str) -> Simulator {
+        let mut sim = Simulator::new();
+        sim.instructions = program.lines().map(|x| x.parse().unwrap()).collect();
+        sim
+    }
+
+    #[test]
+    fn comments_dont_stop_the_program() {
+        let mut sim = lines("ADDI X1, XZR, #1\n// and then\n\nADDI X2, X1, #1");
+
+        let mut ticks = 0;
+        while let RunningState::KeepRunning = sim.tick().unwrap() {
+            ticks += 1;
+        }
+
+        assert_eq!(ticks, 4);
+        assert_eq!(sim.registers.pc, 4);
+        assert_eq!(sim.registers.get(2).unwrap(), 2);
+    }
+
+    #[test]
+    fn nop_changes_nothing() {
+        let run = |program: &str| {
+            let mut sim = lines(program);
+            sim.registers.set(1, 5).unwrap();
+
+            let mut pcs = vec![];
+            while let RunningState::KeepRunning = sim.tick().unwrap() {
+                pcs.push(sim.registers.pc);
+            }
+
+            (sim, pcs)
+        };
+
+        let (mut without, _) = run("ADDI X2, X1, #3\nSTUR X2, [XZR, #8]");
+        let (with, pcs) = run("ADDI X2, X1, #3\nNOP\nSTUR X2, [XZR, #8]");
+
+        // The same besides where PC ended up
+        assert_eq!(pcs, [1, 2, 3]);
+        without.registers.pc = 3;
+        assert_eq!(with.registers, without.registers);
+        assert!(with.memory == without.memory);
+        assert_eq!(with.memory.get(8).unwrap(), 8);
+
+        // Only PC moves, by one instruction
+        let mut sim = lines("NOP");
+        let before = sim.clone();
+        assert_eq!(sim.step().unwrap().effect, TickEffect::None);
+        assert_eq!(sim.registers.pc, 1);
+        sim.registers.pc = 0;
+        assert_eq!(sim.registers, before.registers);
+        assert!(sim.memory == before.memory);
+    }
+
+    #[test]
+    fn steps_report_their_effect() {
+        use TickEffect::*;
+
+        let reg = |reg, old, new| RegWrite { reg, old, new };
+
+        // X1 = 5 and M[16] = 9 before each one, with PC at 1
+        let cases = [
+            ("ADD X2, X1, X1", reg(2, 0, 10)),
+            ("SUB X1, X1, X1", reg(1, 5, 0)),
+            ("ADD X2, X1, X1, LSL #1", reg(2, 0, 15)),
+            ("SUB X2, X1, X1, LSL #1", reg(2, 0, 5u64.wrapping_sub(10))),
+            ("ADDI X1, X1, #3", reg(1, 5, 8)),
+            ("SUBI X1, X1, #3", reg(1, 5, 2)),
+            ("MOV X3, X1", reg(3, 0, 5)),
+            ("LDUR X2, [X1, #11]", reg(2, 0, 9)),
+            (
+                "STUR X1, [X1, #11]",
+                MemWrite {
+                    addr: 16,
+                    old: 9,
+                    new: 5,
+                },
+            ),
+            ("B #2", Branch { from: 1, to: 3 }),
+            ("CBZ XZR, #-1", Branch { from: 1, to: 0 }),
+            ("CBNZ X1, #3", Branch { from: 1, to: 4 }),
+            ("CBZ X1, #3", None),
+            ("CBNZ XZR, #3", None),
+            ("NOP", None),
+            ("// nothing", None),
+            ("HALT", None),
+        ];
+
+        for (line, expected) in cases {
+            let mut sim = lines(&format!("NOP\n{line}\nNOP\nNOP\nNOP"));
+            sim.registers.pc = 1;
+            sim.registers.set(1, 5).unwrap();
+            sim.memory.set(16, 9).unwrap();
+
+            assert_eq!(sim.step().unwrap().effect, expected, "{line}");
+        }
+    }
+
+    #[test]
+    fn validation_finds_every_problem() {
+        use Instruction::*;
+
+        let mut sim = Simulator::new();
+        sim.instructions = vec![
+            Add(1, 2, 40),
+            Nop,
+            Branch(-3),
+            AddI(1, 2, 5000),
+            BranchZero(1, 3),
+            Halt,
+        ];
+
+        let problems = sim.validate_program().unwrap_err();
+        let lines = problems.iter().map(|x| x.0).collect::<Vec<_>>();
+
+        assert_eq!(lines, [0, 2, 3, 4]);
+        assert_eq!(problems[0].1, "Register X40 does not exist!");
+        assert_eq!(
+            problems[1].1,
+            "Jump #-3 lands on line -1, outside the program!"
+        );
+        assert_eq!(
+            problems[3].1,
+            "Jump #3 lands on line 7, outside the program!"
+        );
+
+        // Landing just past the end is fine, that's how a program ends
+        sim.instructions = vec![Nop, BranchZero(1, 1)];
+        assert_eq!(sim.validate_program(), Ok(()));
+    }
+
+    #[test]
+    fn reachability_starts_at_the_entry() {
+        let (mut sim, _) =
+            Simulator::from_listing("ADDI X1, XZR, #1\nB #2\nNOP\nHALT\nNOP").unwrap();
+
+        let reachable = |sim: &Simulator| {
+            let mut found = sim.reachable_instructions().into_iter().collect::<Vec<_>>();
+            found.sort();
+            found
+        };
+
+        assert_eq!(reachable(&sim), [0, 1, 3]);
+
+        sim.entry = 2;
+        assert_eq!(reachable(&sim), [2, 3]);
+
+        // An entry at the end runs nothing
+        sim.entry = 5;
+        assert!(reachable(&sim).is_empty());
+    }
+
+    #[test]
+    fn stores_past_the_memory_limit_fail() {
+        let (mut sim, _) =
+            Simulator::from_listing("STUR X1, [XZR, #56]\nSTUR X1, [XZR, #64]\nNOP").unwrap();
+        sim.memory.max_addr = Some(64);
+        sim.registers.set(1, 7).unwrap();
+
+        assert_eq!(sim.tick().unwrap(), RunningState::KeepRunning);
+        assert_eq!(sim.memory.get(56).unwrap(), 7);
+
+        let error = sim.tick().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Byte address 64 is past the end of memory, which is 64 bytes!"
+        );
+
+        // Nothing was written, and PC stays on the store
+        assert_eq!(sim.memory.get(64).unwrap(), 0);
+        assert_eq!(sim.registers.pc, 1);
+    }
+
+    #[test]
+    fn starting_over_forgets_the_run() {
+        let (mut sim, _) =
+            Simulator::from_listing("LDUR X2, [X1, #0]\nADDI X3, XZR, #1\nSTUR X3, [XZR, #8]")
+                .unwrap();
+        sim.entry = 1;
+
+        sim.registers.pc = 0;
+        for _ in 0..3 {
+            sim.tick().unwrap();
+        }
+        assert!(!sim.accesses.is_empty());
+        assert!(sim.written[2] && sim.written[3]);
+        assert_eq!(sim.read_unwritten, [1].into());
+
+        sim.reset_pc();
+        assert_eq!(sim.registers.pc, 1);
+        assert!(sim.accesses.is_empty());
+        assert_eq!(sim.written, [false; 31]);
+        assert!(sim.read_unwritten.is_empty());
+
+        // What it left behind stays
+        assert_eq!(sim.registers.get(3).unwrap(), 1);
+        assert_eq!(sim.memory.get(8).unwrap(), 1);
+    }
+
+    #[test]
+    fn runs_from_entry() {
+        let (mut sim, _) =
+            Simulator::from_listing("ADDI X1, XZR, #1\nADDI X2, XZR, #2\nADD X3, X1, X2").unwrap();
+
+        sim.entry = 1;
+        sim.reset_pc();
+
+        while let RunningState::KeepRunning = sim.tick().unwrap() {}
+
+        assert_eq!(sim.registers.get(1).unwrap(), 0);
+        assert_eq!(sim.registers.get(3).unwrap(), 2);
+    }
+
+    #[test]
+    fn tick_n_stops_at_the_end() {
+        let (mut sim, _) =
+            Simulator::from_listing("ADDI X1, XZR, #1\nADDI X2, XZR, #2\nADD X3, X1, X2").unwrap();
+
+        assert_eq!(sim.tick_n(5).unwrap(), (3, RunningState::ShouldStop));
+        assert_eq!(sim.registers.get(3).unwrap(), 3);
+        assert_eq!(sim.tick_n(5).unwrap(), (0, RunningState::ShouldStop));
+    }
+
+    #[test]
+    fn saves_are_byte_identical() {
+        let save = |addrs: &[u64]| {
+            let mut sim = Simulator::new();
+            for &addr in addrs {
+                sim.memory.set(addr, addr + 1).unwrap();
+                sim.watchpoints.insert(addr);
+            }
+            serde_json::to_string(&sim).unwrap()
+        };
+
+        let addrs: Vec<u64> = (0..64).map(|i| i * 8).collect();
+        let reversed: Vec<u64> = addrs.iter().rev().copied().collect();
+
+        assert_eq!(save(&addrs), save(&addrs));
+        assert_eq!(save(&addrs), save(&reversed));
+    }
+
+    #[test]
+    fn lints_branches_past_setup() {
+        let (skips, _) = Simulator::from_listing(
+            "        CBZ  X0, use\n        ADDI X1, XZR, #5\nuse:    ADD  X2, X1, X1\n        HALT",
+        )
+        .unwrap();
+
+        assert_eq!(
+            skips.lint_skipped_setup(),
+            [(
+                0,
+                "Jumps to line 2, past line 1 which first sets X1, but line 2 reads it".to_string()
+            )]
+        );
+        assert_eq!(skips.validate_program(), Ok(()));
+
+        // Both ways set X1 before it's read
+        let (if_else, _) = Simulator::from_listing(
+            "        CBZ  X0, else\n        ADDI X1, XZR, #5\n        B    end\nelse:   ADDI X1, XZR, #7\nend:    ADD  X2, X1, X1",
+        )
+        .unwrap();
+
+        assert_eq!(if_else.lint_skipped_setup(), []);
+    }
+
+    #[test]
+    fn branches_count_instructions() {
+        let (mut sim, _) = Simulator::from_listing("NOP\nB #2\nNOP\nNOP").unwrap();
+        sim.registers.pc = 1;
+
+        let branch = sim.instructions[1].clone();
+        let explained =
+            plain(&branch.explain_sub(&sim.registers, &sim.memory, 64, false, &Theme::dark()));
+
+        assert_eq!(
+            plain(&branch.explain_unsub(64, &Theme::dark())),
+            "PC = PC + 2 * 4"
+        );
+        assert_eq!(branch.highlighted_instr(1), Some(3));
+
+        sim.step().unwrap();
+
+        assert_eq!(sim.registers.pc, 3);
+        assert_eq!(
+            explained,
+            format!("PC = 4 + 2 * 4 = {}", sim.registers.pc * 4)
+        );
+    }
+
+    #[test]
+    fn subi_explanation_matches_running_it() {
+        for start in [10, 3, 1] {
+            let (mut sim, _) = Simulator::from_listing("SUBI X1, X2, #3").unwrap();
+            sim.registers.set(2, start).unwrap();
+
+            let explained = plain(&sim.instructions[0].explain_sub(
+                &sim.registers,
+                &sim.memory,
+                64,
+                false,
+                &Theme::dark(),
+            ));
+
+            sim.step().unwrap();
+
+            let result = sim.registers.get(1).unwrap();
+            let expected = format!("X1 = {start} - 3 = {result}");
+
+            assert!(explained.starts_with(&expected), "{explained}");
+        }
+    }
+
+    #[test]
+    fn reading_unwritten_register_warns() {
+        let (mut sim, _) =
+            Simulator::from_listing("ADDI X5, X5, #1\nADD X6, X5, X5\nADD X7, X5, X6").unwrap();
+
+        assert_eq!(sim.step().unwrap().unwritten_read, Some(5));
+        assert_eq!(sim.step().unwrap().unwritten_read, None);
+        assert_eq!(sim.step().unwrap().unwritten_read, None);
+        assert_eq!(sim.read_unwritten, [5].into());
+    }
+
+    #[test]
+    fn mov_copies_registers_and_constants() {
+        let (mut sim, _) =
+            Simulator::from_listing("MOV X1, #42\nMOV X2, X1\nMOV X1, XZR\nMOV X3, #65535")
+                .unwrap();
+
+        while let RunningState::KeepRunning = sim.tick().unwrap() {}
+
+        assert_eq!(sim.registers.get(1).unwrap(), 0);
+        assert_eq!(sim.registers.get(2).unwrap(), 42);
+        assert_eq!(sim.registers.get(3).unwrap(), 65535);
+    }
 }
@@ -0,0 +1,164 @@
+use color_eyre::eyre::Result;
+
+use super::{RunningState, Simulator};
+
+pub const STAGE_NAMES: [&str; 5] = ["IF", "ID", "EX", "MEM", "WB"];
+
+const IF: usize = 0;
+const ID: usize = 1;
+const EX: usize = 2;
+const MEM: usize = 3;
+const WB: usize = 4;
+
+/// A classic 5 stage pipeline layered over `Simulator::tick`.
+///
+/// Instructions are executed as they enter EX, so results are forwarded in
+/// time for the next instruction, except for a load followed by a use of
+/// the loaded register, which stalls for a cycle. A taken branch flushes the
+/// two instructions fetched after it.
+pub struct Pipeline {
+    /// The instruction index in each stage, or `None` for a bubble.
+    pub stages: [Option<u64>; 5],
+    /// `stages` for every cycle so far, oldest first.
+    pub history: Vec<[Option<u64>; 5]>,
+    fetch_pc: u64,
+    halted: bool,
+}
+
+impl Pipeline {
+    pub fn new(pc: u64) -> Self {
+        Self {
+            stages: [None; 5],
+            history: Vec::new(),
+            fetch_pc: pc,
+            halted: false,
+        }
+    }
+
+    pub fn is_done(&self, sim: &Simulator) -> bool {
+        let nothing_to_fetch = self.halted || self.fetch_pc >= sim.instructions.len() as u64;
+
+        nothing_to_fetch && self.stages.iter().all(Option::is_none)
+    }
+
+    /// Advances every stage by one cycle.
+    pub fn step(&mut self, sim: &mut Simulator) -> Result<RunningState> {
+        if self.is_done(sim) {
+            return Ok(RunningState::ShouldStop);
+        }
+
+        let old = self.stages;
+        let mut new = [None; 5];
+
+        new[WB] = old[MEM];
+        new[MEM] = old[EX];
+
+        if self.load_use_hazard(sim, old[EX], old[ID]) {
+            // Hold IF and ID where they are and send a bubble into EX.
+            new[ID] = old[ID];
+            new[IF] = old[IF];
+        } else {
+            new[EX] = old[ID];
+            new[ID] = old[IF];
+            new[IF] = self.fetch(sim);
+
+            if let Some(idx) = new[EX] {
+                let state = sim.tick()?;
+
+                if let RunningState::ShouldStop = state {
+                    self.halted = true;
+                    new[ID] = None;
+                    new[IF] = None;
+                } else if sim.registers.pc != idx + 1 {
+                    // Taken branch, throw away what we fetched after it.
+                    new[ID] = None;
+                    new[IF] = None;
+                    self.fetch_pc = sim.registers.pc;
+                }
+            }
+        }
+
+        self.stages = new;
+        self.history.push(new);
+
+        Ok(RunningState::KeepRunning)
+    }
+
+    fn fetch(&mut self, sim: &Simulator) -> Option<u64> {
+        if self.halted || self.fetch_pc >= sim.instructions.len() as u64 {
+            return None;
+        }
+
+        self.fetch_pc += 1;
+
+        Some(self.fetch_pc - 1)
+    }
+
+    fn load_use_hazard(&self, sim: &Simulator, ex: Option<u64>, id: Option<u64>) -> bool {
+        let (Some(ex), Some(id)) = (ex, id) else {
+            return false;
+        };
+
+        match (
+            sim.instructions.get(ex as usize),
+            sim.instructions.get(id as usize),
+        ) {
+            (Some(ex), Some(id)) => ex.load_use_hazard(id),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The stage occupancy of every cycle until the program is done.
+    fn run(listing: &str) -> Vec<[Option<u64>; 5]> {
+        let (mut sim, _) = Simulator::from_listing(listing).unwrap();
+        let mut pipeline = Pipeline::new(sim.registers.pc);
+
+        while let RunningState::KeepRunning = pipeline.step(&mut sim).unwrap() {}
+
+        pipeline.history
+    }
+
+    #[test]
+    fn load_use_inserts_one_bubble() {
+        let history = run("LDUR X1, [XZR, #0]\nADD X2, X1, X1\nNOP");
+
+        assert_eq!(history[1], [Some(1), Some(0), None, None, None]);
+        assert_eq!(history[2], [Some(2), Some(1), Some(0), None, None]);
+        // The add waits in ID while a bubble goes into EX
+        assert_eq!(history[3], [Some(2), Some(1), None, Some(0), None]);
+        assert_eq!(history[4], [None, Some(2), Some(1), None, Some(0)]);
+        assert_eq!(history.iter().filter(|x| x[EX] == Some(1)).count(), 1);
+
+        // Even when the add writes back to the register it reads
+        for add in ["ADDI X1, X1, #1", "ADD X1, X1, X2"] {
+            let history = run(&format!("LDUR X1, [XZR, #0]\n{add}\nNOP"));
+            assert_eq!(history[3], [Some(2), Some(1), None, Some(0), None], "{add}");
+        }
+
+        // Without the dependency nothing stalls
+        let history = run("LDUR X1, [XZR, #0]\nADD X2, X3, X3\nNOP");
+        assert_eq!(history[3], [None, Some(2), Some(1), Some(0), None]);
+    }
+
+    #[test]
+    fn taken_branches_flush() {
+        for branch in ["B #3", "CBZ XZR, #3"] {
+            let history = run(&format!("{branch}\nNOP\nNOP\nNOP\nNOP"));
+
+            // What was fetched behind the branch never reaches EX
+            assert_eq!(history[1], [Some(1), Some(0), None, None, None]);
+            assert_eq!(history[2], [None, None, Some(0), None, None], "{branch}");
+            assert_eq!(history[3], [Some(3), None, None, Some(0), None], "{branch}");
+            assert!(history.iter().all(|x| x[EX] != Some(1) && x[EX] != Some(2)));
+        }
+
+        // A branch which isn't taken flushes nothing
+        let history = run("CBNZ XZR, #3\nNOP\nNOP\nNOP\nNOP");
+        assert_eq!(history[2], [Some(2), Some(1), Some(0), None, None]);
+    }
+}
@@ -1,13 +1,23 @@
 use color_eyre::{eyre::eyre, Result};
 use serde::{Deserialize, Serialize};
 
+/// LEGv8 uses X28 as the stack pointer, which can be written `SP`.
+pub const SP: u8 = 28;
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Registers {
     registers: [u64; 31],
     pub pc: u64,
 }
 
+impl Default for Registers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Registers {
+    /// Every register zeroed.
     pub fn new() -> Self {
         Self {
             registers: [0; 31],
@@ -15,6 +25,7 @@ impl Registers {
         }
     }
 
+    /// The value of `X<idx>`, 31 being `XZR` which always reads as zero.
     pub fn get(&self, idx: u8) -> Result<u64> {
         match idx {
             0..31 => Ok(self.registers[idx as usize]),
@@ -23,6 +34,7 @@ impl Registers {
         }
     }
 
+    /// Sets `X<idx>`. Writes to `XZR` are thrown away.
     pub fn set(&mut self, idx: u8, val: u64) -> Result<()> {
         match idx {
             0..31 => self.registers[idx as usize] = val,
@@ -32,4 +44,106 @@ impl Registers {
 
         Ok(())
     }
+
+    /// Like `get`, but only the low `word_bits` bits.
+    pub fn get_word(&self, idx: u8, word_bits: u8) -> Result<u64> {
+        Ok(mask_word(self.get(idx)?, word_bits))
+    }
+
+    /// Like `set`, but zeroes everything above the low `word_bits` bits.
+    pub fn set_word(&mut self, idx: u8, val: u64, word_bits: u8) -> Result<()> {
+        self.set(idx, mask_word(val, word_bits))
+    }
+}
+
+/// Truncates `val` to the low `word_bits` bits.
+pub fn mask_word(val: u64, word_bits: u8) -> u64 {
+    if word_bits >= 64 {
+        val
+    } else {
+        val & ((1 << word_bits) - 1)
+    }
+}
+
+/// The low `word_bits` bits of `val`, followed by what they mean as a signed
+/// number when `signed` is set and that's different, like `4294967295 (-1)`.
+pub fn format_word(val: u64, word_bits: u8, signed: bool) -> String {
+    let val = mask_word(val, word_bits);
+
+    // Sign extend from the top bit of the word
+    let shift = 64 - word_bits as u32;
+    let as_signed = ((val << shift) as i64) >> shift;
+
+    if signed && as_signed < 0 {
+        format!("{val} ({as_signed})")
+    } else {
+        format!("{val}")
+    }
+}
+
+/// `a + b` as a `word_bits` wide word, and whether it carried out of the top
+/// bit, wrapping around.
+pub fn add_word(a: u64, b: u64, word_bits: u8) -> (u64, bool) {
+    let (result, carried) = mask_word(a, word_bits).overflowing_add(mask_word(b, word_bits));
+
+    let masked = mask_word(result, word_bits);
+
+    (masked, carried || masked != result)
+}
+
+/// `a - b` as a `word_bits` wide word, and whether it had to borrow, wrapping
+/// around below zero.
+pub fn sub_word(a: u64, b: u64, word_bits: u8) -> (u64, bool) {
+    let (result, borrowed) = mask_word(a, word_bits).overflowing_sub(mask_word(b, word_bits));
+
+    (mask_word(result, word_bits), borrowed)
+}
+
+/// `X<n>` normally, or `W<n>` when working with 32 bit words.
+pub fn register_name(idx: u8, word_bits: u8) -> String {
+    if word_bits == 32 {
+        format!("W{idx}")
+    } else {
+        format!("X{idx}")
+    }
+}
+
+/// Which course's conventions registers are named and warned about by.
+/// Instructions run the same under every profile.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Profile {
+    /// Plain LEGv8, where every register is `X<n>` and only writing to XZR
+    /// warns
+    #[default]
+    Legv8,
+    /// LEGv8 with the special registers named `IP0`, `IP1`, `SP`, `FP` and
+    /// `LR`, and a warning for writes to X16 to X18, which are reserved
+    Reserved,
+}
+
+impl Profile {
+    /// What register `idx` is called in the registers view and warnings.
+    pub fn name(self, idx: u8, word_bits: u8) -> String {
+        let alias = match (self, idx) {
+            (Profile::Legv8, _) => None,
+            (Profile::Reserved, 16) => Some("IP0"),
+            (Profile::Reserved, 17) => Some("IP1"),
+            (Profile::Reserved, SP) => Some("SP"),
+            (Profile::Reserved, 29) => Some("FP"),
+            (Profile::Reserved, 30) => Some("LR"),
+            (Profile::Reserved, _) => None,
+        };
+
+        alias.map_or_else(|| register_name(idx, word_bits), str::to_string)
+    }
+
+    /// Who register `idx` is kept for, if a program shouldn't write to it.
+    pub fn reserved_for(self, idx: u8) -> Option<&'static str> {
+        match (self, idx) {
+            (Profile::Reserved, 16 | 17) => Some("the linker"),
+            (Profile::Reserved, 18) => Some("the platform"),
+            _ => None,
+        }
+    }
 }
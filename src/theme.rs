@@ -0,0 +1,124 @@
+use std::{collections::HashMap, str::FromStr};
+
+use color_eyre::eyre::{bail, eyre, Result};
+use ratatui::style::Color;
+
+/// Every color the UI uses, by what it's used for.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Theme {
+    /// Mnemonics like `add`, and window titles
+    pub keyword: Color,
+    /// Register names and values
+    pub register: Color,
+    /// Immediates and computed results
+    pub literal: Color,
+    pub comment: Color,
+    /// `M[...]`, and the marker for an address being used
+    pub memory: Color,
+    /// `PC`, and the markers for where PC and SP point
+    pub pc: Color,
+    /// Addresses in the memory and stack views
+    pub address: Color,
+    /// Marker for a register or slot being read
+    pub source: Color,
+    /// Marker for a register or slot being written
+    pub dest: Color,
+    /// "Stop Program"
+    pub stop: Color,
+    pub error: Color,
+    /// Load-use hazards and watchpoints
+    pub warning: Color,
+    /// Dialog borders
+    pub dialog: Color,
+    /// Key names in the command bar
+    pub key_hint: Color,
+    /// Bubbles and other filler
+    pub muted: Color,
+}
+
+impl Theme {
+    pub fn dark() -> Self {
+        Self {
+            keyword: Color::Blue,
+            register: Color::Red,
+            literal: Color::Yellow,
+            comment: Color::LightGreen,
+            memory: Color::LightMagenta,
+            pc: Color::Green,
+            address: Color::Red,
+            source: Color::Green,
+            dest: Color::Cyan,
+            stop: Color::Magenta,
+            error: Color::Red,
+            warning: Color::Yellow,
+            dialog: Color::Cyan,
+            key_hint: Color::LightBlue,
+            muted: Color::DarkGray,
+        }
+    }
+
+    /// Darker colors which stay readable on a light background.
+    pub fn light() -> Self {
+        Self {
+            keyword: Color::Blue,
+            register: Color::Red,
+            literal: Color::Indexed(130),
+            comment: Color::Indexed(28),
+            memory: Color::Magenta,
+            pc: Color::Indexed(28),
+            address: Color::Red,
+            source: Color::Indexed(28),
+            dest: Color::Indexed(25),
+            stop: Color::Magenta,
+            error: Color::Red,
+            warning: Color::Indexed(130),
+            dialog: Color::Blue,
+            key_hint: Color::Blue,
+            muted: Color::Gray,
+        }
+    }
+
+    /// Reads a JSON object of color names to colors, like
+    /// `{ "base": "light", "literal": "#aa6600" }`, on top of the dark theme
+    /// or whichever `base` names.
+    pub fn from_config(text: &str) -> Result<Self> {
+        let entries = serde_json::from_str::<HashMap<String, String>>(text)?;
+
+        let mut theme = match entries.get("base").map(String::as_str) {
+            None | Some("dark") => Self::dark(),
+            Some("light") => Self::light(),
+            Some(other) => bail!("Unknown base theme {other:?}, expected \"dark\" or \"light\""),
+        };
+
+        for (name, color) in &entries {
+            if name == "base" {
+                continue;
+            }
+
+            let color = Color::from_str(color).map_err(|_| eyre!("{color:?} is not a color!"))?;
+
+            let slot = match name.as_str() {
+                "keyword" => &mut theme.keyword,
+                "register" => &mut theme.register,
+                "literal" => &mut theme.literal,
+                "comment" => &mut theme.comment,
+                "memory" => &mut theme.memory,
+                "pc" => &mut theme.pc,
+                "address" => &mut theme.address,
+                "source" => &mut theme.source,
+                "dest" => &mut theme.dest,
+                "stop" => &mut theme.stop,
+                "error" => &mut theme.error,
+                "warning" => &mut theme.warning,
+                "dialog" => &mut theme.dialog,
+                "key_hint" => &mut theme.key_hint,
+                "muted" => &mut theme.muted,
+                _ => bail!("Unknown theme color {name:?}"),
+            };
+
+            *slot = color;
+        }
+
+        Ok(theme)
+    }
+}
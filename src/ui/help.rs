@@ -0,0 +1,250 @@
+use ratatui::{
+    buffer::Buffer,
+    layout::{Constraint, Layout, Rect},
+    style::{Style, Styled, Stylize},
+    symbols::border,
+    text::{Line, Text},
+    widgets::{Block, Clear, Paragraph, Widget},
+};
+
+use crate::util::{center, make_title, Action, Keymap, Theme};
+
+use Hint::{Bound, Fixed};
+
+/// How a key appears in the command bar: either whatever an action is bound
+/// to, or a key which can't be rebound.
+#[derive(Copy, Clone)]
+pub enum Hint {
+    Bound(Action),
+    Fixed(&'static str),
+}
+
+impl Hint {
+    pub fn label(self, keymap: &Keymap) -> String {
+        match self {
+            Hint::Bound(action) => keymap.get(action).to_string(),
+            Hint::Fixed(key) => key.to_string(),
+        }
+    }
+}
+
+pub type Keys = &'static [(Hint, &'static str)];
+
+pub const GLOBAL_KEYS: Keys = &[
+    (Bound(Action::Quit), "Quit"),
+    (Bound(Action::Window), "Window"),
+    (Bound(Action::Help), "Help"),
+];
+pub const PICKER_KEYS: Keys = &[
+    (Bound(Action::Quit), "Quit"),
+    (Bound(Action::Load), "Load"),
+    (Bound(Action::Save), "Save"),
+    (Bound(Action::Pipeline), "Pipeline"),
+    (Bound(Action::Theme), "Theme"),
+    (Bound(Action::Signed), "Signed"),
+    (Bound(Action::FixBranches), "Fix Branches"),
+    (Bound(Action::Encoding), "Hex"),
+    (Bound(Action::Stack), "Stack"),
+    (Bound(Action::Problems), "Problems"),
+    (Bound(Action::Record), "Record Macro"),
+    (Bound(Action::Replay), "Replay Macro"),
+];
+/// Shown on the windows themselves while picking, rather than in the bar
+pub const WINDOW_KEYS: Keys = &[
+    (Bound(Action::PickInstructions), "Instructions"),
+    (Bound(Action::PickRegisters), "Registers"),
+    (Bound(Action::PickMemory), "Memory"),
+];
+pub const INSTRUCTION_KEYS: Keys = &[
+    (Bound(Action::Step), "Run 1"),
+    (Bound(Action::Play), "Play"),
+    (Bound(Action::RunAll), "Run All"),
+    (Bound(Action::Burst), "Burst"),
+    (Fixed("<Up>"), "PC -= 4"),
+    (Fixed("<Down>"), "PC += 4"),
+    (Bound(Action::Edit), "Enter Edit Mode"),
+    (Bound(Action::Duplicate), "Duplicate"),
+    (Bound(Action::Delete), "Delete"),
+    (Bound(Action::ToggleComment), "Comment Out"),
+    (Bound(Action::Note), "Note"),
+    (Bound(Action::Snippet), "Template"),
+    (Bound(Action::Entry), "Start Here"),
+    (Bound(Action::Reset), "Start Over"),
+];
+/// `INSTRUCTION_KEYS` while an error or warning from running is shown
+pub const INSTRUCTION_ERROR_KEYS: Keys = &[
+    (Bound(Action::Step), "Run 1"),
+    (Bound(Action::Play), "Play"),
+    (Bound(Action::RunAll), "Run All"),
+    (Bound(Action::Burst), "Burst"),
+    (Fixed("<Up>"), "PC -= 4"),
+    (Fixed("<Down>"), "PC += 4"),
+    (Bound(Action::Edit), "Enter Edit Mode"),
+    (Bound(Action::Duplicate), "Duplicate"),
+    (Bound(Action::Delete), "Delete"),
+    (Bound(Action::ToggleComment), "Comment Out"),
+    (Bound(Action::Note), "Note"),
+    (Bound(Action::Snippet), "Template"),
+    (Bound(Action::Entry), "Start Here"),
+    (Bound(Action::Reset), "Start Over"),
+    (Fixed("<Esc>"), "Dismiss"),
+];
+/// `INSTRUCTION_KEYS` while running on a timer
+pub const INSTRUCTION_PLAYING_KEYS: Keys = &[
+    (Bound(Action::Play), "Pause"),
+    (Bound(Action::Faster), "Faster"),
+    (Bound(Action::Slower), "Slower"),
+];
+/// Picking a template to insert
+pub const SNIPPET_KEYS: Keys = &[
+    (Fixed("<Esc>"), "Cancel"),
+    (Fixed("<Enter>"), "Insert"),
+    (Fixed("<Up/Down>"), "Select"),
+];
+pub const INSTRUCTION_EDIT_KEYS: Keys = &[
+    (Fixed("<Esc>"), "Exit Edit Mode"),
+    (Fixed("<any key>"), "Edit"),
+];
+pub const REGISTER_KEYS: Keys = &[
+    (Fixed("<Arrow Key>"), "Pick"),
+    (Bound(Action::Edit), "Edit"),
+    (Bound(Action::WordSize), "32/64 Bit"),
+    (Bound(Action::Inspect), "Inspect"),
+];
+pub const MEMORY_KEYS: Keys = &[
+    (Bound(Action::Goto), "Goto Addr"),
+    (Bound(Action::GotoRegister), "Goto Reg"),
+    (Bound(Action::Follow), "Follow"),
+    (Bound(Action::Back), "Back"),
+    (Bound(Action::Edit), "Replace"),
+    (Bound(Action::Inspect), "Inspect"),
+    (Bound(Action::FillArray), "Fill Array"),
+    (Bound(Action::Watch), "Watch"),
+    (Bound(Action::Bookmark), "Bookmark"),
+    (Bound(Action::MoreContext), "More Context"),
+    (Bound(Action::LessContext), "Less Context"),
+    (Bound(Action::Heat), "Heat Map"),
+    (Bound(Action::ClearSlot), "Clear Slot"),
+    (Bound(Action::ClearAll), "Clear All"),
+    (Fixed("<Arrow Up/Down>"), "Navigate"),
+];
+pub const MEMORY_CLEAR_KEYS: Keys = &[(Fixed("<any key>"), "Cancel"), (Fixed("<Enter>"), "Clear")];
+pub const PIPELINE_KEYS: Keys = &[
+    (Bound(Action::Step), "Step Cycle"),
+    (Bound(Action::Restart), "Restart"),
+];
+pub const LOAD_TOGGLE_KEYS: Keys = &[
+    (Fixed("<Esc>"), "Cancel"),
+    (Fixed("<Enter>"), "Toggle"),
+    (Fixed("<arrow key>"), "Select"),
+];
+pub const LOAD_FILE_KEYS: Keys = &[
+    (Fixed("<Esc>"), "Cancel"),
+    (Fixed("<Enter>"), "Accept"),
+    (Fixed("<Tab>"), "Complete"),
+    (Fixed("<Up/Down>"), "Select"),
+];
+pub const LOAD_RECENT_KEYS: Keys = &[
+    (Fixed("<Esc>"), "Cancel"),
+    (Fixed("<Enter>"), "Pick"),
+    (Fixed("<Up/Down>"), "Select"),
+];
+pub const SAVE_TOGGLE_KEYS: Keys = &[
+    (Fixed("<Esc>"), "Cancel"),
+    (Fixed("<Enter>"), "Toggle"),
+    (Fixed("<Up/Down>"), "Select"),
+];
+/// `SaveUIState` asking before it overwrites a file
+pub const OVERWRITE_KEYS: Keys = &[(Fixed("<Y>"), "Overwrite"), (Fixed("<any key>"), "Cancel")];
+pub const CONFIRM_KEYS: Keys = &[(Fixed("<Esc>"), "Cancel"), (Fixed("<Enter>"), "Accept")];
+pub const DISMISS_KEYS: Keys = &[(Fixed("<any key>"), "Dismiss")];
+pub const ESC_DISMISS_KEYS: Keys = &[(Fixed("<Esc>"), "Dismiss")];
+pub const HELP_KEYS: Keys = &[(Fixed("<Esc>"), "Close Help")];
+pub const PROBLEMS_KEYS: Keys = &[
+    (Bound(Action::Lints), "Warnings"),
+    (Fixed("<Esc>"), "Close Problems"),
+];
+
+/// Every group of keys, with the modes a window can be in listed one after
+/// the other.
+const GROUPS: [(&str, &[Keys]); 8] = [
+    ("Anywhere", &[GLOBAL_KEYS]),
+    ("Picking a Window", &[WINDOW_KEYS, PICKER_KEYS]),
+    (
+        "Instructions",
+        &[
+            INSTRUCTION_ERROR_KEYS,
+            INSTRUCTION_PLAYING_KEYS,
+            INSTRUCTION_EDIT_KEYS,
+            CONFIRM_KEYS,
+            SNIPPET_KEYS,
+        ],
+    ),
+    ("Registers", &[REGISTER_KEYS, CONFIRM_KEYS]),
+    ("Memory", &[MEMORY_KEYS, CONFIRM_KEYS, MEMORY_CLEAR_KEYS]),
+    ("Pipeline", &[PIPELINE_KEYS]),
+    ("Save", &[SAVE_TOGGLE_KEYS, LOAD_FILE_KEYS, OVERWRITE_KEYS]),
+    (
+        "Load",
+        &[LOAD_TOGGLE_KEYS, LOAD_FILE_KEYS, LOAD_RECENT_KEYS],
+    ),
+];
+
+/// Every keybinding, grouped by the window it applies to.
+#[derive(Copy, Clone)]
+pub struct HelpUI<'a> {
+    pub theme: &'a Theme,
+    pub keymap: &'a Keymap,
+}
+
+impl HelpUI<'_> {
+    fn group(&self, name: &'static str, keys: &[Keys]) -> Vec<Line<'static>> {
+        let mut lines = vec![Line::from(name.bold().fg(self.theme.keyword))];
+
+        for &(key, expl) in keys.iter().copied().flatten() {
+            lines.push(Line::from(vec![
+                format!("  {:<18}", key.label(self.keymap))
+                    .fg(self.theme.key_hint)
+                    .bold(),
+                expl.into(),
+            ]));
+        }
+
+        lines.push(Line::default());
+
+        lines
+    }
+}
+
+impl Widget for HelpUI<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let title = make_title("Help", true, self.theme);
+
+        let area = center(area, Constraint::Percentage(80), Constraint::Percentage(80));
+
+        let block = Block::bordered()
+            .border_set(border::ROUNDED)
+            .set_style(Style::reset().fg(self.theme.dialog))
+            .title(title);
+
+        let inner = block.inner(area);
+
+        block.render(area, buf);
+
+        Clear.render(inner, buf);
+
+        // Two columns, so it all fits on a normal sized terminal
+        let (left, right) = GROUPS.split_at(GROUPS.len() / 2);
+
+        let columns = Layout::horizontal([Constraint::Fill(1), Constraint::Fill(1)]).split(inner);
+
+        for (groups, area) in [left, right].into_iter().zip(columns.iter()) {
+            let lines = groups
+                .iter()
+                .flat_map(|&(name, keys)| self.group(name, keys))
+                .collect::<Vec<_>>();
+
+            Paragraph::new(Text::from(lines)).render(*area, buf);
+        }
+    }
+}
@@ -1,17 +1,22 @@
-use color_eyre::eyre::Error;
+use std::{
+    cell::Cell,
+    collections::{BTreeMap, HashSet, VecDeque},
+};
+
 use ratatui::{
     buffer::Buffer,
-    layout::{Constraint, Layout, Rect},
+    layout::{Constraint, Layout, Position, Rect},
     style::Stylize,
     symbols::border,
     text::{Line, Text},
-    widgets::{Block, Paragraph, Widget},
+    widgets::{Block, Clear, Paragraph, Widget, Wrap},
 };
-use tui_textarea::{Input, Key, TextArea};
+use tui_textarea::{CursorMove, Input, Key, TextArea};
 
+use super::snippets::{fill_in, Snippet, SnippetsUI, SNIPPETS};
 use crate::{
-    simulator::{Instruction, Memory, Registers, Simulator},
-    util::make_title,
+    simulator::{Instruction, Memory, Registers, RunningState, Simulator, TickEffect},
+    util::{make_title, render_notice, Action, Keymap, Theme},
 };
 
 #[derive(Copy, Clone)]
@@ -20,27 +25,87 @@ pub struct InstructionUI<'a> {
     pub registers: &'a Registers,
     pub memory: &'a Memory,
     pub pc: u64,
+    pub word_bits: u8,
+    /// Also show values as signed numbers
+    pub signed: bool,
+    /// Show each instruction's machine code beside it
+    pub show_encoding: bool,
+    /// `(load, use)` pairs from `Simulator::detect_hazards`
+    pub hazards: &'a [(usize, usize)],
+    /// From `Simulator::reachable_instructions`, anything else is dimmed
+    pub reachable: &'a HashSet<usize>,
+    /// From `Simulator::notes`, shown in a column to the right
+    pub notes: &'a BTreeMap<usize, String>,
+    /// From `Simulator::labels`, shown in place of offsets to them
+    pub labels: &'a BTreeMap<usize, String>,
+    /// From `Simulator::entry`, whose line number is underlined
+    pub entry: u64,
+    /// From `Simulator::branches_to` for PC, listed on its line
+    pub branches_to_pc: &'a [u64],
+    /// From `PcHistory`, oldest first, shown along the bottom
+    pub history: &'a [u64],
+    pub theme: &'a Theme,
+    pub persistent: &'a PersistentInstructionState,
     pub state: Option<&'a InstructionUIState>,
 }
 
 impl Widget for InstructionUI<'_> {
     fn render(self, area: Rect, buf: &mut Buffer) {
         let title = if let Some(InstructionUIState { text: Some(_), .. }) = self.state {
-            make_title("Inserting", self.state.is_some())
+            make_title("Inserting", self.state.is_some(), self.theme)
         } else {
-            make_title("Instructions", self.state.is_some())
+            make_title("Instructions", self.state.is_some(), self.theme)
         };
 
-        let block = Block::bordered().title(title).border_set(border::ROUNDED);
+        let mut block = Block::bordered().title(title).border_set(border::ROUNDED);
+
+        if !self.history.is_empty() {
+            let crumbs = breadcrumb(self.history, area.width.saturating_sub(4) as usize);
+
+            block = block.title_bottom(format!(" {crumbs} ").fg(self.theme.muted));
+        }
 
         let idx_width = (self.instrs.len() as f32).log10().floor() as usize + 1;
+        // Eight hex digits and a space
+        let encoding_width = if self.show_encoding { 9 } else { 0 };
+
+        let label_at = |idx: u64| usize::try_from(idx).ok().and_then(|x| self.labels.get(&x));
 
         let mut lines = self
             .instrs
             .iter()
-            .map(Instruction::get_line)
+            .enumerate()
+            .map(|(idx, x)| {
+                let mut line = x.get_line(self.word_bits, self.theme);
+
+                // The offset is always the last operand of a branch
+                if let Some(label) = x.highlighted_instr(idx as u64).and_then(label_at) {
+                    let offset = line.last_mut().unwrap();
+                    offset.content = label.clone().into();
+                }
+
+                if let Some(label) = self.labels.get(&idx) {
+                    line.push(format!("  {label}:").fg(self.theme.muted));
+                }
+
+                line
+            })
             .collect::<Vec<_>>();
 
+        for (idx, line) in lines.iter_mut().enumerate() {
+            if !self.reachable.contains(&idx) {
+                for span in line {
+                    span.style = span.style.dim();
+                }
+            }
+        }
+
+        for idx in self.hazards.iter().flat_map(|&(load, usage)| [load, usage]) {
+            for span in &mut lines[idx] {
+                span.style = span.style.underlined().underline_color(self.theme.warning);
+            }
+        }
+
         for (idx, line) in lines.iter_mut().enumerate() {
             let line_string = format!("{idx:<width$} ", width = idx_width);
 
@@ -52,29 +117,69 @@ impl Widget for InstructionUI<'_> {
                     line.clear();
 
                     if text.lines()[0].parse::<Instruction>().is_err() {
-                        line_string.red()
+                        line_string.fg(self.theme.error)
                     } else {
-                        line_string.green()
+                        line_string.fg(self.theme.pc)
                     }
                 } else {
-                    line_string.green()
+                    line_string.fg(self.theme.pc)
                 }
             } else {
                 line_string.into()
             };
 
+            // Only worth pointing out when it isn't the top
+            let line_number = if self.entry != 0 && idx as u64 == self.entry {
+                line_number.underlined()
+            } else {
+                line_number
+            };
+
+            if self.show_encoding {
+                let editing = idx == self.pc as usize
+                    && matches!(self.state, Some(InstructionUIState { text: Some(_), .. }));
+
+                let encoding = match self.instrs[idx].encode() {
+                    Some(enc) if !editing => format!("{enc:08X} "),
+                    _ => " ".repeat(encoding_width),
+                };
+
+                line.insert(0, encoding.fg(self.theme.muted));
+            }
+
             line.insert(0, line_number);
         }
 
-        let height = block.inner(area).height as usize;
+        // Where PC ends up once the program has run off the end
+        lines.push(vec![
+            format!("{:<width$} ", "", width = idx_width + encoding_width).into(),
+            "END".fg(self.theme.muted).bold(),
+        ]);
 
-        while lines.len() > height {
-            lines.pop();
-        }
+        // Both ends of any branch through PC, so loops are easy to follow
+        if let (Some(line), None | Some(InstructionUIState { text: None, .. })) =
+            (lines.get_mut(self.pc as usize), self.state)
+        {
+            let target = self
+                .instrs
+                .get(self.pc as usize)
+                .and_then(|x| x.highlighted_instr(self.pc));
 
-        let lines = lines.into_iter().map(Line::from);
+            if let Some(target) = target {
+                line.push(format!("  → {target}").fg(self.theme.dest));
+            }
 
-        let text = Text::from(lines.collect::<Vec<_>>());
+            if !self.branches_to_pc.is_empty() {
+                let sources = self
+                    .branches_to_pc
+                    .iter()
+                    .map(|x| x.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+
+                line.push(format!("  ← {sources}").fg(self.theme.source));
+            }
+        }
 
         let instruction_to_explain = self.instrs.get(self.registers.pc as usize).map(|x| {
             if let Some(InstructionUIState {
@@ -93,7 +198,7 @@ impl Widget for InstructionUI<'_> {
         });
 
         let height_explanation = match &instruction_to_explain {
-            None => 0,
+            None => 3,
             Some(Ok(_)) => 4,
             Some(Err(e)) => 2 + e.lines().count(),
         };
@@ -106,11 +211,43 @@ impl Widget for InstructionUI<'_> {
             ])
             .split(block.inner(area));
 
+        self.persistent.list_area.set(vert_layout[0]);
+
+        let height = vert_layout[0].height as usize;
+
+        // One more than there are instructions, as PC can sit just past the end.
+        let scroll = self
+            .persistent
+            .update(self.pc as usize, self.instrs.len() + 1, height);
+
+        // Where an instruction is in the window, if it's in view at all.
+        let row_of = |idx: u64| {
+            let row = (idx as usize).checked_sub(scroll)?;
+
+            (row < height).then_some(row as u16)
+        };
+
+        let more_below = scroll + height < lines.len();
+
+        let lines = lines.into_iter().skip(scroll).take(height).map(Line::from);
+
+        let text = Text::from(lines.collect::<Vec<_>>());
+
+        // Only as wide as the longest note, leaving most of the room for code
+        let notes_width = self
+            .notes
+            .values()
+            .map(|x| x.chars().count() as u16 + 2)
+            .max()
+            .unwrap_or(0)
+            .min(vert_layout[0].width / 3);
+
         let instrs_layout = Layout::default()
             .direction(ratatui::layout::Direction::Horizontal)
             .constraints([
                 Constraint::Length(2), // selected instr, target instr
                 Constraint::Fill(1),
+                Constraint::Length(notes_width),
             ])
             .split(vert_layout[0]);
 
@@ -118,42 +255,102 @@ impl Widget for InstructionUI<'_> {
 
         Paragraph::new(text).render(instrs_layout[1], buf);
 
-        if let Some(instr_or_err) = instruction_to_explain {
-            let by_ref = instr_or_err.as_ref().map_err(|x| &**x);
-            let explanation = InstructionExplanation {
-                instr: by_ref,
-                registers: self.registers,
-                memory: self.memory,
-            };
+        if notes_width > 0 {
+            let notes = (scroll..(scroll + height).min(self.instrs.len() + 1))
+                .map(|idx| {
+                    let note = self.notes.get(&idx).map_or("", |x| x.as_str());
 
-            explanation.render(vert_layout[1], buf);
+                    Line::from(vec!["│ ".fg(self.theme.muted), note.fg(self.theme.comment)])
+                })
+                .collect::<Vec<_>>();
+
+            Paragraph::new(notes).render(instrs_layout[2], buf);
+        }
+
+        let by_ref = instruction_to_explain
+            .as_ref()
+            .map(|x| x.as_ref().map_err(|x| &**x));
+
+        let target = by_ref
+            .and_then(|x| x.ok())
+            .and_then(|x| x.highlighted_instr(self.registers.pc));
+
+        let explanation = InstructionExplanation {
+            instr: by_ref,
+            label: target.and_then(label_at).map(|x| x.as_str()),
+            registers: self.registers,
+            memory: self.memory,
+            word_bits: self.word_bits,
+            signed: self.signed,
+            theme: self.theme,
+        };
 
+        explanation.render(vert_layout[1], buf);
+
+        if let Some(by_ref) = by_ref {
             if let Some(target) = by_ref
                 .ok()
                 .and_then(|x| x.highlighted_instr(self.registers.pc))
+                .and_then(row_of)
             {
                 let target_pos = Layout::default()
                     .direction(ratatui::layout::Direction::Vertical)
                     .constraints([
-                        Constraint::Length(target as u16),
+                        Constraint::Length(target),
                         Constraint::Length(1),
                         Constraint::Fill(1),
                     ])
                     .split(instrs_layout[0])[1];
 
-                ">".cyan().bold().render(target_pos, buf);
+                ">".fg(self.theme.dest).bold().render(target_pos, buf);
+            }
+        }
+
+        // Links PC to the instruction before it, when it reads what that wrote
+        let previous = (self.registers.pc as usize)
+            .checked_sub(1)
+            .and_then(|x| self.instrs.get(x));
+
+        if let (Some(Ok(instr)), Some(previous)) = (by_ref, previous) {
+            let rows = row_of(self.registers.pc - 1).zip(row_of(self.registers.pc));
+
+            if let (true, Some((above, below))) = (instr.depends_on(previous), rows) {
+                let column = Layout::horizontal([Constraint::Length(1), Constraint::Length(1)])
+                    .areas::<2>(instrs_layout[0])[1];
+
+                for (row, glyph) in [(above, "┐"), (below, "┘")] {
+                    let place = Layout::vertical([
+                        Constraint::Length(row),
+                        Constraint::Length(1),
+                        Constraint::Fill(1),
+                    ])
+                    .areas::<3>(column)[1];
+
+                    glyph.fg(self.theme.muted).render(place, buf);
+                }
             }
         }
 
+        if scroll > 0 {
+            "▲".fg(self.theme.muted).render(instrs_layout[0], buf);
+        }
+
+        if more_below {
+            let last_row = Layout::vertical([Constraint::Fill(1), Constraint::Length(1)])
+                .areas::<2>(instrs_layout[0])[1];
+
+            "▼".fg(self.theme.muted).render(last_row, buf);
+        }
+
         let pc_pos = Layout::default()
             .direction(ratatui::layout::Direction::Vertical)
             .constraints([
-                Constraint::Length(self.registers.pc as u16),
+                Constraint::Length(row_of(self.registers.pc).unwrap_or(0)),
                 Constraint::Length(1),
                 Constraint::Fill(1),
             ]);
 
-        ">".green()
+        ">".fg(self.theme.pc)
             .bold()
             .render(pc_pos.areas::<3>(instrs_layout[0])[1], buf);
 
@@ -162,7 +359,7 @@ impl Widget for InstructionUI<'_> {
         }) = self.state
         {
             let idx_width = Layout::horizontal([
-                Constraint::Length(idx_width as u16 + 1),
+                Constraint::Length((idx_width + encoding_width) as u16 + 1),
                 Constraint::Fill(1),
             ]);
 
@@ -170,30 +367,255 @@ impl Widget for InstructionUI<'_> {
             let text = idx_width.areas::<2>(row)[1];
             area.render(text, buf);
         }
+
+        if let Some(note) = self.state.and_then(|x| x.note.as_ref()) {
+            let block = Block::bordered()
+                .fg(self.theme.dialog)
+                .title(" Note ")
+                .border_set(border::ROUNDED);
+
+            let bottom = Layout::vertical([Constraint::Fill(1), Constraint::Length(3)])
+                .areas::<2>(vert_layout[0])[1];
+
+            Clear.render(bottom, buf);
+            note.render(block.inner(bottom), buf);
+            block.render(bottom, buf);
+        }
+
+        if let Some(selected) = self.state.and_then(|x| x.snippet) {
+            let snippets = SnippetsUI {
+                selected,
+                theme: self.theme,
+            };
+
+            snippets.render(vert_layout[0], buf);
+        }
+
+        // Along the top, to stay out of the way of any watchpoint notice
+        if let Some(error) = self.state.and_then(|x| x.error.as_ref()) {
+            let width = vert_layout[0].width.saturating_sub(2).max(1);
+            let height = wrapped_rows(error, width) + 2;
+
+            let top = Layout::vertical([Constraint::Length(height), Constraint::Fill(1)])
+                .areas::<2>(vert_layout[0])[0];
+
+            let block = Block::bordered()
+                .fg(self.theme.error)
+                .title(" Error ")
+                .border_set(border::ROUNDED);
+
+            Clear.render(top, buf);
+
+            Paragraph::new(error.as_str())
+                .wrap(Wrap { trim: true })
+                .block(block)
+                .render(top, buf);
+        }
+
+        if let Some(warning) = self.state.and_then(|x| x.warning.as_ref()) {
+            render_notice(
+                " Warning ",
+                warning,
+                self.theme.warning,
+                vert_layout[0],
+                buf,
+            );
+        }
+
+        if let Some(message) = self.state.and_then(|x| x.message.as_ref()) {
+            render_notice(
+                " Watchpoint ",
+                message,
+                self.theme.warning,
+                vert_layout[0],
+                buf,
+            );
+        }
+    }
+}
+
+fn insert(state: &mut Simulator, idx: usize, instr: Instruction, fix_branches: bool) {
+    if fix_branches {
+        state.insert_instruction(idx, instr);
+    } else {
+        state.insert_line(idx, instr);
+    }
+}
+
+fn remove(state: &mut Simulator, idx: usize, fix_branches: bool) {
+    if fix_branches {
+        state.remove_instruction(idx);
+    } else {
+        state.remove_line(idx);
+    }
+}
+
+/// What the user typed, or a comment holding it if it doesn't parse.
+fn parse_or_comment(line: &str) -> Instruction {
+    line.trim()
+        .parse()
+        .unwrap_or_else(|_| Instruction::Comment(line.to_string()))
+}
+
+/// Roughly how many rows `text` takes up once word wrapped to `width`.
+fn wrapped_rows(text: &str, width: u16) -> u16 {
+    let mut rows = 1;
+    let mut column = 0;
+
+    for word in text.split_whitespace() {
+        let len = word.chars().count() as u16;
+
+        if column > 0 && column + 1 + len > width {
+            rows += 1;
+            column = 0;
+        }
+
+        if column > 0 {
+            column += 1;
+        }
+
+        // Words too long for a row are broken up
+        column += len;
+        rows += column.saturating_sub(1) / width;
+        column = (column.saturating_sub(1) % width) + 1;
+    }
+
+    rows
+}
+
+/// `history` as `0 → 1 → 2`, leaving off the oldest with `…` to fit in
+/// `width`.
+fn breadcrumb(history: &[u64], width: usize) -> String {
+    let join = |pcs: &[u64]| {
+        pcs.iter()
+            .map(|pc| pc.to_string())
+            .collect::<Vec<_>>()
+            .join(" → ")
+    };
+
+    (0..history.len())
+        .map(|start| match start {
+            0 => join(history),
+            _ => format!("… → {}", join(&history[start..])),
+        })
+        .find(|crumbs| crumbs.chars().count() <= width)
+        .unwrap_or_default()
+}
+
+/// The last few instructions run, oldest first, to trace the way through
+/// loops. Kept by `Tui`, as `InstructionUIState` doesn't last.
+pub struct PcHistory {
+    pcs: VecDeque<u64>,
+    /// How many are kept
+    pub limit: usize,
+}
+
+impl PcHistory {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            pcs: VecDeque::new(),
+            limit,
+        }
+    }
+
+    /// Remembers the instruction at `pc` ran, forgetting the oldest past
+    /// `limit`.
+    pub fn push(&mut self, pc: u64) {
+        self.pcs.push_back(pc);
+
+        while self.pcs.len() > self.limit {
+            self.pcs.pop_front();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.pcs.clear();
+    }
+
+    /// Oldest first.
+    pub fn pcs(&self) -> Vec<u64> {
+        self.pcs.iter().copied().collect()
+    }
+}
+
+/// What needs to outlive `InstructionUIState`, which is remade every time
+/// the instructions are focused.
+pub struct PersistentInstructionState {
+    /// Where the instructions were last drawn
+    list_area: Cell<Rect>,
+    /// How many instructions are hidden above the window
+    scroll_dist: Cell<usize>,
+}
+
+impl PersistentInstructionState {
+    pub fn new() -> Self {
+        Self {
+            list_area: Cell::new(Rect::default()),
+            scroll_dist: Cell::new(0),
+        }
+    }
+
+    /// Scrolls so `selected` is centered, or as close as it can be without
+    /// leaving space at either end, and returns the new scroll distance.
+    fn update(&self, selected: usize, len: usize, height: usize) -> usize {
+        let scroll = selected
+            .saturating_sub(height / 2)
+            .min(len.saturating_sub(height));
+
+        self.scroll_dist.set(scroll);
+
+        scroll
+    }
+
+    /// The index of the instruction drawn at a position on screen. May be
+    /// past the end of the program.
+    pub fn instruction_at(&self, position: Position) -> Option<u64> {
+        let area = self.list_area.get();
+
+        area.contains(position)
+            .then(|| (position.y - area.y) as u64 + self.scroll_dist.get() as u64)
     }
 }
 
 #[derive(Copy, Clone)]
 struct InstructionExplanation<'a> {
-    instr: Result<&'a Instruction, &'a str>,
+    /// `None` once PC has run off the end of the program
+    instr: Option<Result<&'a Instruction, &'a str>>,
+    /// The label a branch goes to, if it has one
+    label: Option<&'a str>,
     registers: &'a Registers,
     memory: &'a Memory,
+    word_bits: u8,
+    signed: bool,
+    theme: &'a Theme,
 }
 
 impl<'a> Widget for InstructionExplanation<'a> {
     fn render(self, area: Rect, buf: &mut Buffer) {
-        let title = make_title("Explanation", false);
+        let title = make_title("Explanation", false, self.theme);
 
         let block = Block::bordered().title(title).border_set(border::ROUNDED);
 
         let text = match self.instr {
-            Ok(instr) => Text::from(vec![
-                Line::from(instr.explain_unsub()),
-                Line::from(instr.explain_sub(self.registers, self.memory)),
+            None => Text::from("Program finished".fg(self.theme.stop).bold()),
+            Some(Ok(instr)) => Text::from(vec![
+                Line::from_iter(
+                    instr
+                        .explain_unsub(self.word_bits, self.theme)
+                        .into_iter()
+                        .chain(self.label.map(|x| format!(" ({x})").fg(self.theme.literal))),
+                ),
+                Line::from(instr.explain_sub(
+                    self.registers,
+                    self.memory,
+                    self.word_bits,
+                    self.signed,
+                    self.theme,
+                )),
             ]),
-            Err(t) => Text::from(
+            Some(Err(t)) => Text::from(
                 t.lines()
-                    .map(|x| Line::from(x.red().bold()))
+                    .map(|x| Line::from(x.fg(self.theme.error).bold()))
                     .collect::<Vec<_>>(),
             ),
         };
@@ -205,14 +627,60 @@ impl<'a> Widget for InstructionExplanation<'a> {
 pub struct InstructionUIState {
     // no need for selected instruction -- this is just PC
     pub text: Option<TextArea<'static>>,
-    pub prev_err: Option<Error>,
+    /// Why the last step failed, kept until a step succeeds or it's dismissed
+    pub error: Option<String>,
+    pub message: Option<String>,
+    /// Something suspicious about the last run, kept until it's dismissed
+    pub warning: Option<String>,
+    /// Typing a note for the instruction at PC
+    pub note: Option<TextArea<'static>>,
+    /// Picking a template to insert, and which one
+    pub snippet: Option<usize>,
 }
 
 impl InstructionUIState {
     pub fn new() -> Self {
         Self {
             text: None,
-            prev_err: None,
+            error: None,
+            message: None,
+            warning: None,
+            note: None,
+            snippet: None,
+        }
+    }
+
+    /// Whether keys are going into an instruction, a note or the templates.
+    pub fn is_typing(&self) -> bool {
+        self.text.is_some() || self.note.is_some() || self.snippet.is_some()
+    }
+
+    /// Inserts `snippet` at PC, and starts editing its first placeholder.
+    fn insert_snippet(&mut self, snippet: &Snippet, state: &mut Simulator, fix_branches: bool) {
+        let pc = (state.registers.pc as usize).min(state.instructions.len());
+        let mut first = None;
+
+        for (i, line) in snippet.text.lines().enumerate() {
+            let (line, placeholder) = fill_in(line);
+
+            insert(state, pc + i, parse_or_comment(&line), fix_branches);
+
+            if let (None, Some(column)) = (&first, placeholder) {
+                first = Some((i, line, column));
+            }
+        }
+
+        state.registers.pc = pc as u64;
+
+        // Left as written, rather than how it's displayed, so the cursor
+        // lines up with the placeholder
+        if let Some((i, line, column)) = first {
+            state.registers.pc += i as u64;
+
+            let mut area = TextArea::new(vec![line]);
+            area.move_cursor(CursorMove::Jump(0, column as u16));
+
+            self.text = Some(area);
         }
     }
 
@@ -220,14 +688,7 @@ impl InstructionUIState {
     fn try_set_line(&mut self, state: &mut Simulator) -> usize {
         let area = self.text.take().unwrap();
 
-        let instr = area.lines()[0].trim().parse::<Instruction>();
-
-        if let Ok(instr) = instr {
-            state.instructions[state.registers.pc as usize] = instr;
-        } else {
-            state.instructions[state.registers.pc as usize] =
-                Instruction::Comment(area.lines()[0].clone());
-        }
+        state.instructions[state.registers.pc as usize] = parse_or_comment(&area.lines()[0]);
 
         area.cursor().1
     }
@@ -253,12 +714,244 @@ impl InstructionUIState {
         area
     }
 
-    pub fn handle(&mut self, input: Input, state: &mut Simulator) {
-        if self.text.is_none() {
+    /// Inserts each line of `text` as its own instruction at PC. While
+    /// editing, it goes in at the cursor, as if it had been typed.
+    pub fn paste(&mut self, text: &str, state: &mut Simulator, fix_branches: bool) {
+        // Notes are a single line
+        if let Some(area) = &mut self.note {
+            area.insert_str(text.lines().collect::<Vec<_>>().join(" "));
+            return;
+        }
+
+        let pc = (state.registers.pc as usize).min(state.instructions.len());
+
+        // Some terminals paste lines ending in just `\r`
+        let text = text.replace("\r\n", "\n").replace('\r', "\n");
+
+        let Some(area) = &mut self.text else {
+            // Left at the first pasted line, ready to step through
+            for (i, line) in text.lines().enumerate() {
+                insert(state, pc + i, parse_or_comment(line), fix_branches);
+            }
+
+            state.registers.pc = pc as u64;
+
+            return;
+        };
+
+        let line = &area.lines()[0];
+        let cursor = line
+            .char_indices()
+            .nth(area.cursor().1)
+            .map_or(line.len(), |(i, _)| i);
+
+        let (before, after) = line.split_at(cursor);
+        let combined = format!("{before}{}{after}", text);
+
+        let mut lines = combined.split('\n').collect::<Vec<_>>();
+        let last = lines.pop().unwrap().to_string();
+
+        // Like pressing enter, the line being edited is split, with what
+        // comes after the cursor staying below the new lines
+        for (i, line) in lines.iter().enumerate() {
+            if i == 0 {
+                state.instructions[pc] = parse_or_comment(line);
+            } else {
+                insert(state, pc + i, parse_or_comment(line), fix_branches);
+            }
+        }
+
+        if !lines.is_empty() {
+            insert(state, pc + lines.len(), Instruction::None, fix_branches);
+        }
+
+        state.registers.pc = (pc + lines.len()) as u64;
+
+        let mut area = TextArea::new(vec![last.clone()]);
+        area.move_cursor(tui_textarea::CursorMove::Jump(
+            0,
+            (last.chars().count() - after.chars().count()) as u16,
+        ));
+
+        self.text = Some(area);
+    }
+
+    /// Runs the instruction at PC, returning whether there's any reason to
+    /// stop there, like a `HALT`, an error or a watchpoint.
+    pub fn step(
+        &mut self,
+        state: &mut Simulator,
+        last_effect: &mut TickEffect,
+        history: &mut PcHistory,
+    ) -> bool {
+        let pc = state.registers.pc;
+
+        if !state.is_finished() {
+            history.push(pc);
+        }
+
+        let result = state.step();
+
+        self.error = None;
+        *last_effect = result.as_ref().map_or(TickEffect::None, |x| x.effect);
+
+        if let Some(reg) = result.as_ref().ok().and_then(|x| x.unwritten_read) {
+            self.warning = Some(format!(
+                "Instruction {pc} read {} before anything wrote to it",
+                state.profile.name(reg, state.word_bits)
+            ));
+        }
+
+        if let TickEffect::RegWrite { reg, .. } = *last_effect {
+            if let Some(owner) = state.profile.reserved_for(reg) {
+                self.warning = Some(format!(
+                    "Instruction {pc} wrote to {}, which is reserved for {owner}",
+                    state.profile.name(reg, state.word_bits)
+                ));
+            }
+        }
+
+        // Always allowed, but almost always meant for another register
+        if let TickEffect::RegWrite { reg: 31, .. } = *last_effect {
+            let zero = if state.word_bits == 32 { "WZR" } else { "XZR" };
+
+            self.warning = Some(format!(
+                "Instruction {pc} wrote to {zero}, which always reads as zero, so the result was lost"
+            ));
+        }
+
+        match result.map(|x| x.running) {
+            Ok(RunningState::KeepRunning) => false,
+            Ok(RunningState::Watchpoint { addr, old, new }) => {
+                self.message = Some(format!(
+                    "Instruction {pc} changed M[{addr}] from {old} to {new}"
+                ));
+
+                true
+            }
+            Ok(RunningState::ShouldStop) => true,
+            Err(e) => {
+                self.error = Some(format!("At PC = {}: {e}", pc * 4));
+
+                true
+            }
+        }
+    }
+
+    /// Steps until the program stops, or `limit` instructions have run,
+    /// returning how many did and whether it stopped.
+    pub fn run_for(
+        &mut self,
+        state: &mut Simulator,
+        last_effect: &mut TickEffect,
+        history: &mut PcHistory,
+        limit: usize,
+    ) -> (usize, bool) {
+        self.warning = None;
+
+        for ran in 0..limit {
+            if state.is_finished() {
+                return (ran, true);
+            }
+
+            if self.step(state, last_effect, history) {
+                return (ran + 1, true);
+            }
+        }
+
+        (limit, state.is_finished())
+    }
+
+    /// `run_for`, but hitting the limit leaves a warning, as the program is
+    /// probably stuck in a loop.
+    pub fn run_all(
+        &mut self,
+        state: &mut Simulator,
+        last_effect: &mut TickEffect,
+        history: &mut PcHistory,
+        limit: usize,
+    ) -> (usize, bool) {
+        let (ran, stopped) = self.run_for(state, last_effect, history, limit);
+
+        if !stopped {
+            self.warning = Some(format!(
+                "Still going after {limit} instructions, stuck in a loop?"
+            ));
+        }
+
+        (ran, stopped)
+    }
+
+    /// With `fix_branches`, branches over added or removed lines are changed
+    /// to still land where they did before. Stepping leaves what it changed
+    /// in `last_effect`.
+    pub fn handle(
+        &mut self,
+        input: Input,
+        state: &mut Simulator,
+        keymap: &Keymap,
+        fix_branches: bool,
+        last_effect: &mut TickEffect,
+        history: &mut PcHistory,
+    ) {
+        // Messages only last until the next key press
+        self.message = None;
+
+        if let Some(selected) = &mut self.snippet {
+            match input.key {
+                Key::Esc => self.snippet = None,
+                Key::Up => *selected = selected.saturating_sub(1),
+                Key::Down => *selected = (*selected + 1).min(SNIPPETS.len() - 1),
+                Key::Enter => {
+                    let snippet = &SNIPPETS[*selected];
+
+                    self.snippet = None;
+                    self.insert_snippet(snippet, state, fix_branches);
+                }
+                _ => {}
+            }
+
+            return;
+        }
+
+        if let Some(area) = &mut self.note {
+            let pc = state.registers.pc as usize;
+
             match input {
+                Input { key: Key::Esc, .. } => self.note = None,
                 Input {
                     key: Key::Enter, ..
-                } => self.prev_err = state.tick().err(),
+                } => {
+                    let note = area.lines()[0].trim().to_string();
+
+                    if note.is_empty() {
+                        state.notes.remove(&pc);
+                    } else {
+                        state.notes.insert(pc, note);
+                    }
+
+                    self.note = None;
+                }
+                input => {
+                    area.input(input);
+                }
+            }
+
+            return;
+        }
+
+        if self.text.is_none() {
+            match input {
+                input if keymap.is(Action::Step, &input) => {
+                    self.step(state, last_effect, history);
+                }
+
+                Input { key: Key::Esc, .. } if self.error.is_some() => {
+                    self.error = None;
+                }
+                Input { key: Key::Esc, .. } if self.warning.is_some() => {
+                    self.warning = None;
+                }
 
                 Input { key: Key::Up, .. } => {
                     state.registers.pc = state.registers.pc.saturating_sub(1);
@@ -271,12 +964,60 @@ impl InstructionUIState {
                         as u64;
                 }
 
-                Input {
-                    key: Key::Char('r'),
-                    ctrl: true,
-                    ..
-                } => {
-                    if state.registers.pc == state.instructions.len() as u64 {
+                input if keymap.is(Action::Duplicate, &input) => {
+                    let pc = state.registers.pc as usize;
+
+                    if let Some(instr) = state.instructions.get(pc).cloned() {
+                        insert(state, pc + 1, instr, fix_branches);
+                        state.registers.pc += 1;
+                    }
+                }
+
+                input if keymap.is(Action::Delete, &input) => {
+                    let pc = state.registers.pc as usize;
+
+                    if pc < state.instructions.len() {
+                        remove(state, pc, fix_branches);
+                    }
+                }
+
+                input if keymap.is(Action::ToggleComment, &input) => {
+                    if let Some(instr) = state.instructions.get_mut(state.registers.pc as usize) {
+                        *instr = instr.toggle_comment();
+                    }
+                }
+
+                input if keymap.is(Action::Snippet, &input) => {
+                    self.snippet = Some(0);
+                }
+
+                input if keymap.is(Action::Entry, &input) => {
+                    let pc = state.registers.pc;
+
+                    if state.entry == pc {
+                        state.entry = 0;
+                    } else if pc < state.instructions.len() as u64 {
+                        state.entry = pc;
+                    }
+                }
+
+                input if keymap.is(Action::Note, &input) => {
+                    let pc = state.registers.pc as usize;
+
+                    if pc < state.instructions.len() {
+                        let note = state.notes.get(&pc).cloned().unwrap_or_default();
+                        let mut area = TextArea::new(vec![note]);
+                        area.move_cursor(CursorMove::End);
+
+                        self.note = Some(area);
+                    }
+                }
+
+                input if keymap.is(Action::Edit, &input) => {
+                    // PC may have run (or been set) past the end of the
+                    // program, in which case we start a new line at the end.
+                    if state.registers.pc >= state.instructions.len() as u64 {
+                        state.registers.pc = state.instructions.len() as u64;
                         state.instructions.push(Instruction::None);
                     }
 
@@ -312,7 +1053,7 @@ impl InstructionUIState {
             }
 
             Input { key: Key::Down, .. } => {
-                if state.registers.pc as usize == state.instructions.len() - 1 {
+                if state.registers.pc as usize + 1 >= state.instructions.len() {
                     return;
                 }
 
@@ -343,7 +1084,7 @@ impl InstructionUIState {
                 .map(|x| x.cursor().1 == x.lines()[0].len())
                 .unwrap() =>
             {
-                if state.registers.pc == state.instructions.len() as u64 - 1 {
+                if state.registers.pc as usize + 1 >= state.instructions.len() {
                     return;
                 }
 
@@ -364,22 +1105,15 @@ impl InstructionUIState {
                     ..Default::default()
                 });
 
-                let first_line = &*text.lines()[0];
-                let instr = first_line.parse::<Instruction>();
-                let instr = if let Ok(i) = instr {
-                    i
-                } else {
-                    Instruction::Comment(first_line.to_string())
-                };
+                let pc = state.registers.pc as usize;
 
-                state
-                    .instructions
-                    .insert(state.registers.pc as usize, instr);
+                // The line is split in two, with a new line for whatever was
+                // after the cursor
+                state.instructions[pc] = parse_or_comment(&text.lines()[0]);
+                insert(state, pc + 1, Instruction::None, fix_branches);
 
                 state.registers.pc += 1;
 
-                state.instructions[state.registers.pc as usize] = Instruction::None;
-
                 let new_line = text.lines()[1].clone();
 
                 *text = TextArea::new(vec![new_line]);
@@ -395,7 +1129,7 @@ impl InstructionUIState {
 
                 let text = self.text.as_mut().unwrap();
 
-                state.instructions.remove(state.registers.pc as usize);
+                remove(state, state.registers.pc as usize, fix_branches);
 
                 state.registers.pc -= 1;
 
@@ -1,34 +1,49 @@
 use ratatui::{
     buffer::Buffer,
     layout::{Constraint, Layout, Rect},
-    style::{Color, Style, Styled as _, Stylize as _},
+    style::{Style, Styled as _, Stylize as _},
     symbols::border,
-    text::{Line, Span},
+    text::{Line, Span, Text},
     widgets::{Block, Clear, Widget},
 };
-use tui_textarea::{Input, Key, TextArea};
+use tui_textarea::{CursorMove, Input, Key, TextArea};
 
-use serde_json::{from_str, to_string_pretty};
+use serde_json::{from_str, to_string, to_string_pretty};
 
 use crate::{
     simulator::Simulator,
-    util::{center, make_title},
+    util::{center, make_title, Theme},
 };
 
 pub struct SaveUIState {
+    /// Write JSON without any whitespace, rather than pretty-printed
+    pub compact: bool,
     pub area: TextArea<'static>,
     pub message: Option<String>,
+    /// `message` is asking whether to overwrite the file, not an error
+    pub confirm_overwrite: bool,
+    pub focus: SaveFocus,
+    pub theme: Theme,
+    /// Whether the file was written, rather than the dialog being cancelled
+    pub saved: bool,
+    completion: PathCompletion,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SaveFocus {
+    Compact,
+    File,
 }
 
 impl Widget for &SaveUIState {
     fn render(self, area: Rect, buf: &mut Buffer) {
-        let title = make_title("Save To File", true);
+        let title = make_title("Save To File", true, &self.theme);
 
-        let area = center(area, Constraint::Percentage(75), Constraint::Length(3));
+        let area = center(area, Constraint::Percentage(75), Constraint::Length(4));
 
         let block = Block::bordered()
             .border_set(border::ROUNDED)
-            .set_style(Style::reset().fg(Color::Cyan))
+            .set_style(Style::reset().fg(self.theme.dialog))
             .title(title);
 
         let inner = block.inner(area);
@@ -37,29 +52,98 @@ impl Widget for &SaveUIState {
 
         Clear.render(inner, buf);
 
+        let rows =
+            Layout::vertical([Constraint::Length(1), Constraint::Length(1)]).areas::<2>(inner);
+
         if let Some(x) = &self.message {
-            x.clone().red().render(inner, buf);
-        } else {
-            let message = "File: ";
-            let areas =
-                Layout::horizontal([Constraint::Length(6), Constraint::Fill(1)]).areas::<2>(inner);
+            let color = if self.confirm_overwrite {
+                self.theme.warning
+            } else {
+                self.theme.error
+            };
 
-            message.render(areas[0], buf);
+            x.clone().fg(color).render(rows[0], buf);
+            return;
+        }
+
+        let mut toggle_line = Vec::new();
+        make_toggle(
+            "Compact: ",
+            self.focus == SaveFocus::Compact,
+            self.compact,
+            &mut toggle_line,
+        );
+
+        Line::from(toggle_line).render(rows[0], buf);
+
+        let message = "File: ";
+        let areas =
+            Layout::horizontal([Constraint::Length(6), Constraint::Fill(1)]).areas::<2>(rows[1]);
+
+        if self.focus == SaveFocus::File {
+            message.underlined().render(areas[0], buf);
 
             self.area.render(areas[1], buf);
+        } else {
+            message.render(areas[0], buf);
+
+            self.area.lines()[0].clone().render(areas[1], buf);
         }
     }
 }
 
 impl SaveUIState {
-    pub fn new() -> Self {
+    pub fn new(theme: Theme) -> Self {
         Self {
+            compact: false,
             area: TextArea::default(),
             message: None,
+            confirm_overwrite: false,
+            focus: SaveFocus::File,
+            theme,
+            saved: false,
+            completion: PathCompletion::default(),
+        }
+    }
+
+    /// Writes `simulator` to the file typed in, returning whether it worked.
+    fn write(&mut self, simulator: &Simulator) -> bool {
+        let path = &self.area.lines()[0];
+
+        let to_write = if self.compact {
+            to_string(simulator)
+        } else {
+            to_string_pretty(simulator)
+        };
+
+        let to_write = match to_write {
+            Ok(x) => x,
+            Err(e) => {
+                self.message = Some(format!("{}", e));
+                return false;
+            }
+        };
+
+        let err = std::fs::write(path, to_write);
+
+        if let Err(e) = err {
+            self.message = Some(format!("{}", e));
+            false
+        } else {
+            self.saved = true;
+            true
         }
     }
 
     pub fn handle(&mut self, event: Input, simulator: &Simulator) -> bool {
+        if self.confirm_overwrite {
+            self.confirm_overwrite = false;
+            self.message = None;
+
+            // Anything other than yes goes back to picking a file
+            return matches!(event.key, Key::Char('y' | 'Y')) && self.write(simulator);
+        }
+
         if event.key == Key::Esc {
             return true;
         }
@@ -68,30 +152,46 @@ impl SaveUIState {
             return false;
         }
 
+        if event.key == Key::Enter && self.focus == SaveFocus::Compact {
+            self.compact = !self.compact;
+            return false;
+        }
+
         if event.key == Key::Enter {
             let path = &self.area.lines()[0];
 
-            let to_write = to_string_pretty(simulator);
-
-            let to_write = match to_write {
-                Ok(x) => x,
-                Err(e) => {
-                    self.message = Some(format!("{}", e));
-                    return false;
-                }
-            };
-
-            let err = std::fs::write(path, to_write);
+            if std::path::Path::new(path).exists() {
+                self.message = Some(format!("Overwrite {path}? y/n"));
+                self.confirm_overwrite = true;
 
-            if let Err(e) = err {
-                self.message = Some(format!("{}", e));
                 return false;
-            } else {
-                return true;
             }
+
+            return self.write(simulator);
         }
 
-        self.area.input(event);
+        match event {
+            Input {
+                key: Key::Up | Key::Down,
+                ..
+            } => {
+                self.focus = match self.focus {
+                    SaveFocus::File => SaveFocus::Compact,
+                    SaveFocus::Compact => SaveFocus::File,
+                };
+            }
+
+            Input { key: Key::Tab, .. } if self.focus == SaveFocus::File => {
+                self.completion.complete(&mut self.area);
+            }
+
+            input if self.focus == SaveFocus::File => {
+                self.completion = PathCompletion::default();
+                self.area.input(input);
+            }
+
+            _ => {}
+        }
 
         false
     }
@@ -104,6 +204,12 @@ pub struct LoadUIState {
     pub area: TextArea<'static>,
     pub message: Option<String>,
     pub focus: LoadFocus,
+    pub theme: Theme,
+    /// Whether a file was loaded, rather than the dialog being cancelled
+    pub loaded: bool,
+    /// From `Preferences::recent_files`, listed under the file to pick from
+    pub recent_files: Vec<String>,
+    completion: PathCompletion,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -112,17 +218,56 @@ pub enum LoadFocus {
     Mem,
     Instr,
     File,
+    /// An index into `recent_files`
+    Recent(usize),
+}
+
+/// How many of a loaded program's problems to list before giving up.
+const MAX_PROBLEMS: usize = 5;
+
+/// Lists the problems `Simulator::validate_program` found, one per line.
+fn problems_message(problems: &[(usize, String)]) -> String {
+    let mut message = format!(
+        "Not loading, as the program has {} problem(s):",
+        problems.len()
+    );
+
+    for (idx, problem) in problems.iter().take(MAX_PROBLEMS) {
+        message += &format!("\n  {idx}: {problem}");
+    }
+
+    if problems.len() > MAX_PROBLEMS {
+        message += &format!("\n  ...and {} more", problems.len() - MAX_PROBLEMS);
+    }
+
+    message
 }
 
 impl Widget for &LoadUIState {
     fn render(self, area: Rect, buf: &mut Buffer) {
-        let title = make_title("Load From File", true);
-
-        let area = center(area, Constraint::Percentage(75), Constraint::Length(4));
+        let title = make_title("Load From File", true, &self.theme);
+
+        // A heading and a row for each recent file, if there are any
+        let recent_rows = match self.recent_files.len() {
+            0 => 0,
+            len => len as u16 + 1,
+        };
+
+        // Problems with a loaded program take a row each
+        let message_rows = self
+            .message
+            .as_ref()
+            .map_or(0, |x| x.lines().count() as u16);
+
+        let area = center(
+            area,
+            Constraint::Percentage(75),
+            Constraint::Length((4 + recent_rows).max(2 + message_rows)),
+        );
 
         let block = Block::bordered()
             .border_set(border::ROUNDED)
-            .set_style(Style::reset().fg(Color::Cyan))
+            .set_style(Style::reset().fg(self.theme.dialog))
             .title(title);
 
         let inner = block.inner(area);
@@ -131,14 +276,36 @@ impl Widget for &LoadUIState {
 
         Clear.render(inner, buf);
 
-        let rows =
-            Layout::vertical([Constraint::Length(1), Constraint::Length(1)]).areas::<2>(inner);
+        let rows = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Length(1),
+            Constraint::Fill(1),
+        ])
+        .areas::<3>(inner);
 
         if let Some(x) = &self.message {
-            x.clone().red().render(rows[0], buf);
+            Text::from(x.clone())
+                .fg(self.theme.error)
+                .render(inner, buf);
             return;
         }
 
+        if !self.recent_files.is_empty() {
+            let lines = std::iter::once(Line::from("Recent:".fg(self.theme.muted)))
+                .chain(self.recent_files.iter().enumerate().map(|(idx, path)| {
+                    let path = format!("  {path}");
+
+                    if self.focus == LoadFocus::Recent(idx) {
+                        Line::from(path.underlined())
+                    } else {
+                        Line::from(path)
+                    }
+                }))
+                .collect::<Vec<_>>();
+
+            Text::from(lines).render(rows[2], buf);
+        }
+
         let mut toggle_line = Vec::new();
         make_toggle(
             "Registers: ",
@@ -184,7 +351,7 @@ impl Widget for &LoadUIState {
 }
 
 impl LoadUIState {
-    pub fn new() -> Self {
+    pub fn new(theme: Theme, recent_files: Vec<String>) -> Self {
         Self {
             load_reg: true,
             load_mem: true,
@@ -192,9 +359,22 @@ impl LoadUIState {
             area: TextArea::default(),
             message: None,
             focus: LoadFocus::File,
+            theme,
+            loaded: false,
+            recent_files,
+            completion: PathCompletion::default(),
         }
     }
 
+    /// Whether one of the registers, memory or instructions toggles is
+    /// selected.
+    fn on_toggles(&self) -> bool {
+        matches!(
+            self.focus,
+            LoadFocus::Reg | LoadFocus::Mem | LoadFocus::Instr
+        )
+    }
+
     pub fn handle(&mut self, event: Input, state: &mut Simulator) -> bool {
         if event.key == Key::Esc {
             return true;
@@ -210,6 +390,11 @@ impl LoadUIState {
                 LoadFocus::Mem => self.load_mem = !self.load_mem,
                 LoadFocus::Instr => self.load_instr = !self.load_instr,
 
+                LoadFocus::Recent(idx) => {
+                    set_path(&mut self.area, &self.recent_files[idx]);
+                    self.focus = LoadFocus::File;
+                }
+
                 LoadFocus::File => {
                     let loaded = std::fs::read_to_string(&self.area.lines()[0]);
 
@@ -223,7 +408,7 @@ impl LoadUIState {
 
                     let deserialized = from_str::<Simulator>(&loaded);
 
-                    let deserialized = match deserialized {
+                    let mut deserialized = match deserialized {
                         Ok(x) => x,
                         Err(e) => {
                             self.message = Some(format!("{}", e));
@@ -231,61 +416,124 @@ impl LoadUIState {
                         }
                     };
 
+                    if let Err(e) = deserialized.migrate() {
+                        self.message = Some(format!("{}", e));
+                        return false;
+                    }
+
+                    // Better to refuse now than fail partway through a run
+                    if self.load_instr {
+                        if let Err(problems) = deserialized.validate_program() {
+                            self.message = Some(problems_message(&problems));
+                            return false;
+                        }
+                    }
+
                     let Simulator {
+                        version: _,
                         registers,
                         memory,
                         instructions,
+                        word_bits,
+                        profile,
+                        cycles,
+                        watchpoints,
+                        notes,
+                        labels,
+                        entry,
+                        accesses: _,
+                        written: _,
+                        read_unwritten: _,
                     } = deserialized;
 
+                    // What the old program did means nothing in the new one
+                    state.forget_run();
+
                     if self.load_reg {
                         state.registers = registers;
+                        state.cycles = cycles;
                     }
 
                     if self.load_mem {
                         state.memory = memory;
+                        state.watchpoints = watchpoints;
                     }
 
                     if self.load_instr {
                         state.instructions = instructions;
+                        state.word_bits = word_bits;
+                        state.profile = profile;
+                        state.notes = notes;
+                        state.labels = labels;
+                        state.entry = entry;
+
+                        state.reset_pc();
                     }
 
+                    // Registers and instructions from different programs
+                    // can leave PC past the end
+                    state.registers.pc = state.registers.pc.min(state.instructions.len() as u64);
+
+                    self.loaded = true;
+
                     return true;
                 }
             }
         }
 
         match event {
-            Input {
-                key: Key::Up | Key::Down,
-                ..
-            } => {
+            // Toggles, then the file, then each recent file, wrapping around
+            Input { key: Key::Down, .. } => {
                 self.focus = match self.focus {
-                    LoadFocus::File => LoadFocus::Reg,
                     LoadFocus::Reg | LoadFocus::Mem | LoadFocus::Instr => LoadFocus::File,
+                    LoadFocus::File if !self.recent_files.is_empty() => LoadFocus::Recent(0),
+                    LoadFocus::Recent(idx) if idx + 1 < self.recent_files.len() => {
+                        LoadFocus::Recent(idx + 1)
+                    }
+                    LoadFocus::File | LoadFocus::Recent(_) => LoadFocus::Reg,
                 };
             }
 
-            Input { key: Key::Left, .. } if self.focus != LoadFocus::File => {
+            Input { key: Key::Up, .. } => {
+                self.focus = match self.focus {
+                    LoadFocus::Reg | LoadFocus::Mem | LoadFocus::Instr => {
+                        match self.recent_files.len() {
+                            0 => LoadFocus::File,
+                            len => LoadFocus::Recent(len - 1),
+                        }
+                    }
+                    LoadFocus::File => LoadFocus::Reg,
+                    LoadFocus::Recent(0) => LoadFocus::File,
+                    LoadFocus::Recent(idx) => LoadFocus::Recent(idx - 1),
+                };
+            }
+
+            Input { key: Key::Left, .. } if self.on_toggles() => {
                 self.focus = match self.focus {
                     LoadFocus::Reg => LoadFocus::Instr,
                     LoadFocus::Mem => LoadFocus::Reg,
                     LoadFocus::Instr => LoadFocus::Mem,
-                    LoadFocus::File => unreachable!(),
+                    LoadFocus::File | LoadFocus::Recent(_) => unreachable!(),
                 };
             }
 
             Input {
                 key: Key::Right, ..
-            } if self.focus != LoadFocus::File => {
+            } if self.on_toggles() => {
                 self.focus = match self.focus {
                     LoadFocus::Reg => LoadFocus::Mem,
                     LoadFocus::Mem => LoadFocus::Instr,
                     LoadFocus::Instr => LoadFocus::Reg,
-                    LoadFocus::File => unreachable!(),
+                    LoadFocus::File | LoadFocus::Recent(_) => unreachable!(),
                 };
             }
 
+            Input { key: Key::Tab, .. } if self.focus == LoadFocus::File => {
+                self.completion.complete(&mut self.area);
+            }
+
             input if self.focus == LoadFocus::File => {
+                self.completion = PathCompletion::default();
                 self.area.input(input);
             }
 
@@ -311,3 +559,104 @@ fn make_toggle(name: &'static str, selected: bool, toggled: bool, into: &mut Vec
         into.push(boxed.into());
     }
 }
+
+/// Shell-style `<Tab>` completion for the path typed into a dialog. The
+/// first `<Tab>` fills in as much as every match has in common, and each one
+/// after that shows the next match, until something else is typed and this
+/// is reset.
+#[derive(Default)]
+struct PathCompletion {
+    /// Every match for what was typed, and which to show next
+    cycle: Option<(Vec<String>, usize)>,
+}
+
+impl PathCompletion {
+    /// Completes the path in `area`, for a press of `<Tab>`.
+    fn complete(&mut self, area: &mut TextArea<'static>) {
+        if let Some((matches, next)) = &mut self.cycle {
+            set_path(area, &matches[*next]);
+            *next = (*next + 1) % matches.len();
+
+            return;
+        }
+
+        let typed = area.lines()[0].clone();
+        let matches = path_matches(&typed);
+
+        let Some(first) = matches.first() else {
+            return;
+        };
+
+        let common = common_prefix(&matches);
+
+        // With nothing more in common, go straight to cycling
+        let next = if common.len() > typed.len() {
+            set_path(area, common);
+            0
+        } else {
+            set_path(area, first);
+            1 % matches.len()
+        };
+
+        if matches.len() > 1 {
+            self.cycle = Some((matches, next));
+        }
+    }
+}
+
+fn set_path(area: &mut TextArea<'static>, path: &str) {
+    *area = TextArea::new(vec![path.to_string()]);
+    area.move_cursor(CursorMove::End);
+}
+
+/// Every file and directory starting with what comes after the last
+/// separator in `typed`, in order, with directories ending in a separator.
+/// Hidden files only match once a `.` has been typed. Anything which can't
+/// be read just doesn't match.
+fn path_matches(typed: &str) -> Vec<String> {
+    let (dir, prefix) = match typed.rfind(std::path::is_separator) {
+        Some(idx) => typed.split_at(idx + 1),
+        None => ("", typed),
+    };
+
+    let Ok(entries) = std::fs::read_dir(if dir.is_empty() { "." } else { dir }) else {
+        return Vec::new();
+    };
+
+    let mut matches = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+
+            let separator = if entry.path().is_dir() {
+                std::path::MAIN_SEPARATOR_STR
+            } else {
+                ""
+            };
+
+            Some(format!("{dir}{name}{separator}"))
+        })
+        .collect::<Vec<_>>();
+
+    matches.sort();
+    matches
+}
+
+/// The longest string every one of `strings` starts with.
+fn common_prefix(strings: &[String]) -> &str {
+    let first = &strings[0];
+
+    let len = strings[1..].iter().fold(first.len(), |len, x| {
+        first[..len]
+            .char_indices()
+            .zip(x.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(len.min(x.len()), |((idx, _), _)| idx)
+    });
+
+    &first[..len]
+}
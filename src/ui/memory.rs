@@ -1,32 +1,73 @@
-use std::cell::Cell;
+use std::{
+    cell::{Cell, RefCell},
+    collections::{BTreeMap, BTreeSet},
+};
 
 use ratatui::{
     buffer::Buffer,
-    layout::{Constraint, Layout, Rect},
-    style::Stylize,
+    layout::{Constraint, Layout, Position, Rect},
+    style::{Color, Style, Styled as _, Stylize},
     symbols::border,
     text::{Line, Text},
-    widgets::{block::Title, Block, Widget},
+    widgets::{block::Title, Block, Clear, Widget},
 };
 use tui_textarea::{Input, Key, TextArea};
 
 use crate::{
-    simulator::{Highlight, Instruction, Memory, Registers},
-    util::{get_ranges, make_title},
+    simulator::{
+        format_word, register_name, Accesses, Instruction, Memory, Registers, TickEffect, SP,
+    },
+    util::{
+        center, get_ranges, highlight_change, make_title, render_message, render_value, Action,
+        Keymap, Theme,
+    },
 };
 
 #[derive(Copy, Clone)]
 pub struct MemoryUI<'a> {
     pub memory: &'a Memory,
+    pub watchpoints: &'a BTreeSet<u64>,
+    /// From `Simulator::accesses`, shown while `heat` is on
+    pub accesses: &'a BTreeMap<u64, Accesses>,
     pub instrs: &'a [Instruction],
     pub registers: &'a Registers,
+    /// Also show values as signed numbers
+    pub signed: bool,
+    /// Whatever this changed is highlighted
+    pub last_effect: TickEffect,
+    pub theme: &'a Theme,
     pub state: Option<&'a MemoryUIState>,
     pub persistent: &'a PersistentMemoryState,
 }
 
+impl MemoryUI<'_> {
+    fn value(&self, addr: u64) -> String {
+        format_word(self.memory.get(addr).unwrap(), 64, self.signed)
+    }
+
+    /// Slots never used are muted, and the rest go from cool to hot in
+    /// thirds of the most any slot has been used.
+    fn heat_color(&self, total: u64, hottest: u64) -> Color {
+        if total == 0 {
+            self.theme.muted
+        } else if total * 3 <= hottest {
+            self.theme.source
+        } else if total * 3 <= hottest * 2 {
+            self.theme.warning
+        } else {
+            self.theme.error
+        }
+    }
+}
+
 impl Widget for MemoryUI<'_> {
     fn render(self, area: Rect, buf: &mut Buffer) {
-        let title = make_title("Memory", self.state.is_some());
+        let name = if self.persistent.heat {
+            "Memory (Heat)"
+        } else {
+            "Memory"
+        };
+        let title = make_title(name, self.state.is_some(), self.theme);
 
         let block = Block::bordered().title(title).border_set(border::ROUNDED);
 
@@ -37,18 +78,40 @@ impl Widget for MemoryUI<'_> {
 
         let interaction_idx = mem_interaction.map(|(x, _)| x);
 
+        let changed_idx = match self.last_effect {
+            TickEffect::MemWrite { addr, .. } => Some(addr / 8),
+            _ => None,
+        };
+
         let selected_idx = self
             .state
             .map(|x| x.selected)
             .unwrap_or(self.persistent.selected.get() as u64);
 
+        // Slots which have only been read are still zero, but worth showing
+        let heat_slots = if self.persistent.heat {
+            Some(self.accesses.keys().copied())
+        } else {
+            None
+        };
+
+        let hottest = self.accesses.values().map(Accesses::total).max();
+
         let to_view = get_ranges(
             &self.memory,
-            1,
-            interaction_idx.into_iter().chain([selected_idx]),
+            self.persistent.context,
+            interaction_idx
+                .into_iter()
+                .chain(changed_idx)
+                .chain([selected_idx])
+                .chain(self.watchpoints.iter().map(|x| x / 8))
+                .chain(self.persistent.bookmarks.keys().copied())
+                .chain(heat_slots.into_iter().flatten()),
         );
 
         let mut lines = Vec::new();
+        // The slot shown on each line, if any
+        let mut slots = Vec::new();
 
         let max_height = block.inner(area).height as usize;
 
@@ -70,36 +133,79 @@ impl Widget for MemoryUI<'_> {
 
                     if self.state.and_then(|x| x.insertion.as_ref()).is_some() {
                         lines.push(Line::from(vec![
-                            format!("{:<5}", addr).bold().red().underlined(),
+                            format!("{:<5}", addr)
+                                .bold()
+                                .fg(self.theme.address)
+                                .underlined(),
                             format!(": ").underlined(),
                         ]));
                     } else {
                         if self.state.is_some() {
                             lines.push(Line::from(vec![
-                                format!("{:<5}", addr).bold().red().underlined(),
-                                format!(": {}", self.memory.get(addr).unwrap()).underlined(),
+                                format!("{:<5}", addr)
+                                    .bold()
+                                    .fg(self.theme.address)
+                                    .underlined(),
+                                format!(": {}", self.value(addr)).underlined(),
                             ]));
                         } else {
                             lines.push(Line::from(vec![
-                                format!("{:<5}", addr).bold().red(),
-                                format!(": {}", self.memory.get(addr).unwrap()).into(),
+                                format!("{:<5}", addr).bold().fg(self.theme.address),
+                                format!(": {}", self.value(addr)).into(),
                             ]));
                         }
                     }
                 } else {
                     lines.push(Line::from(vec![
-                        format!("{:<5}", addr).bold().red(),
-                        format!(": {}", self.memory.get(addr).unwrap()).into(),
+                        format!("{:<5}", addr).bold().fg(self.theme.address),
+                        format!(": {}", self.value(addr)).into(),
                     ]));
                 }
+
+                if self.persistent.heat {
+                    let count = self.accesses.get(&x).copied().unwrap_or_default();
+                    let color = self.heat_color(count.total(), hottest.unwrap_or(0));
+                    let line = lines.last_mut().unwrap();
+
+                    if let Some(value) = line.spans.get_mut(1) {
+                        value.style = value.style.fg(color);
+                    }
+
+                    if count.total() != 0 {
+                        line.push_span(format!(" {}r {}w", count.reads, count.writes).fg(color));
+                    }
+                }
+
+                if Some(x) == changed_idx {
+                    highlight_change(lines.last_mut().unwrap(), self.theme);
+                }
+
+                if self.watchpoints.contains(&addr) {
+                    lines
+                        .last_mut()
+                        .unwrap()
+                        .push_span(" ◆".fg(self.theme.warning).bold());
+                }
+
+                if let Some(label) = self.persistent.bookmarks.get(&x) {
+                    lines
+                        .last_mut()
+                        .unwrap()
+                        .push_span(format!(" ★ {label}").fg(self.theme.keyword));
+                }
+
+                slots.push(Some(x));
             }
             lines.push(separator.clone());
+            slots.push(None);
         }
 
         lines.pop();
+        slots.pop();
 
         if lines.is_empty() {
             lines.push(Line::from(vec!["(all zeros)".into()]));
+            slots.push(None);
         }
 
         self.persistent.update(
@@ -116,6 +222,8 @@ impl Widget for MemoryUI<'_> {
 
         let lines = &lines[to_remove..];
 
+        let mut visible_slots = slots[to_remove..to_remove + to_include].to_vec();
+
         let line_1;
         let mut line_2 = Vec::new();
 
@@ -124,7 +232,8 @@ impl Widget for MemoryUI<'_> {
             line_2 = lines[max_height..to_include].to_owned();
             if to_include < lines.len() {
                 line_2.pop();
-                line_2.push(Line::from(vec!["-- Extra Below --".green()]));
+                line_2.push(Line::from(vec!["-- Extra Below --".fg(self.theme.muted)]));
+                *visible_slots.last_mut().unwrap() = None;
             }
         } else {
             line_1 = lines.to_owned();
@@ -145,6 +254,9 @@ impl Widget for MemoryUI<'_> {
             ])
             .split(inner);
 
+        self.persistent.columns.set([layout[1], layout[3]]);
+        self.persistent.rows.replace(visible_slots);
+
         block.render(area, buf);
         text_left.render(layout[1], buf);
         text_right.render(layout[3], buf);
@@ -179,10 +291,7 @@ impl Widget for MemoryUI<'_> {
             if line_idx < to_include {
                 let (_, highlight) = mem_interaction.unwrap();
 
-                let span = match highlight {
-                    Highlight::Source => "<".green().bold(),
-                    Highlight::Dest => ">".cyan().bold(),
-                };
+                let span = highlight.marker(self.theme);
 
                 let area_layout = Layout::vertical([
                     Constraint::Length((line_idx % max_height) as u16),
@@ -198,10 +307,21 @@ impl Widget for MemoryUI<'_> {
             }
         }
 
-        if let Some(input_area) = self.state.and_then(|x| x.line_selection.as_ref()) {
-            let title = Title::from(" Goto ");
+        let prompt = self.state.and_then(|x| {
+            [
+                (" Goto ", &x.line_selection),
+                (" Goto Register ", &x.register_selection),
+                (" Array ", &x.array_insertion),
+                (" Bookmark Label ", &x.bookmark_label),
+            ]
+            .into_iter()
+            .find_map(|(title, area)| area.as_ref().map(|area| (title, area)))
+        });
+
+        if let Some((title, input_area)) = prompt {
+            let title = Title::from(title);
             let block = Block::bordered()
-                .cyan()
+                .fg(self.theme.dialog)
                 .title(title)
                 .border_set(border::ROUNDED);
 
@@ -214,39 +334,125 @@ impl Widget for MemoryUI<'_> {
 
             input_area.render(new_inner, buf);
         }
+
+        if let Some(MemoryUIState {
+            selected,
+            inspecting: true,
+            ..
+        }) = self.state
+        {
+            let value = self.memory.get(selected.wrapping_mul(8)).unwrap();
+
+            render_value(value, 64, self.theme, inner, buf);
+        }
+
+        if let Some(message) = self.state.and_then(|x| x.message.as_ref()) {
+            render_message(message, self.theme, inner, buf);
+        }
+
+        if let Some(MemoryUIState {
+            confirm_clear: true,
+            ..
+        }) = self.state
+        {
+            let area = center(inner, Constraint::Percentage(75), Constraint::Length(3));
+
+            let block = Block::bordered()
+                .border_set(border::ROUNDED)
+                .set_style(Style::reset().fg(self.theme.dialog))
+                .title(make_title("Clear Memory", true, self.theme));
+
+            let prompt_area = block.inner(area);
+
+            block.render(area, buf);
+
+            Clear.render(prompt_area, buf);
+
+            "Set every slot to zero?".render(prompt_area, buf);
+        }
     }
 }
 
 pub struct MemoryUIState {
     selected: u64,
+    /// Where each `Follow` came from, most recent last
+    history: Vec<u64>,
     pub insertion: Option<TextArea<'static>>,
     pub line_selection: Option<TextArea<'static>>,
+    /// Typing a register, to go to the slot it points at
+    pub register_selection: Option<TextArea<'static>>,
+    /// Typing several values, to write from the selected slot on
+    pub array_insertion: Option<TextArea<'static>>,
+    /// Typing a label for a new bookmark on the selected slot
+    pub bookmark_label: Option<TextArea<'static>>,
+    pub message: Option<String>,
+    /// Waiting for confirmation before clearing all of memory
+    pub confirm_clear: bool,
+    /// Showing the selected slot in every base
+    pub inspecting: bool,
 }
 
 impl MemoryUIState {
     pub fn new(selected: usize) -> Self {
         Self {
             selected: selected as u64,
+            history: Vec::new(),
             insertion: None,
             line_selection: None,
+            register_selection: None,
+            array_insertion: None,
+            bookmark_label: None,
+            message: None,
+            confirm_clear: false,
+            inspecting: false,
         }
     }
 
-    pub fn handle(&mut self, input: Input, memory: &mut Memory) {
+    pub fn handle(
+        &mut self,
+        input: Input,
+        memory: &mut Memory,
+        watchpoints: &mut BTreeSet<u64>,
+        persistent: &mut PersistentMemoryState,
+        registers: &Registers,
+        keymap: &Keymap,
+    ) {
+        // Messages only last until the next key press
+        self.message = None;
+
+        if self.confirm_clear {
+            self.confirm_clear = false;
+
+            if input.key == Key::Enter {
+                memory.clear();
+            }
+
+            return;
+        }
+
         match input {
             Input { key: Key::Esc, .. } => {
                 self.insertion = None;
                 self.line_selection = None;
+                self.register_selection = None;
+                self.array_insertion = None;
+                self.bookmark_label = None;
+                self.inspecting = false;
             }
 
             Input {
                 key: Key::Enter, ..
             } if self.insertion.is_some() => {
                 let area = self.insertion.take().unwrap();
-                let text = area.lines()[0].parse::<i128>();
+                let text = area.lines()[0].trim();
 
-                if let Ok(val) = text {
-                    memory.set(self.selected * 8, val as u64).unwrap();
+                match parse_value(text) {
+                    Ok(val) => {
+                        if let Err(e) = memory.set(self.selected * 8, val) {
+                            self.message = Some(e.to_string());
+                        }
+                    }
+                    Err(reason) => self.message = Some(format!("{text:?} is {reason}!")),
                 }
             }
 
@@ -254,13 +460,84 @@ impl MemoryUIState {
                 key: Key::Enter, ..
             } if self.line_selection.is_some() => {
                 let area = self.line_selection.take().unwrap();
-                let text = area.lines()[0].parse::<i128>();
+                let text = area.lines()[0].trim();
 
-                if let Ok(val) = text {
-                    self.selected = (val / 8) as u64;
+                match text.parse::<i128>() {
+                    Ok(val) if val < 0 || val > u64::MAX as i128 => {
+                        self.message = Some(format!("Byte address {val} is out of range!"));
+                    }
+                    Ok(val) if val % 8 != 0 => {
+                        self.message = Some(format!("Byte address {val} is not a multiple of 8!"));
+                    }
+                    Ok(val) => self.selected = (val / 8) as u64,
+                    Err(_) => self.message = Some(format!("{text:?} is not an address!")),
                 }
             }
 
+            Input {
+                key: Key::Enter, ..
+            } if self.register_selection.is_some() => {
+                let area = self.register_selection.take().unwrap();
+                let text = area.lines()[0].trim();
+
+                let Some(reg) = parse_register(text) else {
+                    self.message = Some(format!("{text:?} is not a register!"));
+                    return;
+                };
+
+                let val = registers.get(reg).unwrap();
+
+                if !val.is_multiple_of(8) {
+                    self.message = Some(format!(
+                        "{} = {val} is not a multiple of 8!",
+                        register_name(reg, 64)
+                    ));
+                } else {
+                    self.selected = val / 8;
+                }
+            }
+
+            Input {
+                key: Key::Enter, ..
+            } if self.array_insertion.is_some() => {
+                let area = self.array_insertion.take().unwrap();
+
+                let tokens = area.lines()[0]
+                    .split(|c: char| c == ',' || c.is_whitespace())
+                    .filter(|x| !x.is_empty());
+
+                let mut vals = Vec::new();
+
+                for token in tokens {
+                    match parse_value(token) {
+                        Ok(val) => vals.push(val),
+                        Err(reason) => {
+                            self.message = Some(format!("{token:?} is {reason}!"));
+                            return;
+                        }
+                    }
+                }
+
+                for (i, val) in vals.into_iter().enumerate() {
+                    let slot = self.selected.wrapping_add(i as u64);
+
+                    if let Err(e) = memory.set(slot.wrapping_mul(8), val) {
+                        self.message = Some(e.to_string());
+                        return;
+                    }
+                }
+            }
+
+            Input {
+                key: Key::Enter, ..
+            } if self.bookmark_label.is_some() => {
+                let area = self.bookmark_label.take().unwrap();
+
+                persistent
+                    .bookmarks
+                    .insert(self.selected, area.lines()[0].trim().to_string());
+            }
+
             input if self.insertion.is_some() => {
                 self.insertion.as_mut().unwrap().input(input);
             }
@@ -269,33 +546,136 @@ impl MemoryUIState {
                 self.line_selection.as_mut().unwrap().input(input);
             }
 
+            input if self.register_selection.is_some() => {
+                self.register_selection.as_mut().unwrap().input(input);
+            }
+
+            input if self.array_insertion.is_some() => {
+                self.array_insertion.as_mut().unwrap().input(input);
+            }
+
+            input if self.bookmark_label.is_some() => {
+                self.bookmark_label.as_mut().unwrap().input(input);
+            }
+
             Input { key: Key::Up, .. } => self.selected = self.selected.saturating_sub(1),
 
             Input { key: Key::Down, .. } => self.selected = self.selected.wrapping_add(1),
 
-            Input {
-                key: Key::Char('g'),
-                ..
-            } => {
+            input if keymap.is(Action::Goto, &input) => {
                 self.line_selection = Some(TextArea::default());
             }
 
-            Input {
-                key: Key::Char('r'),
-                ctrl: true,
-                ..
-            } => {
+            input if keymap.is(Action::GotoRegister, &input) => {
+                self.register_selection = Some(TextArea::default());
+            }
+
+            input if keymap.is(Action::Follow, &input) => {
+                let val = memory.get(self.selected.wrapping_mul(8)).unwrap();
+
+                if !val.is_multiple_of(8) {
+                    self.message = Some(format!("{val} is not a multiple of 8!"));
+                } else {
+                    self.history.push(self.selected);
+                    self.selected = val / 8;
+                }
+            }
+
+            input if keymap.is(Action::Back, &input) => match self.history.pop() {
+                Some(selected) => self.selected = selected,
+                None => self.message = Some("Nothing to go back to!".to_string()),
+            },
+
+            input if keymap.is(Action::MoreContext, &input) => {
+                persistent.context = (persistent.context + 1).min(MAX_CONTEXT);
+            }
+
+            input if keymap.is(Action::LessContext, &input) => {
+                persistent.context = persistent.context.saturating_sub(1);
+            }
+
+            input if keymap.is(Action::Heat, &input) => persistent.heat = !persistent.heat,
+
+            input if keymap.is(Action::ClearSlot, &input) => {
+                if let Err(e) = memory.set(self.selected.wrapping_mul(8), 0) {
+                    self.message = Some(e.to_string());
+                }
+            }
+
+            input if keymap.is(Action::ClearAll, &input) => self.confirm_clear = true,
+
+            input if keymap.is(Action::Watch, &input) => {
+                let addr = self.selected.wrapping_mul(8);
+
+                if !watchpoints.remove(&addr) {
+                    watchpoints.insert(addr);
+                }
+            }
+
+            input if keymap.is(Action::Edit, &input) => {
                 self.insertion = Some(TextArea::default());
             }
 
+            input if keymap.is(Action::Inspect, &input) => self.inspecting = !self.inspecting,
+
+            input if keymap.is(Action::FillArray, &input) => {
+                self.array_insertion = Some(TextArea::default());
+            }
+
+            input if keymap.is(Action::Bookmark, &input) => {
+                if persistent.bookmarks.remove(&self.selected).is_none() {
+                    self.bookmark_label = Some(TextArea::default());
+                }
+            }
+
             _ => {}
         }
     }
 }
 
+/// A value typed into a slot. Negative numbers down to `i64::MIN` are stored
+/// in two's complement, and positive ones go up to `u64::MAX`.
+fn parse_value(text: &str) -> Result<u64, &'static str> {
+    let val = text.parse::<i128>().map_err(|_| "not a number")?;
+
+    if val < i64::MIN as i128 || val > u64::MAX as i128 {
+        return Err("out of range");
+    }
+
+    Ok(val as u64)
+}
+
+/// `X5`, `5`, `SP` or `XZR`, as a register number.
+fn parse_register(text: &str) -> Option<u8> {
+    let text = text.to_uppercase();
+
+    match text.as_str() {
+        "SP" => Some(SP),
+        "XZR" | "WZR" => Some(31),
+        _ => {
+            let number = text.strip_prefix(['X', 'W']).unwrap_or(&text);
+
+            number.parse().ok().filter(|&x| x < 32)
+        }
+    }
+}
+
+/// Past this, the gaps between used slots would hardly ever be skipped.
+pub const MAX_CONTEXT: u64 = 16;
+
 pub struct PersistentMemoryState {
     scroll_dist: Cell<usize>,
     pub selected: Cell<usize>,
+    /// Where the two columns of slots were last drawn
+    columns: Cell<[Rect; 2]>,
+    /// The slot on each line drawn, left column first
+    rows: RefCell<Vec<Option<u64>>>,
+    /// Slots which are always shown, and their labels
+    pub bookmarks: BTreeMap<u64, String>,
+    /// How many slots either side of each one in use are shown
+    pub context: u64,
+    /// Whether slots are colored by how often they've been used
+    pub heat: bool,
 }
 
 impl PersistentMemoryState {
@@ -303,9 +683,29 @@ impl PersistentMemoryState {
         Self {
             scroll_dist: Cell::new(0),
             selected: Cell::new(0),
+            columns: Cell::new([Rect::default(); 2]),
+            rows: RefCell::new(Vec::new()),
+            bookmarks: BTreeMap::new(),
+            context: 1,
+            heat: false,
         }
     }
 
+    /// The slot drawn at a position on screen, if any.
+    pub fn slot_at(&self, position: Position) -> Option<u64> {
+        let [left, right] = self.columns.get();
+
+        let idx = if left.contains(position) {
+            position.y - left.y
+        } else if right.contains(position) {
+            left.height + position.y - right.y
+        } else {
+            return None;
+        };
+
+        self.rows.borrow().get(idx as usize).copied().flatten()
+    }
+
     pub fn update(
         &self,
         max_height: usize,
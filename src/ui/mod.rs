@@ -1,29 +1,68 @@
+use std::{
+    panic,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
 use color_eyre::eyre::Result;
 use ratatui::{
-    crossterm::event::{self, Event, KeyEvent, KeyEventKind},
-    layout::{Constraint, Layout},
+    crossterm::{
+        event::{
+            self, DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste,
+            EnableMouseCapture, Event, KeyEvent, KeyEventKind, MouseButton, MouseEvent,
+            MouseEventKind,
+        },
+        execute,
+    },
+    layout::{Constraint, Layout, Position, Rect},
     style::Stylize,
     text::{Line, Span},
     DefaultTerminal, Frame,
 };
 use tui_textarea::{Input, Key};
 
-use crate::simulator::Simulator;
+use crate::{
+    simulator::{Simulator, TickEffect},
+    util::{remember_file, Action, Keymap, Preferences, Theme},
+};
 
 mod registers;
 use registers::{RegisterUI, RegisterUIState};
 
 mod memory;
-use memory::{MemoryUI, MemoryUIState, PersistentMemoryState};
+use memory::{MemoryUI, MemoryUIState, PersistentMemoryState, MAX_CONTEXT};
 
 mod instruction;
-use instruction::{InstructionUI, InstructionUIState};
+use instruction::{InstructionUI, InstructionUIState, PcHistory, PersistentInstructionState};
 
 mod picker;
 use picker::Picker;
 
 mod io;
-use io::{LoadFocus, LoadUIState, SaveUIState};
+use io::{LoadFocus, LoadUIState, SaveFocus, SaveUIState};
+
+mod pipeline;
+use pipeline::{PipelineUI, PipelineUIState};
+
+mod stack;
+use stack::StackUI;
+
+mod help;
+use help::{
+    HelpUI, CONFIRM_KEYS, DISMISS_KEYS, ESC_DISMISS_KEYS, GLOBAL_KEYS, HELP_KEYS,
+    INSTRUCTION_EDIT_KEYS, INSTRUCTION_ERROR_KEYS, INSTRUCTION_KEYS, INSTRUCTION_PLAYING_KEYS,
+    LOAD_FILE_KEYS, LOAD_RECENT_KEYS, LOAD_TOGGLE_KEYS, MEMORY_CLEAR_KEYS, MEMORY_KEYS,
+    OVERWRITE_KEYS, PICKER_KEYS, PIPELINE_KEYS, PROBLEMS_KEYS, REGISTER_KEYS, SAVE_TOGGLE_KEYS,
+    SNIPPET_KEYS,
+};
+
+mod problems;
+use problems::ProblemsUI;
+
+mod snippets;
+
+#[cfg(test)]
+mod tests;
 
 enum Focus {
     Memory(MemoryUIState),
@@ -31,33 +70,157 @@ enum Focus {
     Instructions(InstructionUIState),
     Save(SaveUIState),
     Load(LoadUIState),
+    Pipeline(PipelineUIState),
 }
 
+/// The most instructions a second playing can run.
+const MAX_SPEED: u32 = 20;
+
+/// The most instructions running to the end can run, like `run --max-iters`.
+const MAX_ITERS: usize = 1000;
+
+/// The most instructions `PcHistory` can remember.
+const MAX_HISTORY: usize = 100;
+
 pub struct Tui {
     focus: Focus,
     running: bool,
     picking: bool,
+    /// Whether the help overlay is open
+    help: bool,
+    /// Whether the problems overlay is open
+    problems: bool,
+    /// Whether values are also shown as signed numbers
+    signed: bool,
+    /// Whether editing instructions keeps branches pointing where they were
+    fix_branches: bool,
+    /// Whether each instruction's machine code is shown next to it
+    show_encoding: bool,
+    /// Whether memory is split in two, with the stack around `SP` beside it
+    show_stack: bool,
+    /// Whether the problems overlay also lists `lint_skipped_setup`
+    lints: bool,
+    /// Files last loaded or saved, most recent first
+    recent_files: Vec<String>,
     state: Simulator,
+    /// What the last step changed, highlighted until the next one
+    last_effect: TickEffect,
+    /// Whether instructions are being run on a timer
+    playing: bool,
+    /// Instructions a second while playing
+    speed: u32,
+    /// When playing runs the next instruction
+    next_step: Instant,
+    /// How many instructions the last run to the end or burst ran, out of
+    /// how many it could have, and whether the program stopped by itself
+    ran: Option<(usize, usize, bool)>,
+    /// Instructions run at once by `Action::Burst`
+    burst: usize,
+    /// Where PC has been lately, cleared by `forget_run`
+    history: PcHistory,
+    /// Every key pressed since recording started, if it has
+    recording: Option<Vec<Input>>,
+    /// The last recording, pressed again on request
+    recorded: Vec<Input>,
+    /// Whether the keys being handled are from `recorded`
+    replaying: bool,
 
     persistent_memory: PersistentMemoryState,
+    persistent_instructions: PersistentInstructionState,
+    /// Where the registers and memory were last drawn, for the mouse
+    registers_area: Rect,
+    memory_area: Rect,
+
+    theme: Theme,
+    /// Swapped with `theme` on request
+    other_theme: Theme,
+    keymap: Keymap,
 }
 
 impl Tui {
-    pub fn new(state: Simulator) -> Self {
+    pub fn new(state: Simulator, theme: Theme, keymap: Keymap) -> Self {
+        let other_theme = if theme == Theme::light() {
+            Theme::dark()
+        } else {
+            Theme::light()
+        };
+
         Self {
             running: true,
             picking: false,
+            help: false,
+            problems: false,
+            signed: false,
+            fix_branches: true,
+            show_encoding: false,
+            show_stack: false,
+            lints: false,
+            recent_files: Vec::new(),
             focus: Focus::Instructions(InstructionUIState::new()),
             state,
+            last_effect: TickEffect::None,
+            playing: false,
+            speed: 5,
+            next_step: Instant::now(),
+            ran: None,
+            burst: 10,
+            history: PcHistory::new(10),
+            recording: None,
+            recorded: Vec::new(),
+            replaying: false,
 
             persistent_memory: PersistentMemoryState::new(),
+            persistent_instructions: PersistentInstructionState::new(),
+            registers_area: Rect::default(),
+            memory_area: Rect::default(),
+
+            theme,
+            other_theme,
+            keymap,
+        }
+    }
+
+    /// Picks up where the last session left the view settings.
+    pub fn apply_preferences(&mut self, prefs: &Preferences) {
+        self.signed = prefs.signed;
+        self.fix_branches = prefs.fix_branches;
+        self.show_encoding = prefs.show_encoding;
+        self.show_stack = prefs.show_stack;
+        self.lints = prefs.lints;
+        self.persistent_memory.context = prefs.context.min(MAX_CONTEXT);
+        self.burst = prefs.burst.clamp(1, MAX_ITERS);
+        self.history.limit = prefs.history.clamp(1, MAX_HISTORY);
+        self.recent_files = prefs.recent_files.clone();
+    }
+
+    pub fn preferences(&self) -> Preferences {
+        Preferences {
+            signed: self.signed,
+            fix_branches: self.fix_branches,
+            show_encoding: self.show_encoding,
+            show_stack: self.show_stack,
+            lints: self.lints,
+            light_theme: self.theme == Theme::light(),
+            context: self.persistent_memory.context,
+            burst: self.burst,
+            history: self.history.limit,
+            recent_files: self.recent_files.clone(),
         }
     }
 
     pub fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
         terminal.draw(|frame| self.draw(frame))?;
         while self.running {
-            self.handle_events()?;
+            // While playing, only wait for a key until the next step is due
+            let has_event = !self.playing
+                || event::poll(self.next_step.saturating_duration_since(Instant::now()))?;
+
+            if has_event {
+                self.handle_events()?;
+            } else {
+                self.play_step();
+            }
+
             terminal.draw(|frame| self.draw(frame))?;
         }
 
@@ -74,69 +237,330 @@ impl Tui {
             return Ok(());
         }
 
-        match event.into() {
-            Input {
-                key: Key::Char('q'),
-                ctrl: true,
-                ..
-            } => self.running = false,
+        if let Event::Mouse(mouse) = event {
+            self.handle_mouse(mouse);
+            return Ok(());
+        }
 
-            Input {
-                key: Key::Char('w'),
-                ctrl: true,
-                ..
-            } => self.picking = true,
+        // Only the instructions know what to do with more than a line
+        if let Event::Paste(text) = event {
+            if let Focus::Instructions(state) = &mut self.focus {
+                if !self.help && !self.problems && !self.picking {
+                    state.paste(&text, &mut self.state, self.fix_branches);
+                }
+            }
+
+            return Ok(());
+        }
+
+        self.handle_input(event.into());
+
+        Ok(())
+    }
+
+    /// Handles a key press, whether it was just pressed or is being replayed.
+    fn handle_input(&mut self, event: Input) {
+        if let (Some(recording), false) = (&mut self.recording, self.replaying) {
+            recording.push(event.clone());
+        }
+
+        let keymap = &self.keymap;
+
+        match event {
+            event if self.is_global(Action::Quit, &event) => self.running = false,
+
+            event if self.help && (event.key == Key::Esc || keymap.is(Action::Help, &event)) => {
+                self.help = false
+            }
+
+            // Nothing else gets through while the help is open
+            _ if self.help => {}
+
+            event if self.problems && event.key == Key::Esc => self.problems = false,
+
+            event if self.problems && keymap.is(Action::Lints, &event) => self.lints = !self.lints,
+
+            _ if self.problems => {}
+
+            event if !self.picking && self.is_global(Action::Help, &event) => self.help = true,
+
+            event if self.is_global(Action::Window, &event) => self.picking = true,
 
             event if self.picking => {
-                match event {
-                    Input {
-                        key: Key::Char('i'),
-                        ..
-                    } => self.focus = Focus::Instructions(InstructionUIState::new()),
-                    Input {
-                        key: Key::Char('m'),
-                        ..
-                    } => {
-                        self.focus =
-                            Focus::Memory(MemoryUIState::new(self.persistent_memory.selected.get()))
+                if keymap.is(Action::PickInstructions, &event) {
+                    self.focus = Focus::Instructions(InstructionUIState::new());
+                } else if keymap.is(Action::PickMemory, &event) {
+                    self.focus =
+                        Focus::Memory(MemoryUIState::new(self.persistent_memory.selected.get()));
+                } else if keymap.is(Action::PickRegisters, &event) {
+                    self.focus = Focus::Registers(RegisterUIState::new(0));
+                } else if keymap.is(Action::Save, &event) {
+                    self.focus = Focus::Save(SaveUIState::new(self.theme));
+                } else if keymap.is(Action::Load, &event) {
+                    self.focus =
+                        Focus::Load(LoadUIState::new(self.theme, self.recent_files.clone()));
+                } else if keymap.is(Action::Theme, &event) {
+                    std::mem::swap(&mut self.theme, &mut self.other_theme);
+                } else if keymap.is(Action::Signed, &event) {
+                    self.signed = !self.signed;
+                } else if keymap.is(Action::FixBranches, &event) {
+                    self.fix_branches = !self.fix_branches;
+                } else if keymap.is(Action::Encoding, &event) {
+                    self.show_encoding = !self.show_encoding;
+                } else if keymap.is(Action::Stack, &event) {
+                    self.show_stack = !self.show_stack;
+                } else if keymap.is(Action::Problems, &event) {
+                    self.problems = true;
+                } else if keymap.is(Action::Record, &event) && !self.replaying {
+                    self.recording = match self.recording.take() {
+                        Some(mut recording) => {
+                            // Leave out the keys which stopped the recording
+                            recording.truncate(recording.len().saturating_sub(2));
+                            self.recorded = recording;
+                            None
+                        }
+                        None => Some(Vec::new()),
+                    };
+                } else if keymap.is(Action::Replay, &event) && !self.replaying {
+                    self.picking = false;
+                    self.replaying = true;
+
+                    for input in self.recorded.clone() {
+                        self.handle_input(input);
                     }
-                    Input {
-                        key: Key::Char('r'),
-                        ..
-                    } => self.focus = Focus::Registers(RegisterUIState::new()),
-                    Input {
-                        key: Key::Char('s'),
-                        ..
-                    } => self.focus = Focus::Save(SaveUIState::new()),
-                    Input {
-                        key: Key::Char('l'),
-                        ..
-                    } => self.focus = Focus::Load(LoadUIState::new()),
-                    _ => {}
+
+                    self.replaying = false;
+                } else if keymap.is(Action::Pipeline, &event) {
+                    self.focus = Focus::Pipeline(PipelineUIState::new(&self.state));
                 }
 
                 self.picking = false;
             }
 
-            event => match &mut self.focus {
-                Focus::Instructions(state) => state.handle(event, &mut self.state),
-                Focus::Registers(state) => state.handle(event, &mut self.state),
-                Focus::Memory(state) => state.handle(event, &mut self.state.memory),
-                Focus::Save(state) => {
-                    if state.handle(event, &self.state) {
-                        self.focus = Focus::Instructions(InstructionUIState::new());
+            event if self.is_playback(Action::Play, &event) => {
+                self.playing = !self.playing;
+                self.next_step = Instant::now();
+            }
+            event if self.is_playback(Action::RunAll, &event) => {
+                if let Focus::Instructions(state) = &mut self.focus {
+                    self.playing = false;
+                    let (ran, stopped) = state.run_all(
+                        &mut self.state,
+                        &mut self.last_effect,
+                        &mut self.history,
+                        MAX_ITERS,
+                    );
+                    self.ran = Some((ran, MAX_ITERS, stopped));
+                }
+            }
+            event if self.is_playback(Action::Burst, &event) => {
+                if let Focus::Instructions(state) = &mut self.focus {
+                    self.playing = false;
+
+                    let (ran, stopped) = state.run_for(
+                        &mut self.state,
+                        &mut self.last_effect,
+                        &mut self.history,
+                        self.burst,
+                    );
+                    self.ran = Some((ran, self.burst, stopped));
+                }
+            }
+            event if self.is_playback(Action::Faster, &event) => {
+                self.speed = (self.speed + 1).min(MAX_SPEED);
+            }
+            event if self.is_playback(Action::Slower, &event) => {
+                self.speed = self.speed.saturating_sub(1).max(1);
+            }
+            event if self.is_playback(Action::Reset, &event) => {
+                self.playing = false;
+                self.ran = None;
+                // Errors and warnings from the old run go with it
+                self.focus = Focus::Instructions(InstructionUIState::new());
+                self.state.reset_pc();
+                self.forget_run();
+            }
+
+            event => self.handle_focused(event),
+        }
+    }
+
+    /// Starts with a macro saved by `save_macro`.
+    pub fn load_macro(&mut self, text: &str) -> Result<()> {
+        self.recorded = serde_json::from_str(text)?;
+
+        Ok(())
+    }
+
+    /// The last recorded macro, as JSON.
+    pub fn save_macro(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(&self.recorded)?)
+    }
+
+    /// Runs the next instruction while playing, stopping if there's a
+    /// reason to or the instructions aren't there to watch.
+    fn play_step(&mut self) {
+        self.next_step = Instant::now() + Duration::from_secs(1) / self.speed;
+
+        match &mut self.focus {
+            Focus::Instructions(state) if !state.is_typing() => {
+                self.playing =
+                    !state.step(&mut self.state, &mut self.last_effect, &mut self.history);
+            }
+            _ => self.playing = false,
+        }
+    }
+
+    /// Whether `input` is bound to a playback `action`, which only works
+    /// from the instructions, outside of edit mode.
+    fn is_playback(&self, action: Action, input: &Input) -> bool {
+        matches!(&self.focus, Focus::Instructions(state) if !state.is_typing())
+            && self.keymap.is(action, input)
+    }
+
+    /// Whether `input` is bound to `action`, which works from any window.
+    /// Keys which could be typed don't count while typing.
+    fn is_global(&self, action: Action, input: &Input) -> bool {
+        let binding = self.keymap.get(action);
+
+        binding.matches(input) && !(binding.is_plain() && self.is_typing())
+    }
+
+    fn handle_focused(&mut self, event: Input) {
+        match &mut self.focus {
+            Focus::Instructions(state) => state.handle(
+                event,
+                &mut self.state,
+                &self.keymap,
+                self.fix_branches,
+                &mut self.last_effect,
+                &mut self.history,
+            ),
+            Focus::Registers(state) => state.handle(event, &mut self.state, &self.keymap),
+            Focus::Memory(state) => state.handle(
+                event,
+                &mut self.state.memory,
+                &mut self.state.watchpoints,
+                &mut self.persistent_memory,
+                &self.state.registers,
+                &self.keymap,
+            ),
+            Focus::Pipeline(state) => state.handle(event, &mut self.state, &self.keymap),
+            Focus::Save(state) => {
+                if state.handle(event, &self.state) {
+                    if state.saved {
+                        remember_file(&mut self.recent_files, &state.area.lines()[0]);
                     }
+
+                    self.focus = Focus::Instructions(InstructionUIState::new());
                 }
-                Focus::Load(state) => {
-                    if state.handle(event, &mut self.state) {
-                        self.focus = Focus::Instructions(InstructionUIState::new());
+            }
+            Focus::Load(state) => {
+                if state.handle(event, &mut self.state) {
+                    let (loaded, load_mem) = (state.loaded, state.load_mem);
+
+                    if loaded {
+                        remember_file(&mut self.recent_files, &state.area.lines()[0]);
+                    }
+
+                    self.focus = Focus::Instructions(InstructionUIState::new());
+
+                    if loaded {
+                        self.forget_run();
+                    }
+
+                    // Slots picked out in the old memory mean nothing in the
+                    // new one, but how much context to show is a preference,
+                    // as is whether to show the heat map
+                    if loaded && load_mem {
+                        let context = self.persistent_memory.context;
+                        let heat = self.persistent_memory.heat;
 
                         self.persistent_memory = PersistentMemoryState::new();
+                        self.persistent_memory.context = context;
+                        self.persistent_memory.heat = heat;
                     }
                 }
-            },
+            }
+        }
+    }
+
+    /// Forgets what running has done so far, in the simulator and in how
+    /// it's shown, as when a file is loaded or the program starts over.
+    fn forget_run(&mut self) {
+        self.state.forget_run();
+        self.last_effect = TickEffect::None;
+        self.history.clear();
+    }
+
+    /// Whether a key press would go towards some text being typed, or a
+    /// dialog, which the mouse shouldn't take focus away from.
+    fn is_typing(&self) -> bool {
+        match &self.focus {
+            Focus::Instructions(state) => state.is_typing(),
+            Focus::Registers(state) => state.replacing.is_some(),
+            Focus::Memory(state) => {
+                state.insertion.is_some()
+                    || state.line_selection.is_some()
+                    || state.register_selection.is_some()
+                    || state.array_insertion.is_some()
+                    || state.bookmark_label.is_some()
+                    || state.confirm_clear
+            }
+            Focus::Pipeline(_) => false,
+            Focus::Save(_) | Focus::Load(_) => true,
+        }
+    }
+
+    /// Clicks pick an instruction, register or memory slot, and scrolling
+    /// acts like the arrow keys on whatever is under the cursor.
+    fn handle_mouse(&mut self, mouse: MouseEvent) {
+        if self.picking || self.help || self.problems || self.is_typing() {
+            return;
+        }
+
+        let position = Position::new(mouse.column, mouse.row);
+
+        let scroll = match mouse.kind {
+            MouseEventKind::Down(MouseButton::Left) => None,
+            MouseEventKind::ScrollUp => Some(Key::Up),
+            MouseEventKind::ScrollDown => Some(Key::Down),
+            _ => return,
+        };
+
+        if let Some(idx) = self.persistent_instructions.instruction_at(position) {
+            if !matches!(self.focus, Focus::Instructions(_)) {
+                self.focus = Focus::Instructions(InstructionUIState::new());
+            }
+
+            if scroll.is_none() && idx <= self.state.instructions.len() as u64 {
+                self.state.registers.pc = idx;
+            }
+        } else if let Some(reg) = RegisterUI::register_at(self.registers_area, position) {
+            if scroll.is_none() || !matches!(self.focus, Focus::Registers(_)) {
+                self.focus = Focus::Registers(RegisterUIState::new(reg));
+            }
+        } else if self.memory_area.contains(position) && !matches!(self.focus, Focus::Pipeline(_)) {
+            match self.persistent_memory.slot_at(position) {
+                Some(slot) if scroll.is_none() => {
+                    self.focus = Focus::Memory(MemoryUIState::new(slot as usize));
+                }
+                _ if !matches!(self.focus, Focus::Memory(_)) => {
+                    self.focus =
+                        Focus::Memory(MemoryUIState::new(self.persistent_memory.selected.get()));
+                }
+                _ => {}
+            }
+        } else {
+            return;
+        }
+
+        if let Some(key) = scroll {
+            self.handle_focused(Input {
+                key,
+                ..Default::default()
+            });
         }
-        Ok(())
     }
 
     fn draw(&mut self, frame: &mut Frame) {
@@ -157,7 +581,13 @@ impl Tui {
 
         let registers = RegisterUI {
             registers: &self.state.registers,
+            word_bits: self.state.word_bits,
+            profile: self.state.profile,
+            signed: self.signed,
             instrs: &self.state.instructions,
+            read_unwritten: &self.state.read_unwritten,
+            last_effect: self.last_effect,
+            theme: &self.theme,
             state: if let Focus::Registers(reg) = &self.focus {
                 Some(reg)
             } else {
@@ -166,11 +596,17 @@ impl Tui {
         };
 
         frame.render_widget(registers, layout_reg_mem[0]);
+        self.registers_area = layout_reg_mem[0];
 
         let memory = MemoryUI {
             memory: &self.state.memory,
+            watchpoints: &self.state.watchpoints,
+            accesses: &self.state.accesses,
             registers: &self.state.registers,
+            signed: self.signed,
             instrs: &self.state.instructions,
+            last_effect: self.last_effect,
+            theme: &self.theme,
             state: if let Focus::Memory(state) = &self.focus {
                 Some(state)
             } else {
@@ -180,13 +616,63 @@ impl Tui {
             persistent: &self.persistent_memory,
         };
 
-        frame.render_widget(memory, layout_reg_mem[1]);
+        let memory_area = if self.show_stack {
+            let [memory_area, stack_area] =
+                Layout::horizontal([Constraint::Fill(1), Constraint::Length(26)])
+                    .areas(layout_reg_mem[1]);
+
+            let stack = StackUI {
+                memory: &self.state.memory,
+                registers: &self.state.registers,
+                signed: self.signed,
+                theme: &self.theme,
+            };
+
+            frame.render_widget(stack, stack_area);
+
+            memory_area
+        } else {
+            layout_reg_mem[1]
+        };
+
+        self.memory_area = memory_area;
+
+        // The pipeline takes the place of memory while we're looking at it
+        if let Focus::Pipeline(state) = &self.focus {
+            let pipeline = PipelineUI {
+                instrs: &self.state.instructions,
+                word_bits: self.state.word_bits,
+                theme: &self.theme,
+                state,
+            };
+
+            frame.render_widget(pipeline, memory_area);
+        } else {
+            frame.render_widget(memory, memory_area);
+        }
+
+        let hazards = self.state.detect_hazards();
+        let reachable = self.state.reachable_instructions();
+        let branches_to_pc = self.state.branches_to(self.state.registers.pc);
+        let history = self.history.pcs();
 
         let instructions = InstructionUI {
             instrs: &self.state.instructions,
             registers: &self.state.registers,
             memory: &self.state.memory,
             pc: self.state.registers.pc,
+            word_bits: self.state.word_bits,
+            signed: self.signed,
+            show_encoding: self.show_encoding,
+            hazards: &hazards,
+            reachable: &reachable,
+            notes: &self.state.notes,
+            labels: &self.state.labels,
+            entry: self.state.entry,
+            branches_to_pc: &branches_to_pc,
+            history: &history,
+            theme: &self.theme,
+            persistent: &self.persistent_instructions,
             state: if let Focus::Instructions(state) = &self.focus {
                 Some(state)
             } else {
@@ -197,13 +683,13 @@ impl Tui {
         frame.render_widget(instructions, main_layout[0]);
 
         if self.picking {
-            let mut picker = Picker::new('r');
+            let picker = Picker::new(self.keymap.get(Action::PickRegisters), &self.theme);
             frame.render_widget(picker, layout_reg_mem[0]);
 
-            picker = Picker::new('m');
+            let picker = Picker::new(self.keymap.get(Action::PickMemory), &self.theme);
             frame.render_widget(picker, layout_reg_mem[1]);
 
-            picker = Picker::new('i');
+            let picker = Picker::new(self.keymap.get(Action::PickInstructions), &self.theme);
             frame.render_widget(picker, main_layout[0]);
         }
 
@@ -211,7 +697,7 @@ impl Tui {
 
         let mut prev = commands.next();
 
-        let mut command_components = Vec::<Span<'static>>::new();
+        let mut command_components = Vec::<Span>::new();
 
         while let Some(next) = commands.next() {
             let Some((key, expl)) = prev else {
@@ -220,7 +706,7 @@ impl Tui {
 
             command_components.push(expl.into());
             command_components.push(" ".into());
-            command_components.push(key.light_blue().bold());
+            command_components.push(key.fg(self.theme.key_hint).bold());
             command_components.push(" | ".into());
 
             prev = Some(next);
@@ -229,99 +715,240 @@ impl Tui {
         if let Some((key, expl)) = prev {
             command_components.push(expl.into());
             command_components.push(" ".into());
-            command_components.push(key.light_blue().bold());
+            command_components.push(key.fg(self.theme.key_hint).bold());
         }
 
         let explanations = Line::from(command_components);
-        frame.render_widget(explanations, command_list_layout[1]);
+
+        let mut cycles = Line::default();
+
+        if self.recording.is_some() {
+            cycles.push_span("● Recording".fg(self.theme.error).bold());
+            cycles.push_span(" | ");
+        }
+
+        if self.playing {
+            cycles.push_span(format!("▶ {}/s", self.speed).fg(self.theme.pc).bold());
+            cycles.push_span(" | ");
+        }
+
+        if let Some((ran, limit, stopped)) = self.ran {
+            // Like the warning `run_all` leaves
+            let color = if ran == limit && !stopped {
+                self.theme.warning
+            } else {
+                self.theme.key_hint
+            };
+
+            cycles.push_span("Ran: ");
+            cycles.push_span(format!("{ran}/{limit}").fg(color).bold());
+            cycles.push_span(" | ");
+        }
+
+        cycles.extend([
+            "Cycles: ".into(),
+            format!("~{}", self.state.cycles)
+                .fg(self.theme.key_hint)
+                .bold(),
+        ]);
+
+        let [commands_area, cycles_area] = Layout::horizontal([
+            Constraint::Fill(1),
+            Constraint::Length(cycles.width() as u16),
+        ])
+        .areas(command_list_layout[1]);
+
+        frame.render_widget(explanations, commands_area);
+        frame.render_widget(cycles, cycles_area);
 
         if let Focus::Save(state) = &self.focus {
             frame.render_widget(state, frame.area());
         } else if let Focus::Load(state) = &self.focus {
             frame.render_widget(state, frame.area());
         }
+
+        if self.help {
+            let help = HelpUI {
+                theme: &self.theme,
+                keymap: &self.keymap,
+            };
+
+            frame.render_widget(help, command_list_layout[0]);
+        }
+
+        if self.problems {
+            let problems = self.state.validate_program().err().unwrap_or_default();
+            let warnings = if self.lints {
+                self.state.lint_skipped_setup()
+            } else {
+                Vec::new()
+            };
+
+            let problems = ProblemsUI {
+                problems: &problems,
+                warnings: &warnings,
+                theme: &self.theme,
+            };
+
+            frame.render_widget(problems, command_list_layout[0]);
+        }
     }
 
-    fn get_commands(&self) -> impl Iterator<Item = (&'static str, &'static str)> + 'static {
-        let default = if self.picking {
-            [("<Ctrl> <Q>", "Quit"), ("<L>", "Load"), ("<S>", "Save")][..].into_iter()
+    fn get_commands(&self) -> impl Iterator<Item = (String, &'static str)> + '_ {
+        let default = if self.help {
+            HELP_KEYS
+        } else if self.problems {
+            PROBLEMS_KEYS
+        } else if self.picking {
+            PICKER_KEYS
         } else {
-            [("<Ctrl> <Q>", "Quit"), ("<Ctrl> <W>", "Window")][..].into_iter()
+            GLOBAL_KEYS
         };
 
         let window = match &self.focus {
+            _ if self.help || self.problems => &[],
+
+            Focus::Instructions(InstructionUIState {
+                message: Some(_), ..
+            }) => DISMISS_KEYS,
             Focus::Instructions(state) => {
-                if state.text.is_some() {
-                    [("<Esc>", "Exit Edit Mode"), ("<any key>", "Edit")][..].into_iter()
+                if state.snippet.is_some() {
+                    SNIPPET_KEYS
+                } else if state.note.is_some() {
+                    CONFIRM_KEYS
+                } else if state.text.is_some() {
+                    INSTRUCTION_EDIT_KEYS
+                } else if self.playing {
+                    INSTRUCTION_PLAYING_KEYS
+                } else if state.error.is_some() || state.warning.is_some() {
+                    INSTRUCTION_ERROR_KEYS
                 } else {
-                    [
-                        ("<Enter>", "Run 1"),
-                        ("<Up>", "PC -= 4"),
-                        ("<Down>", "PC += 4"),
-                        ("<Ctrl> <R>", "Enter Edit Mode"),
-                    ][..]
-                        .into_iter()
+                    INSTRUCTION_KEYS
                 }
             }
+            Focus::Registers(RegisterUIState {
+                message: Some(_), ..
+            }) => DISMISS_KEYS,
             Focus::Registers(RegisterUIState { replacing, .. }) => match replacing {
-                Some(_) => [("<Esc>", "Cancel"), ("<Enter>", "Accept")][..].into_iter(),
-                None => [("<Arrow Key>", "Pick"), ("<Ctrl> <R>", "Edit")][..].into_iter(),
+                Some(_) => CONFIRM_KEYS,
+                None => REGISTER_KEYS,
             },
             Focus::Memory(MemoryUIState {
                 insertion,
                 line_selection,
+                register_selection,
+                array_insertion,
+                bookmark_label,
+                message,
+                confirm_clear,
                 ..
             }) => {
-                if insertion.is_some() || line_selection.is_some() {
-                    [("<Esc>", "Cancel"), ("<Enter>", "Accept")][..].into_iter()
+                if message.is_some() {
+                    DISMISS_KEYS
+                } else if *confirm_clear {
+                    MEMORY_CLEAR_KEYS
+                } else if insertion.is_some()
+                    || line_selection.is_some()
+                    || register_selection.is_some()
+                    || array_insertion.is_some()
+                    || bookmark_label.is_some()
+                {
+                    CONFIRM_KEYS
                 } else {
-                    [
-                        ("<G>", "Goto Addr"),
-                        ("<Ctrl> <R>", "Replace"),
-                        ("<Arrow Up/Down>", "Navigate"),
-                    ][..]
-                        .into_iter()
+                    MEMORY_KEYS
                 }
             }
 
             Focus::Load(LoadUIState { message, focus, .. }) => {
                 if message.is_some() {
-                    [("<Esc>", "Dismiss")][..].into_iter()
+                    ESC_DISMISS_KEYS
                 } else {
                     match focus {
-                        LoadFocus::Reg | LoadFocus::Mem | LoadFocus::Instr => [
-                            ("<Esc>", "Cancel"),
-                            ("<Enter>", "Toggle"),
-                            ("<arrow key>", "Select"),
-                        ][..]
-                            .into_iter(),
-                        LoadFocus::File => [
-                            ("<Esc>", "Cancel"),
-                            ("<Enter>", "Accept"),
-                            ("<Up/Down>", "Select"),
-                        ][..]
-                            .into_iter(),
+                        LoadFocus::Reg | LoadFocus::Mem | LoadFocus::Instr => LOAD_TOGGLE_KEYS,
+                        LoadFocus::File => LOAD_FILE_KEYS,
+                        LoadFocus::Recent(_) => LOAD_RECENT_KEYS,
                     }
                 }
             }
 
-            Focus::Save(SaveUIState { message, .. }) => match message {
-                Some(_) => [("<Esc>", "Dismiss")][..].into_iter(),
+            Focus::Pipeline(PipelineUIState { message, .. }) => match message {
+                Some(_) => DISMISS_KEYS,
+                None => PIPELINE_KEYS,
+            },
 
-                None => [("<Esc>", "Cancel"), ("<Enter>", "Accept")][..].into_iter(),
+            Focus::Save(SaveUIState {
+                message,
+                focus,
+                confirm_overwrite,
+                ..
+            }) => match (message, focus) {
+                _ if *confirm_overwrite => OVERWRITE_KEYS,
+                (Some(_), _) => ESC_DISMISS_KEYS,
+
+                (None, SaveFocus::Compact) => SAVE_TOGGLE_KEYS,
+                (None, SaveFocus::File) => LOAD_FILE_KEYS,
             },
         };
 
-        default.copied().chain(window.copied())
+        default
+            .iter()
+            .chain(window)
+            .map(|&(key, expl)| (key.label(&self.keymap), expl))
     }
 }
 
-pub fn setup_and_run_tui(simulator: Simulator) -> Result<()> {
+pub fn setup_and_run_tui(
+    simulator: Simulator,
+    theme: Theme,
+    keymap: Keymap,
+    prefs: &Preferences,
+    macro_file: Option<&str>,
+) -> Result<()> {
+    let mut tui = Tui::new(simulator, theme, keymap);
+    tui.apply_preferences(prefs);
+
+    // A file which doesn't exist yet is where to save the first recording
+    if let Some(text) = macro_file.and_then(|x| std::fs::read_to_string(x).ok()) {
+        tui.load_macro(&text)?;
+    }
+
     let mut terminal = ratatui::init();
+    execute!(std::io::stdout(), EnableMouseCapture, EnableBracketedPaste)?;
+
+    // ratatui's hook leaves raw mode and the alternate screen before the
+    // panic is printed, but doesn't know about the mouse or pasting
+    let hook: Arc<dyn Fn(&panic::PanicHookInfo) + Sync + Send> = panic::take_hook().into();
+    let previous = hook.clone();
+    panic::set_hook(Box::new(move |info| {
+        let _ = execute!(
+            std::io::stdout(),
+            DisableMouseCapture,
+            DisableBracketedPaste
+        );
+        previous(info);
+    }));
+
+    let result = tui.run(&mut terminal);
+
+    // Back to ratatui's hook, as the mouse and pasting are turned off below
+    drop(panic::take_hook());
+    panic::set_hook(Box::new(move |info| hook(info)));
+
+    execute!(
+        std::io::stdout(),
+        DisableMouseCapture,
+        DisableBracketedPaste
+    )?;
+    ratatui::restore();
 
-    Tui::new(simulator).run(&mut terminal)?;
+    // Losing these isn't worth failing over
+    let _ = tui.preferences().save();
 
-    ratatui::restore();
+    result?;
+
+    if let Some(path) = macro_file {
+        std::fs::write(path, tui.save_macro()?)?;
+    }
 
     Ok(())
 }
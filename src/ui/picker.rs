@@ -7,16 +7,20 @@ use ratatui::{
     widgets::{Block, Paragraph, Widget},
 };
 
-use crate::util::center;
+use crate::util::{center, Binding, Theme};
 
 #[derive(Clone, Copy)]
 pub struct Picker {
-    name: char,
+    name: Binding,
+    border: Color,
 }
 
 impl Picker {
-    pub fn new(name: char) -> Self {
-        Self { name }
+    pub fn new(name: Binding, theme: &Theme) -> Self {
+        Self {
+            name,
+            border: theme.dialog,
+        }
     }
 }
 
@@ -24,7 +28,7 @@ impl Widget for Picker {
     fn render(self, area: Rect, buf: &mut Buffer) {
         let block = Block::bordered()
             .border_set(border::DOUBLE)
-            .set_style(Style::reset().fg(Color::Cyan));
+            .set_style(Style::reset().fg(self.border));
 
         let text = Text::raw(format!("Select this window: {}", self.name));
 
@@ -0,0 +1,127 @@
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::Stylize,
+    symbols::border,
+    text::{Line, Span, Text},
+    widgets::{Block, Paragraph, Widget},
+};
+use tui_textarea::Input;
+
+use crate::{
+    simulator::{Instruction, Pipeline, Simulator, STAGE_NAMES},
+    util::{make_title, render_message, Action, Keymap, Theme},
+};
+
+#[derive(Copy, Clone)]
+pub struct PipelineUI<'a> {
+    pub instrs: &'a [Instruction],
+    pub word_bits: u8,
+    pub theme: &'a Theme,
+    pub state: &'a PipelineUIState,
+}
+
+impl Widget for PipelineUI<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let pipeline = &self.state.pipeline;
+
+        let title = make_title("Pipeline", true, self.theme);
+
+        let block = Block::bordered().title(title).border_set(border::ROUNDED);
+
+        let inner = block.inner(area);
+
+        let mut lines = Vec::new();
+
+        lines.push(Line::from(vec![
+            "Cycle ".into(),
+            format!("{}", pipeline.history.len()).fg(self.theme.literal),
+        ]));
+
+        for (name, stage) in STAGE_NAMES.iter().zip(pipeline.stages) {
+            let mut line = vec![
+                format!("{name:<4}").bold().fg(self.theme.keyword),
+                ": ".into(),
+            ];
+
+            match stage.and_then(|x| self.instrs.get(x as usize).map(|i| (x, i))) {
+                Some((idx, instr)) => {
+                    line.push(format!("{idx:<3} ").into());
+                    line.extend(instr.get_line(self.word_bits, self.theme));
+                }
+                None => line.push("(bubble)".fg(self.theme.muted)),
+            }
+
+            lines.push(Line::from(line));
+        }
+
+        lines.push(Line::default());
+
+        // The most recent cycles which fit, each column being 4 wide.
+        let columns = (inner.width.saturating_sub(4) / 4) as usize;
+        let first = pipeline.history.len().saturating_sub(columns);
+        let shown = &pipeline.history[first..];
+
+        let mut header = vec![Span::from("    ")];
+        header.extend(
+            (first..pipeline.history.len()).map(|x| format!("{:>4}", x + 1).fg(self.theme.muted)),
+        );
+        lines.push(Line::from(header));
+
+        for (i, name) in STAGE_NAMES.iter().enumerate() {
+            let mut line = vec![format!("{name:<4}").bold().fg(self.theme.keyword)];
+
+            line.extend(shown.iter().map(|stages| match stages[i] {
+                Some(idx) => format!("{idx:>4}").into(),
+                None => format!("{:>4}", "-").fg(self.theme.muted),
+            }));
+
+            lines.push(Line::from(line));
+        }
+
+        block.render(area, buf);
+
+        Paragraph::new(Text::from(lines)).render(inner, buf);
+
+        if let Some(message) = &self.state.message {
+            render_message(message, self.theme, inner, buf);
+        }
+    }
+}
+
+pub struct PipelineUIState {
+    pub pipeline: Pipeline,
+    pub message: Option<String>,
+    /// The simulator as it was before the first cycle, to restart from
+    start: Simulator,
+}
+
+impl PipelineUIState {
+    pub fn new(sim: &Simulator) -> Self {
+        Self {
+            pipeline: Pipeline::new(sim.registers.pc),
+            message: None,
+            start: sim.clone(),
+        }
+    }
+
+    pub fn handle(&mut self, input: Input, state: &mut Simulator, keymap: &Keymap) {
+        // Messages only last until the next key press
+        self.message = None;
+
+        match input {
+            input if keymap.is(Action::Step, &input) => {
+                if let Err(e) = self.pipeline.step(state) {
+                    self.message = Some(e.to_string());
+                }
+            }
+
+            input if keymap.is(Action::Restart, &input) => {
+                *state = self.start.clone();
+                self.pipeline = Pipeline::new(state.registers.pc);
+            }
+
+            _ => {}
+        }
+    }
+}
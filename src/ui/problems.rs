@@ -0,0 +1,66 @@
+use ratatui::{
+    buffer::Buffer,
+    layout::{Constraint, Rect},
+    style::{Style, Styled, Stylize},
+    symbols::border,
+    text::{Line, Text},
+    widgets::{Block, Clear, Paragraph, Widget, Wrap},
+};
+
+use crate::util::{center, make_title, Theme};
+
+/// Everything `Simulator::validate_program` found wrong, all at once.
+#[derive(Copy, Clone)]
+pub struct ProblemsUI<'a> {
+    pub problems: &'a [(usize, String)],
+    /// From `Simulator::lint_skipped_setup` when they're turned on, listed
+    /// after the problems
+    pub warnings: &'a [(usize, String)],
+    pub theme: &'a Theme,
+}
+
+impl Widget for ProblemsUI<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let title = make_title("Problems", true, self.theme);
+
+        let area = center(area, Constraint::Percentage(80), Constraint::Percentage(80));
+
+        let block = Block::bordered()
+            .border_set(border::ROUNDED)
+            .set_style(Style::reset().fg(self.theme.dialog))
+            .title(title);
+
+        let inner = block.inner(area);
+
+        block.render(area, buf);
+
+        Clear.render(inner, buf);
+
+        let lines = if self.problems.is_empty() && self.warnings.is_empty() {
+            vec![Line::from("No problems found".fg(self.theme.muted))]
+        } else {
+            let problems = self
+                .problems
+                .iter()
+                .map(|(idx, problem)| (idx, problem, self.theme.error));
+            let warnings = self
+                .warnings
+                .iter()
+                .map(|(idx, warning)| (idx, warning, self.theme.warning));
+
+            problems
+                .chain(warnings)
+                .map(|(idx, text, color)| {
+                    Line::from(vec![
+                        format!("{idx}: ").fg(self.theme.pc),
+                        text.clone().fg(color),
+                    ])
+                })
+                .collect()
+        };
+
+        Paragraph::new(Text::from(lines))
+            .wrap(Wrap { trim: false })
+            .render(inner, buf);
+    }
+}
@@ -1,6 +1,8 @@
+use std::collections::BTreeSet;
+
 use ratatui::{
     buffer::Buffer,
-    layout::{Constraint, Layout, Rect},
+    layout::{Constraint, Layout, Position, Rect},
     style::Stylize,
     symbols::border,
     text::{Line, Text},
@@ -9,20 +11,55 @@ use ratatui::{
 use tui_textarea::{Input, Key, TextArea};
 
 use crate::{
-    simulator::{Highlight, Instruction, Registers, Simulator},
-    util::make_title,
+    simulator::{format_word, Instruction, Profile, Registers, Simulator, TickEffect},
+    util::{highlight_change, make_title, render_message, render_value, Action, Keymap, Theme},
 };
 
 #[derive(Copy, Clone)]
 pub struct RegisterUI<'a> {
     pub registers: &'a Registers,
+    pub word_bits: u8,
+    /// From `Simulator::profile`, for the names of registers
+    pub profile: Profile,
+    /// Also show values as signed numbers
+    pub signed: bool,
     pub instrs: &'a [Instruction],
+    /// From `Simulator::read_unwritten`, marked with a `!`
+    pub read_unwritten: &'a BTreeSet<u8>,
+    /// Whatever this changed is highlighted
+    pub last_effect: TickEffect,
+    pub theme: &'a Theme,
     pub state: Option<&'a RegisterUIState>,
 }
 
+impl RegisterUI<'_> {
+    /// The register drawn at a position on screen when drawn in `area`, 31
+    /// being PC.
+    pub fn register_at(area: Rect, position: Position) -> Option<u8> {
+        let inner = Block::bordered().inner(area);
+
+        if !inner.contains(position) {
+            return None;
+        }
+
+        let [left, _] =
+            Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)])
+                .areas::<2>(inner);
+
+        let row = (position.y - inner.y) as u8;
+
+        match (left.contains(position), row) {
+            (true, 0..16) => Some(row),
+            (false, 0..15) => Some(16 + row),
+            (false, 15) => Some(31),
+            _ => None,
+        }
+    }
+}
+
 impl Widget for RegisterUI<'_> {
     fn render(self, area: Rect, buf: &mut Buffer) {
-        let title = make_title("Registers", self.state.is_some());
+        let title = make_title("Registers", self.state.is_some(), self.theme);
 
         let block = Block::bordered().title(title).border_set(border::ROUNDED);
 
@@ -31,28 +68,40 @@ impl Widget for RegisterUI<'_> {
         let mut textarea_draw = None;
 
         for i in 0..31 {
+            let name = format!("{:<3}", self.profile.name(i, self.word_bits));
+            let value = format_word(self.registers.get(i).unwrap(), self.word_bits, self.signed);
+
             if Some(i) == self.state.map(|x| x.selected) {
                 if let Some(area) = self.state.and_then(|x| x.replacing.as_ref()) {
                     textarea_draw = Some((i, area));
 
                     lines.push(Line::from(vec![
-                        format!("X{i:<2}").bold().red().underlined(),
+                        name.bold().fg(self.theme.register).underlined(),
                         ": ".bold().underlined(),
                     ]));
                 } else {
                     lines.push(Line::from(vec![
-                        format!("X{i:<2}").bold().red().underlined(),
-                        format!(": {}", self.registers.get(i).unwrap())
-                            .bold()
-                            .underlined(),
+                        name.bold().fg(self.theme.register).underlined(),
+                        format!(": {}", value).bold().underlined(),
                     ]));
                 }
             } else {
                 lines.push(Line::from(vec![
-                    format!("X{i:<2}").bold().red(),
-                    format!(": {}", self.registers.get(i).unwrap()).into(),
+                    name.bold().fg(self.theme.register),
+                    format!(": {}", value).into(),
                 ]));
             }
+
+            if matches!(self.last_effect, TickEffect::RegWrite { reg, .. } if reg == i) {
+                highlight_change(lines.last_mut().unwrap(), self.theme);
+            }
+
+            if self.read_unwritten.contains(&i) {
+                lines
+                    .last_mut()
+                    .unwrap()
+                    .push_span(" !".fg(self.theme.warning).bold());
+            }
         }
 
         if self.state.map(|x| x.selected) == Some(31) {
@@ -60,22 +109,27 @@ impl Widget for RegisterUI<'_> {
                 textarea_draw = Some((31, area));
 
                 lines.push(Line::from(vec![
-                    "PC ".bold().green().underlined(),
+                    "PC ".bold().fg(self.theme.pc).underlined(),
                     ": ".bold().underlined(),
                 ]));
             } else {
                 lines.push(Line::from(vec![
-                    "PC ".bold().green().underlined(),
+                    "PC ".bold().fg(self.theme.pc).underlined(),
                     format!(": {}", self.registers.pc * 4).bold().underlined(),
                 ]));
             }
         } else {
             lines.push(Line::from(vec![
-                "PC ".bold().green(),
+                "PC ".bold().fg(self.theme.pc),
                 format!(": {}", self.registers.pc * 4).into(),
             ]));
         }
 
+        // PC always moves, so only a branch counts
+        if let TickEffect::Branch { .. } = self.last_effect {
+            highlight_change(lines.last_mut().unwrap(), self.theme);
+        }
+
         let text_left = Text::from(lines[..16].to_owned());
         let text_right = Text::from(lines[16..].to_owned());
 
@@ -111,10 +165,7 @@ impl Widget for RegisterUI<'_> {
                     ])
                     .areas::<3>(layout_left[0])[1];
 
-                    match high {
-                        Highlight::Source => "<".green().bold().render(place, buf),
-                        Highlight::Dest => ">".cyan().bold().render(place, buf),
-                    }
+                    high.marker(self.theme).render(place, buf);
                 }
             }
 
@@ -129,10 +180,7 @@ impl Widget for RegisterUI<'_> {
                     ])
                     .split(layout_right[0])[1];
 
-                    match high {
-                        Highlight::Source => "<".green().bold().render(place, buf),
-                        Highlight::Dest => ">".cyan().bold().render(place, buf),
-                    }
+                    high.marker(self.theme).render(place, buf);
                 }
             }
 
@@ -144,7 +192,7 @@ impl Widget for RegisterUI<'_> {
                 ])
                 .areas::<3>(layout_right[0])[1];
 
-                ">".cyan().bold().render(place, buf);
+                ">".fg(self.theme.dest).bold().render(place, buf);
             }
         }
 
@@ -165,27 +213,90 @@ impl Widget for RegisterUI<'_> {
             let place = Layout::horizontal([Constraint::Length(5), Constraint::Fill(1)])
                 .areas::<2>(place)[1];
 
+            // PC is typed as a byte address, so say which instruction it is
+            let hint = match pc_target(&area.lines()[0], self.instrs.len()) {
+                _ if idx != 31 || area.is_empty() => None,
+                Ok(target) => Some(format!(" → instruction {target}").fg(self.theme.muted)),
+                Err(reason) => Some(format!(" {reason}").fg(self.theme.error)),
+            };
+
+            let [place, hint_place] = Layout::horizontal([
+                Constraint::Fill(1),
+                Constraint::Length(hint.as_ref().map_or(0, |x| x.width() as u16)),
+            ])
+            .areas(place);
+
             area.render(place, buf);
+
+            if let Some(hint) = hint {
+                hint.render(hint_place, buf);
+            }
+        }
+
+        if let Some(RegisterUIState {
+            selected,
+            inspecting: true,
+            ..
+        }) = self.state
+        {
+            let (value, word_bits) = match *selected {
+                31 => (self.registers.pc * 4, 64),
+                reg => (self.registers.get(reg).unwrap(), self.word_bits),
+            };
+
+            render_value(value, word_bits, self.theme, inner, buf);
+        }
+
+        if let Some(message) = self.state.and_then(|x| x.message.as_ref()) {
+            render_message(message, self.theme, inner, buf);
         }
     }
 }
 
+/// The instruction a byte address typed in for PC goes to, or why it can't.
+fn pc_target(text: &str, len: usize) -> Result<u64, &'static str> {
+    let Ok(addr) = text.trim().parse::<i128>() else {
+        return Err("not a number");
+    };
+
+    if addr < 0 || addr > u64::MAX as i128 {
+        Err("out of range")
+    } else if addr % 4 != 0 {
+        Err("not a multiple of 4")
+    } else if addr / 4 > len as i128 {
+        Err("past the end")
+    } else {
+        Ok(addr as u64 / 4)
+    }
+}
+
 pub struct RegisterUIState {
     selected: u8,
     pub replacing: Option<TextArea<'static>>,
+    pub message: Option<String>,
+    /// Showing the selected register in every base
+    pub inspecting: bool,
 }
 
 impl RegisterUIState {
-    pub fn new() -> Self {
+    pub fn new(selected: u8) -> Self {
         Self {
-            selected: 0,
+            selected,
             replacing: None,
+            message: None,
+            inspecting: false,
         }
     }
 
-    pub fn handle(&mut self, input: Input, state: &mut Simulator) {
+    pub fn handle(&mut self, input: Input, state: &mut Simulator, keymap: &Keymap) {
+        // Messages only last until the next key press
+        self.message = None;
+
         match input {
-            Input { key: Key::Esc, .. } => self.replacing = None,
+            Input { key: Key::Esc, .. } => {
+                self.replacing = None;
+                self.inspecting = false;
+            }
 
             Input {
                 key: Key::Enter, ..
@@ -194,24 +305,36 @@ impl RegisterUIState {
                     return;
                 };
 
-                let text = &replacement.lines()[0];
+                let text = replacement.lines()[0].trim();
 
-                if let Ok(new_val) = text.trim().parse::<i128>() {
-                    if self.selected != 31 {
-                        state.registers.set(self.selected, new_val as u64).unwrap();
-                    } else {
-                        state.registers.pc = new_val as u64 / 4;
+                if self.selected == 31 {
+                    match pc_target(text, state.instructions.len()) {
+                        Ok(target) => state.registers.pc = target,
+                        Err(reason) => {
+                            self.message = Some(format!("Byte address {text:?} is {reason}!"))
+                        }
                     }
+
+                    return;
                 }
+
+                let Ok(new_val) = text.parse::<i128>() else {
+                    return;
+                };
+
+                state.registers.set(self.selected, new_val as u64).unwrap();
+                state.written[self.selected as usize] = true;
             }
 
-            Input {
-                key: Key::Char('r'),
-                ctrl: true,
-                ..
-            } => {
+            input if keymap.is(Action::Edit, &input) => {
                 if self.replacing.is_none() {
-                    self.replacing = Some(TextArea::default())
+                    let mut area = TextArea::default();
+
+                    if self.selected == 31 {
+                        area.set_placeholder_text("byte address");
+                    }
+
+                    self.replacing = Some(area);
                 }
             }
 
@@ -223,6 +346,12 @@ impl RegisterUIState {
                 replacing.input(input);
             }
 
+            input if keymap.is(Action::WordSize, &input) => {
+                state.word_bits = if state.word_bits == 32 { 64 } else { 32 };
+            }
+
+            input if keymap.is(Action::Inspect, &input) => self.inspecting = !self.inspecting,
+
             Input { key: Key::Up, .. } => self.selected = (self.selected + 31) % 32,
             Input { key: Key::Down, .. } => self.selected = (self.selected + 1) % 32,
             Input {
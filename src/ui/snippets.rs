@@ -0,0 +1,125 @@
+use ratatui::{
+    buffer::Buffer,
+    layout::{Constraint, Rect},
+    style::{Style, Styled, Stylize},
+    symbols::border,
+    text::{Line, Text},
+    widgets::{Block, Clear, Paragraph, Widget},
+};
+
+use crate::util::{center, make_title, Theme};
+
+/// A common pattern to start from, inserted a line at a time. Anything in
+/// `{}` is a placeholder, which the cursor is put on to be changed.
+pub struct Snippet {
+    pub name: &'static str,
+    pub text: &'static str,
+}
+
+pub const SNIPPETS: [Snippet; 3] = [
+    Snippet {
+        name: "Counted loop",
+        text: "\
+ADDI X9, XZR, #{10} // times around
+// loop body
+SUBI X9, X9, #1
+CBNZ X9, #-2",
+    },
+    Snippet {
+        name: "Array iteration",
+        text: "\
+ADD X10, {X19}, XZR // address of A[0]
+ADDI X11, XZR, #{8} // length of A
+LDUR X9, [X10, #0]
+// use A[i] in X9
+ADDI X10, X10, #8
+SUBI X11, X11, #1
+CBNZ X11, #-4",
+    },
+    Snippet {
+        name: "Function prologue/epilogue",
+        text: "\
+SUBI SP, SP, #{16} // room for X30 and X19
+STUR X30, [SP, #8]
+STUR X19, [SP, #0]
+// function body
+LDUR X19, [SP, #0]
+LDUR X30, [SP, #8]
+ADDI SP, SP, #16",
+    },
+];
+
+/// Takes the `{}` out of a line of a snippet, returning it along with the
+/// column of the first placeholder, if there is one.
+pub fn fill_in(line: &str) -> (String, Option<usize>) {
+    let mut result = String::new();
+    let mut placeholder = None;
+
+    for c in line.chars() {
+        match c {
+            '{' => placeholder = placeholder.or(Some(result.chars().count())),
+            '}' => {}
+            c => result.push(c),
+        }
+    }
+
+    (result, placeholder)
+}
+
+/// Every snippet to pick from, with the picked one shown underneath.
+#[derive(Copy, Clone)]
+pub struct SnippetsUI<'a> {
+    pub selected: usize,
+    pub theme: &'a Theme,
+}
+
+impl Widget for SnippetsUI<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let title = make_title("Insert Template", true, self.theme);
+
+        let longest = SNIPPETS
+            .iter()
+            .map(|x| x.text.lines().count())
+            .max()
+            .unwrap_or(0);
+
+        // Borders, every name, a gap, and the longest snippet
+        let height = 2 + SNIPPETS.len() + 1 + longest;
+        let area = center(
+            area,
+            Constraint::Percentage(90),
+            Constraint::Length(height as u16),
+        );
+
+        let block = Block::bordered()
+            .border_set(border::ROUNDED)
+            .set_style(Style::reset().fg(self.theme.dialog))
+            .title(title);
+
+        let inner = block.inner(area);
+
+        block.render(area, buf);
+
+        Clear.render(inner, buf);
+
+        let mut lines = SNIPPETS
+            .iter()
+            .enumerate()
+            .map(|(idx, snippet)| {
+                if idx == self.selected {
+                    Line::from(format!("> {}", snippet.name).fg(self.theme.dest).bold())
+                } else {
+                    Line::from(format!("  {}", snippet.name))
+                }
+            })
+            .collect::<Vec<_>>();
+
+        lines.push(Line::default());
+
+        for line in SNIPPETS[self.selected].text.lines() {
+            lines.push(Line::from(fill_in(line).0.fg(self.theme.muted)));
+        }
+
+        Paragraph::new(Text::from(lines)).render(inner, buf);
+    }
+}
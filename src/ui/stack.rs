@@ -0,0 +1,68 @@
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::Stylize,
+    symbols::border,
+    text::{Line, Text},
+    widgets::{Block, Paragraph, Widget},
+};
+
+use crate::{
+    simulator::{format_word, Memory, Registers, SP},
+    util::{get_ranges_around, make_title, Theme},
+};
+
+/// Memory around `SP`, highest address first so the stack grows downward.
+#[derive(Copy, Clone)]
+pub struct StackUI<'a> {
+    pub memory: &'a Memory,
+    pub registers: &'a Registers,
+    /// Also show values as signed numbers
+    pub signed: bool,
+    pub theme: &'a Theme,
+}
+
+impl Widget for StackUI<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let title = make_title("Stack", false, self.theme);
+
+        let block = Block::bordered().title(title).border_set(border::ROUNDED);
+
+        let inner = block.inner(area);
+        let height = inner.height as u64;
+
+        let sp = self.registers.get(SP).unwrap();
+        let sp_slot = sp / 8;
+
+        // Mostly what's been pushed, with a couple of slots below SP.
+        let top = sp_slot.saturating_add(height.saturating_sub(3));
+
+        let lines = get_ranges_around(height, [sp_slot])
+            .into_iter()
+            .flatten()
+            .rev()
+            .skip_while(|&x| x > top)
+            .take(height as usize)
+            .map(|slot| {
+                let addr = slot.wrapping_mul(8);
+                let val = format_word(self.memory.get(addr).unwrap(), 64, self.signed);
+
+                let marker = if slot == sp_slot {
+                    "SP> ".fg(self.theme.pc).bold()
+                } else {
+                    "    ".into()
+                };
+
+                Line::from(vec![
+                    marker,
+                    format!("{:<5}", addr).bold().fg(self.theme.address),
+                    format!(": {val}").into(),
+                ])
+            })
+            .collect::<Vec<_>>();
+
+        block.render(area, buf);
+
+        Paragraph::new(Text::from(lines)).render(inner, buf);
+    }
+}
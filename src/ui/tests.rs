@@ -0,0 +1,1177 @@
+//! Renders each window against a fixed state and checks what comes out, so
+//! the layout math doesn't quietly shift as features land. Only the text is
+//! compared, apart from the tests which are about a highlight.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use ratatui::{
+    backend::TestBackend, buffer::Buffer, layout::Rect, style::Modifier, widgets::Widget, Terminal,
+};
+use tui_textarea::{Input, Key, TextArea};
+
+use super::{
+    instruction::{InstructionUI, InstructionUIState, PcHistory, PersistentInstructionState},
+    io::{LoadUIState, SaveUIState},
+    memory::{MemoryUI, MemoryUIState, PersistentMemoryState},
+    pipeline::PipelineUIState,
+    registers::{RegisterUI, RegisterUIState},
+    Focus, Tui,
+};
+use crate::{
+    simulator::{Instruction, Memory, Profile, Registers, Simulator, TickEffect},
+    util::{Keymap, Theme},
+};
+
+/// `widget` drawn into a buffer of the given size.
+fn render(widget: impl Widget, width: u16, height: u16) -> Buffer {
+    let mut buf = Buffer::empty(Rect::new(0, 0, width, height));
+    widget.render(buf.area, &mut buf);
+    buf
+}
+
+/// Each row of `buf`, without trailing spaces.
+fn text(buf: &Buffer) -> Vec<String> {
+    (0..buf.area.height)
+        .map(|y| {
+            let row = (0..buf.area.width)
+                .map(|x| buf[(x, y)].symbol())
+                .collect::<String>();
+
+            row.trim_end().to_string()
+        })
+        .collect()
+}
+
+/// Stopped on the `ADD`, with its operands and a couple of slots set.
+fn program() -> Simulator {
+    let (mut sim, _) = Simulator::from_listing(
+        "ADDI X1, XZR, #5\nADD X2, X1, X3\nSTUR X2, [X4, #8]\nCBNZ X2, #-2",
+    )
+    .unwrap();
+
+    sim.registers.pc = 1;
+    sim.registers.set(1, 5).unwrap();
+    sim.registers.set(3, 7).unwrap();
+    sim.registers.set(4, 16).unwrap();
+    sim.memory.set(16, 42).unwrap();
+    sim.memory.set(48, 9).unwrap();
+
+    sim
+}
+
+fn registers<'a>(
+    sim: &'a Simulator,
+    last_effect: TickEffect,
+    read_unwritten: &'a BTreeSet<u8>,
+    theme: &'a Theme,
+) -> RegisterUI<'a> {
+    RegisterUI {
+        registers: &sim.registers,
+        word_bits: sim.word_bits,
+        profile: sim.profile,
+        signed: false,
+        instrs: &sim.instructions,
+        read_unwritten,
+        last_effect,
+        theme,
+        state: None,
+    }
+}
+
+fn instructions<'a>(
+    sim: &'a Simulator,
+    hazards: &'a [(usize, usize)],
+    reachable: &'a HashSet<usize>,
+    branches_to_pc: &'a [u64],
+    persistent: &'a PersistentInstructionState,
+    theme: &'a Theme,
+) -> InstructionUI<'a> {
+    InstructionUI {
+        instrs: &sim.instructions,
+        registers: &sim.registers,
+        memory: &sim.memory,
+        pc: sim.registers.pc,
+        word_bits: sim.word_bits,
+        signed: false,
+        show_encoding: false,
+        hazards,
+        reachable,
+        notes: &sim.notes,
+        labels: &sim.labels,
+        entry: sim.entry,
+        branches_to_pc,
+        history: &[],
+        theme,
+        persistent,
+        state: None,
+    }
+}
+
+#[test]
+fn registers_mark_what_pc_uses() {
+    let sim = program();
+    let theme = Theme::dark();
+    let none = BTreeSet::new();
+
+    let buf = render(registers(&sim, TickEffect::None, &none, &theme), 50, 18);
+
+    assert_eq!(
+        text(&buf),
+        [
+            "╭ Registers ─────────────────────────────────────╮",
+            "│  X0 : 0                  X16: 0                │",
+            "│< X1 : 5                  X17: 0                │",
+            "│> X2 : 0                  X18: 0                │",
+            "│< X3 : 7                  X19: 0                │",
+            "│  X4 : 16                 X20: 0                │",
+            "│  X5 : 0                  X21: 0                │",
+            "│  X6 : 0                  X22: 0                │",
+            "│  X7 : 0                  X23: 0                │",
+            "│  X8 : 0                  X24: 0                │",
+            "│  X9 : 0                  X25: 0                │",
+            "│  X10: 0                  X26: 0                │",
+            "│  X11: 0                  X27: 0                │",
+            "│  X12: 0                  X28: 0                │",
+            "│  X13: 0                  X29: 0                │",
+            "│  X14: 0                  X30: 0                │",
+            "│  X15: 0                  PC : 4                │",
+            "╰────────────────────────────────────────────────╯",
+        ]
+    );
+}
+
+#[test]
+fn registers_highlight_the_last_write() {
+    let sim = program();
+    let theme = Theme::dark();
+    let none = BTreeSet::new();
+    let effect = TickEffect::RegWrite {
+        reg: 1,
+        old: 0,
+        new: 5,
+    };
+
+    let buf = render(registers(&sim, effect, &none, &theme), 50, 18);
+
+    // The value of X1, then of X0 which wasn't written
+    let changed = buf[(8, 2)].style();
+    let unchanged = buf[(8, 1)].style();
+
+    assert_eq!(buf[(8, 2)].symbol(), "5");
+    assert_eq!(changed.fg, Some(theme.dest));
+    assert!(changed.add_modifier.contains(Modifier::BOLD));
+    assert_ne!(unchanged.fg, Some(theme.dest));
+}
+
+#[test]
+fn registers_mark_both_roles() {
+    let (sim, _) = Simulator::from_listing("STUR X1, [X1, #0]").unwrap();
+    let theme = Theme::dark();
+    let none = BTreeSet::new();
+
+    let buf = render(registers(&sim, TickEffect::None, &none, &theme), 50, 18);
+
+    assert_eq!(
+        text(&buf)[2],
+        "│<@X1 : 0                  X17: 0                │"
+    );
+    assert_eq!(buf[(1, 2)].style().fg, Some(theme.source));
+}
+
+#[test]
+fn registers_flag_reads_before_writes() {
+    let sim = program();
+    let theme = Theme::dark();
+    let read_unwritten = BTreeSet::from([3]);
+
+    let buf = render(
+        registers(&sim, TickEffect::None, &read_unwritten, &theme),
+        50,
+        18,
+    );
+    let rows = text(&buf);
+
+    assert_eq!(
+        rows[4],
+        "│< X3 : 7 !                X19: 0                │"
+    );
+    assert_eq!(buf[(10, 4)].style().fg, Some(theme.warning));
+}
+
+#[test]
+fn memory_skips_runs_of_zeros() {
+    let sim = program();
+    let theme = Theme::dark();
+    let persistent = PersistentMemoryState::new();
+
+    let memory = MemoryUI {
+        memory: &sim.memory,
+        watchpoints: &BTreeSet::new(),
+        accesses: &BTreeMap::new(),
+        instrs: &sim.instructions,
+        registers: &sim.registers,
+        signed: false,
+        last_effect: TickEffect::None,
+        theme: &theme,
+        state: None,
+        persistent: &persistent,
+    };
+
+    assert_eq!(
+        text(&render(memory, 50, 10)),
+        [
+            "╭ Memory ────────────────────────────────────────╮",
+            "│  0    : 0                                      │",
+            "│  8    : 0                                      │",
+            "│  16   : 42                                     │",
+            "│  24   : 0                                      │",
+            "│  ... zeros ...                                 │",
+            "│  40   : 0                                      │",
+            "│  48   : 9                                      │",
+            "│  56   : 0                                      │",
+            "╰────────────────────────────────────────────────╯",
+        ]
+    );
+}
+
+#[test]
+fn instructions_point_at_pc() {
+    let sim = program();
+    let theme = Theme::dark();
+    let persistent = PersistentInstructionState::new();
+    let hazards = sim.detect_hazards();
+    let reachable = sim.reachable_instructions();
+    let branches = sim.branches_to(sim.registers.pc);
+
+    let instructions = instructions(&sim, &hazards, &reachable, &branches, &persistent, &theme);
+    let buf = render(instructions, 40, 12);
+
+    assert_eq!(
+        text(&buf),
+        [
+            "╭ Instructions ────────────────────────╮",
+            "│ ┐0 addi X1, X31, #5                  │",
+            "│>┘1 add  X2, X1, X3  ← 3              │",
+            "│  2 stur X2, [X4, #8]                 │",
+            "│  3 cbnz X2, #-2                      │",
+            "│    END                               │",
+            "│                                      │",
+            "│╭ Explanation ───────────────────────╮│",
+            "││X2 = X1 + X3                        ││",
+            "││X2 = 5 + 7 = 12                     ││",
+            "│╰────────────────────────────────────╯│",
+            "╰──────────────────────────────────────╯",
+        ]
+    );
+    assert_eq!(buf[(1, 2)].style().fg, Some(theme.pc));
+}
+
+#[test]
+fn instructions_follow_pc_to_the_end() {
+    let listing = vec!["NOP"; 500].join("\n");
+    let (mut sim, _) = Simulator::from_listing(&listing).unwrap();
+    sim.registers.pc = 495;
+
+    let theme = Theme::dark();
+    let persistent = PersistentInstructionState::new();
+    let reachable = sim.reachable_instructions();
+
+    let widget = instructions(&sim, &[], &reachable, &[], &persistent, &theme);
+
+    assert_eq!(
+        text(&render(widget, 30, 16))[..11],
+        [
+            "╭ Instructions ──────────────╮",
+            "│▲ 490 nop                   │",
+            "│  491 nop                   │",
+            "│  492 nop                   │",
+            "│  493 nop                   │",
+            "│  494 nop                   │",
+            "│> 495 nop                   │",
+            "│  496 nop                   │",
+            "│  497 nop                   │",
+            "│  498 nop                   │",
+            "│▼ 499 nop                   │",
+        ]
+    );
+
+    // Near the end it stops scrolling rather than leave space below
+    sim.registers.pc = 499;
+    let widget = instructions(&sim, &[], &reachable, &[], &persistent, &theme);
+    let rows = text(&render(widget, 30, 16));
+
+    assert_eq!(rows[1], "│▲ 491 nop                   │");
+    assert_eq!(rows[9], "│> 499 nop                   │");
+    assert_eq!(rows[10], "│      END                   │");
+}
+
+#[test]
+fn branches_show_both_ends() {
+    let mut sim = program();
+    sim.registers.pc = 3;
+
+    let theme = Theme::dark();
+    let persistent = PersistentInstructionState::new();
+    let reachable = sim.reachable_instructions();
+    let branches = sim.branches_to(sim.registers.pc);
+
+    let instructions = instructions(&sim, &[], &reachable, &branches, &persistent, &theme);
+    let buf = render(instructions, 40, 12);
+    let rows = text(&buf);
+
+    assert_eq!(rows[2], "│> 1 add  X2, X1, X3                   │");
+    assert_eq!(rows[4], "│> 3 cbnz X2, #-2  → 1                 │");
+    assert_eq!(buf[(1, 2)].style().fg, Some(theme.dest));
+    assert_eq!(buf[(1, 4)].style().fg, Some(theme.pc));
+}
+
+#[test]
+fn history_traces_the_loop() {
+    let (mut sim, _) =
+        Simulator::from_listing("ADDI X0, XZR, #3\nSUBI X0, X0, #1\nCBNZ X0, #-1\nHALT").unwrap();
+
+    let mut state = InstructionUIState::new();
+    let mut last_effect = TickEffect::None;
+    let mut history = PcHistory::new(6);
+
+    state.run_for(&mut sim, &mut last_effect, &mut history, 100);
+    assert_eq!(history.pcs(), [2, 1, 2, 1, 2, 3]);
+
+    let theme = Theme::dark();
+    let persistent = PersistentInstructionState::new();
+    let reachable = sim.reachable_instructions();
+    let pcs = history.pcs();
+
+    let wide = InstructionUI {
+        history: &pcs,
+        ..instructions(&sim, &[], &reachable, &[], &persistent, &theme)
+    };
+    assert_eq!(
+        text(&render(wide, 40, 7))[6],
+        "╰ 2 → 1 → 2 → 1 → 2 → 3 ───────────────╯"
+    );
+
+    let narrow = InstructionUI {
+        history: &pcs,
+        ..instructions(&sim, &[], &reachable, &[], &persistent, &theme)
+    };
+    assert_eq!(text(&render(narrow, 20, 7))[6], "╰ … → 1 → 2 → 3 ───╯");
+
+    history.clear();
+    assert!(history.pcs().is_empty());
+}
+
+#[test]
+fn pasting_inserts_each_line() {
+    let mut sim = program();
+    let mut state = InstructionUIState::new();
+
+    state.paste("ADDI X5, X5, #1\r\nnot code\nNOP", &mut sim, true);
+
+    let expected = [
+        "ADDI X1, XZR, #5",
+        "ADDI X5, X5, #1",
+        "//not code",
+        "NOP",
+        "ADD X2, X1, X3",
+        "STUR X2, [X4, #8]",
+        // Still goes back to the add
+        "CBNZ X2, #-2",
+    ]
+    .map(|x| x.parse::<Instruction>().unwrap());
+
+    assert_eq!(sim.instructions, expected);
+    assert_eq!(sim.registers.pc, 1);
+}
+
+#[test]
+fn inserting_into_a_loop_fixes_its_branches() {
+    let listing = "CBZ X0, #4\nADDI X0, XZR, #3\nSUBI X0, X0, #1\nCBNZ X0, #-1\nHALT";
+    let keymap = Keymap::default();
+
+    let program_after = |fix_branches, keys: &[char]| {
+        let (mut sim, _) = Simulator::from_listing(listing).unwrap();
+        sim.registers.pc = 2;
+
+        let mut state = InstructionUIState::new();
+        let mut last_effect = TickEffect::None;
+        let mut history = PcHistory::new(10);
+
+        for &c in keys {
+            let input = Input {
+                key: Key::Char(c),
+                ..Default::default()
+            };
+
+            state.handle(
+                input,
+                &mut sim,
+                &keymap,
+                fix_branches,
+                &mut last_effect,
+                &mut history,
+            );
+        }
+
+        sim.instructions
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>()
+    };
+
+    // Duplicating the `SUBI` puts another line in the loop body
+    assert_eq!(
+        program_after(true, &['d']),
+        [
+            "cbz  X0, #5",
+            "addi X0, X31, #3",
+            "subi X0, X0, #1",
+            "subi X0, X0, #1",
+            "cbnz X0, #-2",
+            "halt",
+        ]
+    );
+
+    // Deleting it again puts every branch back
+    assert_eq!(program_after(true, &['d', 'x']), program_after(true, &[]));
+
+    // Left alone when turned off
+    assert_eq!(
+        program_after(false, &['d']),
+        [
+            "cbz  X0, #4",
+            "addi X0, X31, #3",
+            "subi X0, X0, #1",
+            "subi X0, X0, #1",
+            "cbnz X0, #-1",
+            "halt",
+        ]
+    );
+}
+
+#[test]
+fn comments_run_off_the_end() {
+    let mut sim = Simulator::new();
+    sim.instructions = ["ADDI X1, XZR, #1", "// done", "", "// really"]
+        .map(|x| x.parse().unwrap())
+        .into();
+    let mut ticked = sim.clone();
+
+    let mut state = InstructionUIState::new();
+    let mut last_effect = TickEffect::None;
+    let mut history = PcHistory::new(10);
+
+    assert_eq!(
+        state.run_all(&mut sim, &mut last_effect, &mut history, 100),
+        (4, true)
+    );
+    assert_eq!(state.warning, None);
+    assert!(sim.is_finished());
+
+    // Running without the UI stops in the same place
+    assert_eq!(
+        ticked.tick_n(100).unwrap(),
+        (4, crate::simulator::RunningState::ShouldStop)
+    );
+    assert!(ticked == sim);
+
+    let theme = Theme::dark();
+    let persistent = PersistentInstructionState::new();
+    let reachable = sim.reachable_instructions();
+    let rows = text(&render(
+        instructions(&sim, &[], &reachable, &[], &persistent, &theme),
+        30,
+        12,
+    ));
+
+    assert_eq!(rows[5], "│>   END                     │");
+    assert_eq!(rows[9], "││Program finished          ││");
+}
+
+#[test]
+fn branches_show_their_labels() {
+    let (mut sim, _) =
+        Simulator::from_listing("loop: SUBI X0, X0, #1\n      CBNZ X0, loop").unwrap();
+    sim.registers.pc = 1;
+
+    let theme = Theme::dark();
+    let persistent = PersistentInstructionState::new();
+    let reachable = sim.reachable_instructions();
+
+    let instructions = instructions(&sim, &[], &reachable, &[], &persistent, &theme);
+
+    assert_eq!(
+        text(&render(instructions, 40, 10)),
+        [
+            "╭ Instructions ────────────────────────╮",
+            "│>┐0 subi X0, X0, #1  loop:            │",
+            "│>┘1 cbnz X0, loop  → 0                │",
+            "│    END                               │",
+            "│                                      │",
+            "│╭ Explanation ───────────────────────╮│",
+            "││if X0 != 0: PC = PC + -1 * 4 (loop) ││",
+            "││if 0 != 0: PC = 4 + -1 * 4 = 0      ││",
+            "│╰────────────────────────────────────╯│",
+            "╰──────────────────────────────────────╯",
+        ]
+    );
+}
+
+#[test]
+fn instructions_point_at_end_once_finished() {
+    let mut sim = program();
+    sim.registers.pc = sim.instructions.len() as u64;
+
+    let theme = Theme::dark();
+    let persistent = PersistentInstructionState::new();
+    let hazards = sim.detect_hazards();
+    let reachable = sim.reachable_instructions();
+    let branches = sim.branches_to(sim.registers.pc);
+
+    let instructions = instructions(&sim, &hazards, &reachable, &branches, &persistent, &theme);
+    let rows = text(&render(instructions, 40, 12));
+
+    assert_eq!(rows[2], "│  1 add  X2, X1, X3                   │");
+    assert_eq!(rows[5], "│>   END                               │");
+}
+
+#[test]
+fn editing_an_empty_program_never_panics() {
+    let mut sim = Simulator::new();
+    let keymap = Keymap::default();
+    let mut state = InstructionUIState::new();
+    let mut last_effect = TickEffect::None;
+    let mut history = PcHistory::new(10);
+
+    let mut press = |state: &mut InstructionUIState, sim: &mut Simulator, key, ctrl| {
+        let input = Input {
+            key,
+            ctrl,
+            ..Default::default()
+        };
+
+        state.handle(input, sim, &keymap, true, &mut last_effect, &mut history);
+    };
+
+    // Everything outside the editor, with nothing to work on
+    for key in [
+        Key::Up,
+        Key::Down,
+        Key::Enter,
+        Key::Char('d'),
+        Key::Char('x'),
+        Key::Char('/'),
+        Key::Char('n'),
+        Key::Char('e'),
+    ] {
+        press(&mut state, &mut sim, key, false);
+    }
+    assert!(sim.instructions.is_empty());
+    assert_eq!(sim.registers.pc, 0);
+
+    // Into the editor, which makes a line to edit, and every way out of it
+    press(&mut state, &mut sim, Key::Char('r'), true);
+    for key in [Key::Up, Key::Down, Key::Left, Key::Right, Key::Backspace] {
+        press(&mut state, &mut sim, key, false);
+    }
+    assert_eq!(sim.instructions, [Instruction::None]);
+
+    for c in "NOP".chars() {
+        press(&mut state, &mut sim, Key::Char(c), false);
+    }
+    press(&mut state, &mut sim, Key::Enter, false);
+    assert_eq!(sim.instructions, [Instruction::Nop, Instruction::None]);
+    assert_eq!(sim.registers.pc, 1);
+
+    // Joins the new line back onto the first
+    press(&mut state, &mut sim, Key::Backspace, false);
+    press(&mut state, &mut sim, Key::Esc, false);
+    assert_eq!(sim.instructions, [Instruction::Nop]);
+    assert_eq!(sim.registers.pc, 0);
+
+    // And back down to nothing
+    press(&mut state, &mut sim, Key::Char('x'), false);
+    press(&mut state, &mut sim, Key::Char('x'), false);
+    press(&mut state, &mut sim, Key::Down, false);
+    assert!(sim.instructions.is_empty());
+    assert_eq!(sim.registers.pc, 0);
+}
+
+#[test]
+fn writing_xzr_warns() {
+    let (mut sim, _) = Simulator::from_listing("ADDI X1, XZR, #2\nADD XZR, X1, X1").unwrap();
+    let mut state = InstructionUIState::new();
+    let mut last_effect = TickEffect::None;
+    let mut history = PcHistory::new(10);
+
+    state.step(&mut sim, &mut last_effect, &mut history);
+    assert_eq!(state.warning, None);
+
+    state.step(&mut sim, &mut last_effect, &mut history);
+    assert_eq!(
+        state.warning.as_deref(),
+        Some("Instruction 1 wrote to XZR, which always reads as zero, so the result was lost")
+    );
+    assert_eq!(sim.registers.get(31).unwrap(), 0);
+}
+
+#[test]
+fn reserved_profile_names_and_warns() {
+    let (mut sim, _) = Simulator::from_listing("ADDI X16, XZR, #1").unwrap();
+    sim.profile = Profile::Reserved;
+
+    let mut state = InstructionUIState::new();
+    let mut last_effect = TickEffect::None;
+    let mut history = PcHistory::new(10);
+
+    state.step(&mut sim, &mut last_effect, &mut history);
+    assert_eq!(
+        state.warning.as_deref(),
+        Some("Instruction 0 wrote to IP0, which is reserved for the linker")
+    );
+
+    let theme = Theme::dark();
+    let none = BTreeSet::new();
+    let rows = text(&render(
+        registers(&sim, TickEffect::None, &none, &theme),
+        50,
+        18,
+    ));
+
+    assert_eq!(
+        rows[1],
+        "│  X0 : 0                  IP0: 1                │"
+    );
+    assert_eq!(
+        rows[13],
+        "│  X12: 0                  SP : 0                │"
+    );
+    assert_eq!(
+        rows[15],
+        "│  X14: 0                  LR : 0                │"
+    );
+}
+
+#[test]
+fn pc_is_typed_as_a_byte_address() {
+    let mut sim = program();
+    let theme = Theme::dark();
+    let keymap = Keymap::default();
+    let none = BTreeSet::new();
+    let mut state = RegisterUIState::new(31);
+
+    let press = |state: &mut RegisterUIState, sim: &mut Simulator, key, ctrl| {
+        let input = Input {
+            key,
+            ctrl,
+            ..Default::default()
+        };
+
+        state.handle(input, sim, &keymap);
+    };
+
+    press(&mut state, &mut sim, Key::Char('r'), true);
+    press(&mut state, &mut sim, Key::Char('8'), false);
+
+    let mut registers = registers(&sim, TickEffect::None, &none, &theme);
+    registers.state = Some(&state);
+
+    let rows = text(&render(registers, 60, 18));
+    assert_eq!(
+        rows[16],
+        "│  X15: 0                       PC : 8      → instruction 2│"
+    );
+
+    press(&mut state, &mut sim, Key::Enter, false);
+    assert_eq!(sim.registers.pc, 2);
+
+    press(&mut state, &mut sim, Key::Char('r'), true);
+    press(&mut state, &mut sim, Key::Char('6'), false);
+    press(&mut state, &mut sim, Key::Enter, false);
+
+    assert_eq!(sim.registers.pc, 2);
+    assert_eq!(
+        state.message.as_deref(),
+        Some("Byte address \"6\" is not a multiple of 4!")
+    );
+}
+
+#[test]
+fn pc_edits_must_be_aligned() {
+    let mut sim = program();
+    let keymap = Keymap::default();
+    let mut state = RegisterUIState::new(31);
+
+    let mut set_pc = |sim: &mut Simulator, text: &str| {
+        let typed = text.chars().map(|c| (Key::Char(c), false));
+        let keys = [(Key::Char('r'), true)]
+            .into_iter()
+            .chain(typed)
+            .chain([(Key::Enter, false)]);
+
+        for (key, ctrl) in keys {
+            let input = Input {
+                key,
+                ctrl,
+                ..Default::default()
+            };
+
+            state.handle(input, sim, &keymap);
+        }
+
+        state.message.take()
+    };
+
+    assert_eq!(set_pc(&mut sim, "8"), None);
+    assert_eq!(sim.registers.pc, 2);
+
+    assert_eq!(
+        set_pc(&mut sim, "7").as_deref(),
+        Some("Byte address \"7\" is not a multiple of 4!")
+    );
+    assert_eq!(
+        set_pc(&mut sim, "-4").as_deref(),
+        Some("Byte address \"-4\" is out of range!")
+    );
+    assert_eq!(sim.registers.pc, 2);
+}
+
+#[test]
+fn memory_goto_takes_aligned_addresses() {
+    let mut memory = Memory::new();
+    let registers = Registers::new();
+    let keymap = Keymap::default();
+    let mut persistent = PersistentMemoryState::new();
+    let mut state = MemoryUIState::new(0);
+
+    let mut press = |state: &mut MemoryUIState, memory: &mut Memory, keys: Vec<(Key, bool)>| {
+        for (key, ctrl) in keys {
+            let input = Input {
+                key,
+                ctrl,
+                ..Default::default()
+            };
+
+            state.handle(
+                input,
+                memory,
+                &mut BTreeSet::new(),
+                &mut persistent,
+                &registers,
+                &keymap,
+            );
+        }
+    };
+
+    // Go to the address, then write a value there to see where it went
+    let mut goto = |state: &mut MemoryUIState, memory: &mut Memory, addr: &str| {
+        let mut keys: Vec<_> = format!("g{addr}")
+            .chars()
+            .map(|c| (Key::Char(c), false))
+            .collect();
+        keys.push((Key::Enter, false));
+        press(state, memory, keys);
+        let message = state.message.clone();
+
+        let keys = [
+            (Key::Char('r'), true),
+            (Key::Char('7'), false),
+            (Key::Enter, false),
+        ];
+        press(state, memory, keys.into());
+        message
+    };
+
+    assert_eq!(goto(&mut state, &mut memory, "16"), None);
+    assert_eq!(memory.get(16).unwrap(), 7);
+
+    assert_eq!(
+        goto(&mut state, &mut memory, "12").as_deref(),
+        Some("Byte address 12 is not a multiple of 8!")
+    );
+    assert_eq!(memory.get(8).unwrap(), 0);
+
+    assert_eq!(
+        goto(&mut state, &mut memory, "-8").as_deref(),
+        Some("Byte address -8 is out of range!")
+    );
+    assert_eq!(memory.get(16).unwrap(), 7);
+}
+
+#[test]
+fn arrays_fill_consecutive_slots() {
+    let mut memory = Memory::new();
+    let registers = Registers::new();
+    let keymap = Keymap::default();
+    let mut persistent = PersistentMemoryState::new();
+    let mut state = MemoryUIState::new(2);
+
+    let mut fill = |memory: &mut Memory, values: &str| {
+        let typed = values.chars().map(Key::Char);
+
+        for key in [Key::Char('a')]
+            .into_iter()
+            .chain(typed)
+            .chain([Key::Enter])
+        {
+            let input = Input {
+                key,
+                ..Default::default()
+            };
+
+            state.handle(
+                input,
+                memory,
+                &mut BTreeSet::new(),
+                &mut persistent,
+                &registers,
+                &keymap,
+            );
+        }
+
+        state.message.take()
+    };
+
+    assert_eq!(fill(&mut memory, "1 2 3"), None);
+    assert_eq!(
+        [8, 16, 24, 32, 40].map(|addr| memory.get(addr).unwrap()),
+        [0, 1, 2, 3, 0]
+    );
+
+    // Nothing is written unless every value is good
+    assert_eq!(
+        fill(&mut memory, "7, x, 9").as_deref(),
+        Some("\"x\" is not a number!")
+    );
+    assert_eq!(memory.get(16).unwrap(), 1);
+}
+
+#[test]
+fn memory_values_fit_in_64_bits() {
+    let mut sim = program();
+    let theme = Theme::dark();
+    let keymap = Keymap::default();
+    let mut persistent = PersistentMemoryState::new();
+    let mut state = MemoryUIState::new(0);
+
+    let mut enter = |state: &mut MemoryUIState, sim: &mut Simulator, text: &str| {
+        let inputs = [(Key::Char('r'), true)]
+            .into_iter()
+            .chain(text.chars().map(|c| (Key::Char(c), false)))
+            .chain([(Key::Enter, false)]);
+
+        for (key, ctrl) in inputs {
+            let input = Input {
+                key,
+                ctrl,
+                ..Default::default()
+            };
+
+            state.handle(
+                input,
+                &mut sim.memory,
+                &mut BTreeSet::new(),
+                &mut persistent,
+                &sim.registers,
+                &keymap,
+            );
+        }
+    };
+
+    enter(&mut state, &mut sim, "-1");
+    assert_eq!(sim.memory.get(0).unwrap(), u64::MAX);
+    assert_eq!(state.message, None);
+
+    let memory = MemoryUI {
+        memory: &sim.memory,
+        watchpoints: &BTreeSet::new(),
+        accesses: &BTreeMap::new(),
+        instrs: &sim.instructions,
+        registers: &sim.registers,
+        signed: true,
+        last_effect: TickEffect::None,
+        theme: &theme,
+        state: None,
+        persistent: &PersistentMemoryState::new(),
+    };
+    assert_eq!(
+        text(&render(memory, 80, 10))[1],
+        "│  0    : 18446744073709551615 (-1)                                            │"
+    );
+
+    enter(&mut state, &mut sim, "18446744073709551615");
+    assert_eq!(sim.memory.get(0).unwrap(), u64::MAX);
+    assert_eq!(state.message, None);
+
+    enter(&mut state, &mut sim, "18446744073709551616");
+    assert_eq!(sim.memory.get(0).unwrap(), u64::MAX);
+    assert_eq!(
+        state.message.as_deref(),
+        Some("\"18446744073709551616\" is out of range!")
+    );
+}
+
+/// The `load` subcommand hands the TUI a program with PC wherever its entry is,
+/// which shouldn't be left off screen until the first step.
+#[test]
+fn starts_scrolled_to_pc() {
+    let listing = vec!["NOP"; 500].join("\n");
+    let (mut sim, _) = Simulator::from_listing(&listing).unwrap();
+    sim.entry = 300;
+    sim.reset_pc();
+
+    let mut tui = Tui::new(sim, Theme::dark(), Keymap::default());
+    let mut terminal = Terminal::new(TestBackend::new(120, 40)).unwrap();
+    let frame = terminal.draw(|frame| tui.draw(frame)).unwrap();
+
+    assert!(text(frame.buffer)
+        .iter()
+        .any(|row| row.starts_with("│> 300 nop")));
+}
+
+#[test]
+fn inspecting_shows_every_base() {
+    let mut sim = program();
+    sim.registers.set(1, 0xFFFF_FFFF_4869_2100).unwrap();
+
+    let theme = Theme::dark();
+    let keymap = Keymap::default();
+    let none = BTreeSet::new();
+    let mut state = RegisterUIState::new(1);
+
+    let input = Input {
+        key: Key::Char('i'),
+        ..Default::default()
+    };
+    state.handle(input, &mut sim, &keymap);
+
+    let mut registers = registers(&sim, TickEffect::None, &none, &theme);
+    registers.state = Some(&state);
+
+    assert_eq!(
+        text(&render(registers, 60, 18))[9..],
+        [
+            "│╭ Value ─────────────────────────────────────────────────╮│",
+            "││Unsigned 18446744070629433600                           ││",
+            "││Signed   -3080118016                                    ││",
+            "││Hex      0xFFFFFFFF48692100                             ││",
+            "││Binary   11111111 11111111 11111111 11111111            ││",
+            "││         01001000 01101001 00100001 00000000            ││",
+            "││ASCII    ....Hi!.                                       ││",
+            "│╰────────────────────────────────────────────────────────╯│",
+            "╰──────────────────────────────────────────────────────────╯",
+        ]
+    );
+}
+
+#[test]
+fn loading_a_bad_register_is_refused() {
+    let mut bad = Simulator::new();
+    bad.instructions = vec![Instruction::Add(99, 1, 2)];
+
+    let path = std::env::temp_dir().join("cs251simulator_bad_register.json");
+    std::fs::write(&path, serde_json::to_string(&bad).unwrap()).unwrap();
+
+    let mut sim = program();
+    let before = sim.instructions.clone();
+
+    let mut state = LoadUIState::new(Theme::dark(), vec![]);
+    state.area = TextArea::from([path.to_string_lossy().into_owned()]);
+
+    let input = Input {
+        key: Key::Enter,
+        ..Default::default()
+    };
+    assert!(!state.handle(input, &mut sim));
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(!state.loaded);
+    assert_eq!(sim.instructions, before);
+    assert_eq!(
+        text(&render(&state, 80, 8))[2..6],
+        [
+            "          ╭ Load From File ──────────────────────────────────────────╮",
+            "          │Not loading, as the program has 1 problem(s):             │",
+            "          │  0: Register X99 does not exist!                         │",
+            "          ╰──────────────────────────────────────────────────────────╯",
+        ]
+    );
+}
+
+#[test]
+fn saving_over_a_file_asks_first() {
+    let path = std::env::temp_dir().join("cs251simulator_overwrite.json");
+    let sim = program();
+
+    let save = |answer: char| {
+        std::fs::write(&path, "reference").unwrap();
+
+        let mut state = SaveUIState::new(Theme::dark());
+        state.area = TextArea::from([path.to_string_lossy().into_owned()]);
+
+        let press = |state: &mut SaveUIState, key| {
+            let input = Input {
+                key,
+                ..Default::default()
+            };
+
+            state.handle(input, &sim)
+        };
+
+        assert!(!press(&mut state, Key::Enter));
+        assert!(state.confirm_overwrite);
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "reference",
+            "written before {answer:?}"
+        );
+
+        let done = press(&mut state, Key::Char(answer));
+        (done, std::fs::read_to_string(&path).unwrap())
+    };
+
+    for answer in ['y', 'Y'] {
+        let (done, contents) = save(answer);
+
+        assert!(done);
+        assert!(serde_json::from_str::<Simulator>(&contents).unwrap() == sim);
+    }
+
+    assert_eq!(save('n'), (false, "reference".to_string()));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn loading_some_sections_keeps_the_rest() {
+    let mut saved = Simulator::new();
+    saved.memory.set(0, 1).unwrap();
+    saved.registers.pc = 10;
+    saved.instructions = vec![Instruction::Nop];
+
+    let path = std::env::temp_dir().join("cs251simulator_partial_load.json");
+    std::fs::write(&path, serde_json::to_string(&saved).unwrap()).unwrap();
+
+    let mut tui = Tui::new(program(), Theme::dark(), Keymap::default());
+    tui.state.registers.pc = 3;
+    tui.persistent_memory.selected.set(5);
+    tui.persistent_memory.bookmarks.insert(2, "array".into());
+
+    let load = |tui: &mut Tui, load_reg, load_mem| {
+        let mut state = LoadUIState::new(Theme::dark(), vec![]);
+        state.load_reg = load_reg;
+        state.load_mem = load_mem;
+        state.load_instr = false;
+        state.area = TextArea::from([path.to_string_lossy().into_owned()]);
+
+        tui.focus = Focus::Load(state);
+        tui.handle_input(Input {
+            key: Key::Enter,
+            ..Default::default()
+        });
+    };
+
+    // Memory alone leaves the program where it was, but forgets the slots
+    // picked out in the old memory
+    load(&mut tui, false, true);
+
+    assert_eq!(tui.state.registers.pc, 3);
+    assert_eq!(tui.state.instructions, program().instructions);
+    assert_eq!(tui.state.memory, saved.memory);
+    assert_eq!(tui.persistent_memory.selected.get(), 0);
+    assert!(tui.persistent_memory.bookmarks.is_empty());
+
+    // Registers alone keep the memory selection, and PC in the program
+    tui.persistent_memory.selected.set(5);
+    load(&mut tui, true, false);
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(tui.state.registers.pc, 4);
+    assert_eq!(tui.persistent_memory.selected.get(), 5);
+}
+
+#[test]
+fn pipeline_restarts_where_it_was_opened() {
+    let (mut sim, _) = Simulator::from_listing("ADDI X1, X1, #1\nSTUR X1, [XZR, #0]").unwrap();
+    let keymap = Keymap::default();
+    sim.registers.set(1, 5).unwrap();
+
+    let mut state = PipelineUIState::new(&sim);
+    let before = sim.clone();
+
+    let press = |state: &mut PipelineUIState, sim: &mut Simulator, key| {
+        let input = Input {
+            key,
+            ..Default::default()
+        };
+
+        state.handle(input, sim, &keymap);
+    };
+
+    for _ in 0..4 {
+        press(&mut state, &mut sim, Key::Enter);
+    }
+
+    assert_eq!(sim.registers.get(1).unwrap(), 6);
+    assert_eq!(sim.memory.get(0).unwrap(), 6);
+
+    press(&mut state, &mut sim, Key::Char('r'));
+
+    assert!(sim == before);
+    assert!(state.pipeline.history.is_empty());
+}
+
+#[test]
+fn starting_over_forgets_the_run() {
+    let mut tui = Tui::new(program(), Theme::dark(), Keymap::default());
+    let press = |tui: &mut Tui, key| {
+        tui.handle_input(Input {
+            key,
+            ..Default::default()
+        })
+    };
+
+    press(&mut tui, Key::Enter);
+    press(&mut tui, Key::Enter);
+    assert_eq!(tui.state.registers.pc, 3);
+    assert!(!tui.state.accesses.is_empty());
+    assert!(tui.state.read_unwritten.contains(&3));
+    assert_eq!(tui.history.pcs(), [1, 2]);
+
+    press(&mut tui, Key::Char('0'));
+
+    assert_eq!(tui.state.registers.pc, 0);
+    assert!(tui.state.accesses.is_empty());
+    assert!(!tui.state.read_unwritten.contains(&3));
+    assert!(matches!(tui.last_effect, TickEffect::None));
+    assert!(tui.history.pcs().is_empty());
+}
+
+#[test]
+fn only_a_run_still_going_is_a_warning() {
+    let color = |listing: &str, burst| {
+        let (sim, _) = Simulator::from_listing(listing).unwrap();
+        let mut tui = Tui::new(sim, Theme::dark(), Keymap::default());
+        tui.burst = burst;
+        tui.handle_input(Input {
+            key: Key::Char('>'),
+            ..Default::default()
+        });
+
+        let mut terminal = Terminal::new(TestBackend::new(120, 40)).unwrap();
+        let frame = terminal.draw(|frame| tui.draw(frame)).unwrap();
+        let rows = text(frame.buffer);
+        let row = rows.iter().position(|x| x.contains("Ran: ")).unwrap();
+        let (before, _) = rows[row].split_once("Ran: ").unwrap();
+        // In cells, not bytes, as the borders are wider than one
+        let column = before.chars().count() + "Ran: ".len();
+
+        frame.buffer[(column as u16, row as u16)].fg
+    };
+
+    // Stopping on the last instruction allowed isn't being stuck
+    assert_eq!(color("NOP\nNOP\nNOP", 3), Theme::dark().key_hint);
+    assert_eq!(color("NOP\nHALT\nNOP", 2), Theme::dark().key_hint);
+    // Still going at the end of the burst, however big it is
+    assert_eq!(color("B #0", 3), Theme::dark().warning);
+    assert_eq!(color("B #0", 1000), Theme::dark().warning);
+}
@@ -0,0 +1,405 @@
+use std::{collections::HashMap, fmt::Display};
+
+use color_eyre::eyre::{bail, eyre, Result};
+use tui_textarea::{Input, Key};
+
+/// Everything which can be bound to a key. Arrow keys, and `<Enter>` and
+/// `<Esc>` while typing, aren't listed as they behave the same everywhere.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    Quit,
+    /// Start picking a window
+    Window,
+    Help,
+    PickInstructions,
+    PickRegisters,
+    PickMemory,
+    Save,
+    Load,
+    Pipeline,
+    Theme,
+    /// Also show values as signed numbers
+    Signed,
+    /// Whether adding and removing instructions fixes up branches
+    FixBranches,
+    /// Show the machine code for each instruction
+    Encoding,
+    /// Split the memory view, following `SP` in the second pane
+    Stack,
+    /// List everything wrong with the program
+    Problems,
+    /// Also list what might be wrong with it, in the problems overlay
+    Lints,
+    /// Start recording key presses as a macro, or stop
+    Record,
+    /// Press every key in the macro again
+    Replay,
+    /// Run one instruction, or one pipeline cycle
+    Step,
+    /// Keep running instructions on a timer, or stop
+    Play,
+    /// Run instructions until the program stops, up to a limit
+    RunAll,
+    /// Run several instructions at once, as many as `burst` in the preferences
+    Burst,
+    /// Run more instructions a second while playing
+    Faster,
+    /// Run fewer instructions a second while playing
+    Slower,
+    /// Put PC back at the entry and forget what running has done
+    Reset,
+    /// Edit an instruction or register, or replace a memory slot
+    Edit,
+    /// Copy the instruction at PC to just below it
+    Duplicate,
+    /// Remove the instruction at PC
+    Delete,
+    /// Comment out the instruction at PC, or bring it back
+    ToggleComment,
+    /// Write a note beside the instruction at PC, or change it
+    Note,
+    /// Insert a common pattern of instructions at PC
+    Snippet,
+    /// Start the program from the instruction at PC, or from the top again
+    Entry,
+    WordSize,
+    /// Show the selected value in every base, and as text
+    Inspect,
+    Goto,
+    /// Go to the slot a register points at
+    GotoRegister,
+    /// Go to the slot the selected slot points at
+    Follow,
+    /// Go back to where the last `Follow` was from
+    Back,
+    /// Write several values into the slots from the selected one on
+    FillArray,
+    /// Pin the selected slot, with a label, or unpin it
+    Bookmark,
+    /// Show more of the slots around the ones in use
+    MoreContext,
+    /// Show fewer of the slots around the ones in use
+    LessContext,
+    /// Color slots by how often they've been loaded from and stored to
+    Heat,
+    Watch,
+    ClearSlot,
+    ClearAll,
+    /// Restart the pipeline from where it was opened
+    Restart,
+}
+
+impl Action {
+    pub const ALL: [Action; 47] = [
+        Action::Quit,
+        Action::Window,
+        Action::Help,
+        Action::PickInstructions,
+        Action::PickRegisters,
+        Action::PickMemory,
+        Action::Save,
+        Action::Load,
+        Action::Pipeline,
+        Action::Theme,
+        Action::Signed,
+        Action::FixBranches,
+        Action::Encoding,
+        Action::Stack,
+        Action::Problems,
+        Action::Lints,
+        Action::Record,
+        Action::Replay,
+        Action::Step,
+        Action::Play,
+        Action::RunAll,
+        Action::Burst,
+        Action::Faster,
+        Action::Slower,
+        Action::Reset,
+        Action::Edit,
+        Action::Duplicate,
+        Action::Delete,
+        Action::ToggleComment,
+        Action::Note,
+        Action::Snippet,
+        Action::Entry,
+        Action::WordSize,
+        Action::Inspect,
+        Action::Goto,
+        Action::GotoRegister,
+        Action::Follow,
+        Action::Back,
+        Action::FillArray,
+        Action::Bookmark,
+        Action::MoreContext,
+        Action::LessContext,
+        Action::Heat,
+        Action::Watch,
+        Action::ClearSlot,
+        Action::ClearAll,
+        Action::Restart,
+    ];
+
+    /// The name used in keymap files.
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::Window => "window",
+            Action::Help => "help",
+            Action::PickInstructions => "pick_instructions",
+            Action::PickRegisters => "pick_registers",
+            Action::PickMemory => "pick_memory",
+            Action::Save => "save",
+            Action::Load => "load",
+            Action::Pipeline => "pipeline",
+            Action::Theme => "theme",
+            Action::Signed => "signed",
+            Action::FixBranches => "fix_branches",
+            Action::Encoding => "encoding",
+            Action::Stack => "stack",
+            Action::Problems => "problems",
+            Action::Lints => "lints",
+            Action::Record => "record",
+            Action::Replay => "replay",
+            Action::Step => "step",
+            Action::Play => "play",
+            Action::RunAll => "run_all",
+            Action::Burst => "burst",
+            Action::Faster => "faster",
+            Action::Slower => "slower",
+            Action::Reset => "reset",
+            Action::Edit => "edit",
+            Action::Duplicate => "duplicate",
+            Action::Delete => "delete",
+            Action::ToggleComment => "toggle_comment",
+            Action::Note => "note",
+            Action::Snippet => "snippet",
+            Action::Entry => "entry",
+            Action::WordSize => "word_size",
+            Action::Inspect => "inspect",
+            Action::Goto => "goto",
+            Action::GotoRegister => "goto_register",
+            Action::Follow => "follow",
+            Action::Back => "back",
+            Action::FillArray => "fill_array",
+            Action::Bookmark => "bookmark",
+            Action::MoreContext => "more_context",
+            Action::LessContext => "less_context",
+            Action::Heat => "heat",
+            Action::Watch => "watch",
+            Action::ClearSlot => "clear_slot",
+            Action::ClearAll => "clear_all",
+            Action::Restart => "restart",
+        }
+    }
+}
+
+/// A key, and whether `<Ctrl>` or `<Alt>` has to be held with it. Shift is
+/// part of the key, so `C` is `<Shift> <C>`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Binding {
+    pub key: Key,
+    pub ctrl: bool,
+    pub alt: bool,
+}
+
+impl Binding {
+    const fn plain(c: char) -> Self {
+        Self {
+            key: Key::Char(c),
+            ctrl: false,
+            alt: false,
+        }
+    }
+
+    const fn ctrl(c: char) -> Self {
+        Self {
+            key: Key::Char(c),
+            ctrl: true,
+            alt: false,
+        }
+    }
+
+    pub fn matches(&self, input: &Input) -> bool {
+        input.key == self.key && input.ctrl == self.ctrl && input.alt == self.alt
+    }
+
+    /// Whether this would also be typed into a text box.
+    pub fn is_plain(&self) -> bool {
+        !self.ctrl && !self.alt
+    }
+
+    /// Parses things like `ctrl+w`, `C`, `?` and `f5`.
+    pub fn parse(text: &str) -> Result<Self> {
+        // `+` itself can be bound, as `+` or `ctrl++`
+        let (modifiers, key) = if text == "+" {
+            ("", "+")
+        } else if let Some(modifiers) = text.strip_suffix("++") {
+            (modifiers, "+")
+        } else {
+            text.rsplit_once('+').unwrap_or(("", text))
+        };
+
+        let mut result = Self {
+            key: Self::parse_key(key).ok_or_else(|| eyre!("{key:?} is not a key!"))?,
+            ctrl: false,
+            alt: false,
+        };
+
+        for modifier in modifiers.split('+').filter(|x| !x.is_empty()) {
+            match modifier.to_lowercase().as_str() {
+                "ctrl" => result.ctrl = true,
+                "alt" => result.alt = true,
+                _ => bail!("Unknown modifier {modifier:?} in {text:?}"),
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn parse_key(key: &str) -> Option<Key> {
+        let mut chars = key.chars();
+
+        if let (Some(c), None) = (chars.next(), chars.next()) {
+            return Some(Key::Char(c));
+        }
+
+        let key = match key.to_lowercase().as_str() {
+            "enter" => Key::Enter,
+            "esc" => Key::Esc,
+            "tab" => Key::Tab,
+            "backspace" => Key::Backspace,
+            "delete" => Key::Delete,
+            "home" => Key::Home,
+            "end" => Key::End,
+            "pageup" => Key::PageUp,
+            "pagedown" => Key::PageDown,
+            "space" => Key::Char(' '),
+            other => Key::F(other.strip_prefix('f')?.parse().ok()?),
+        };
+
+        Some(key)
+    }
+}
+
+impl Display for Binding {
+    /// Written the way the command bar shows keys, like `<Ctrl> <W>`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.ctrl {
+            write!(f, "<Ctrl> ")?;
+        }
+
+        if self.alt {
+            write!(f, "<Alt> ")?;
+        }
+
+        match self.key {
+            Key::Char(' ') => write!(f, "<Space>"),
+            Key::Char(c) if c.is_uppercase() => write!(f, "<Shift> <{c}>"),
+            Key::Char(c) => write!(f, "<{}>", c.to_uppercase()),
+            Key::F(n) => write!(f, "<F{n}>"),
+            Key::PageUp => write!(f, "<Page Up>"),
+            Key::PageDown => write!(f, "<Page Down>"),
+            key => write!(f, "<{key:?}>"),
+        }
+    }
+}
+
+/// Which key each action is bound to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Keymap {
+    bindings: HashMap<Action, Binding>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let bindings = [
+            (Action::Quit, Binding::ctrl('q')),
+            (Action::Window, Binding::ctrl('w')),
+            (Action::Help, Binding::plain('?')),
+            (Action::PickInstructions, Binding::plain('i')),
+            (Action::PickRegisters, Binding::plain('r')),
+            (Action::PickMemory, Binding::plain('m')),
+            (Action::Save, Binding::plain('s')),
+            (Action::Load, Binding::plain('l')),
+            (Action::Pipeline, Binding::plain('p')),
+            (Action::Theme, Binding::plain('t')),
+            (Action::Signed, Binding::plain('n')),
+            (Action::FixBranches, Binding::plain('b')),
+            (Action::Encoding, Binding::plain('h')),
+            (Action::Stack, Binding::plain('k')),
+            (Action::Problems, Binding::plain('v')),
+            (Action::Lints, Binding::plain('w')),
+            (Action::Record, Binding::plain('o')),
+            (Action::Replay, Binding::plain('y')),
+            (
+                Action::Step,
+                Binding {
+                    key: Key::Enter,
+                    ctrl: false,
+                    alt: false,
+                },
+            ),
+            (Action::Play, Binding::plain(' ')),
+            (Action::RunAll, Binding::plain('R')),
+            (Action::Burst, Binding::plain('>')),
+            (Action::Faster, Binding::plain(']')),
+            (Action::Slower, Binding::plain('[')),
+            (Action::Reset, Binding::plain('0')),
+            (Action::Edit, Binding::ctrl('r')),
+            (Action::Duplicate, Binding::plain('d')),
+            (Action::Delete, Binding::plain('x')),
+            (Action::ToggleComment, Binding::plain('/')),
+            (Action::Note, Binding::plain('n')),
+            (Action::Snippet, Binding::plain('s')),
+            (Action::Entry, Binding::plain('e')),
+            (Action::WordSize, Binding::plain('w')),
+            (Action::Inspect, Binding::plain('i')),
+            (Action::Goto, Binding::plain('g')),
+            (Action::GotoRegister, Binding::plain('x')),
+            (Action::Follow, Binding::plain('f')),
+            (Action::Back, Binding::plain('b')),
+            (Action::FillArray, Binding::plain('a')),
+            (Action::Bookmark, Binding::plain('k')),
+            (Action::MoreContext, Binding::plain('+')),
+            (Action::LessContext, Binding::plain('-')),
+            (Action::Heat, Binding::plain('h')),
+            (Action::Watch, Binding::plain('w')),
+            (Action::ClearSlot, Binding::plain('c')),
+            (Action::ClearAll, Binding::plain('C')),
+            (Action::Restart, Binding::plain('r')),
+        ];
+
+        Self {
+            bindings: bindings.into_iter().collect(),
+        }
+    }
+}
+
+impl Keymap {
+    pub fn get(&self, action: Action) -> Binding {
+        self.bindings[&action]
+    }
+
+    pub fn is(&self, action: Action, input: &Input) -> bool {
+        self.get(action).matches(input)
+    }
+
+    /// Reads a TOML table of action names to keys, like
+    /// `window = "ctrl+o"`, with anything left out keeping its default.
+    pub fn from_config(text: &str) -> Result<Self> {
+        let entries = toml::from_str::<HashMap<String, String>>(text)?;
+
+        let mut keymap = Self::default();
+
+        for (name, binding) in &entries {
+            let Some(&action) = Action::ALL.iter().find(|x| x.name() == name) else {
+                bail!("Unknown action {name:?}");
+            };
+
+            keymap.bindings.insert(action, Binding::parse(binding)?);
+        }
+
+        Ok(keymap)
+    }
+}
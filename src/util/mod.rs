@@ -1,22 +1,37 @@
 use std::{collections::HashSet, ops::Range};
 
 use ratatui::{
+    buffer::Buffer,
     layout::{Constraint, Flex, Layout, Rect},
-    style::Stylize,
-    text::Line,
-    widgets::block::Title,
+    style::{Color, Stylize},
+    symbols::border,
+    text::{Line, Text},
+    widgets::{block::Title, Block, Clear, Widget},
 };
 
 use crate::simulator::Memory;
 
+mod keymap;
+pub use keymap::{Action, Binding, Keymap};
+
+pub use cs251simulator::theme::Theme;
+
+mod prefs;
+pub use prefs::{remember_file, Preferences};
+
 pub fn get_ranges(
     memory: &Memory,
     around: u64,
     extras: impl IntoIterator<Item = u64>,
 ) -> Vec<Range<u64>> {
+    get_ranges_around(around, memory.get_used().chain(extras))
+}
+
+/// Like `get_ranges`, but only around `entries` rather than all used memory.
+pub fn get_ranges_around(around: u64, entries: impl IntoIterator<Item = u64>) -> Vec<Range<u64>> {
     let mut to_see = HashSet::new();
 
-    for entry in memory.get_used().chain(extras) {
+    for entry in entries {
         to_see.insert(entry);
 
         for i in 1..around + 1 {
@@ -63,14 +78,94 @@ pub fn center(area: Rect, horizontal: Constraint, vertical: Constraint) -> Rect
     area
 }
 
-pub fn make_title(name: &'static str, picked: bool) -> Title {
+pub fn make_title(name: &'static str, picked: bool, theme: &Theme) -> Title<'static> {
     if picked {
         Title::from(Line::default().spans([
             " ".into(),
-            name.bold().blue().underlined(),
+            name.bold().fg(theme.keyword).underlined(),
             " ".into(),
         ]))
     } else {
-        Title::from(Line::default().spans([" ".into(), name.bold().blue(), " ".into()]))
+        Title::from(Line::default().spans([" ".into(), name.bold().fg(theme.keyword), " ".into()]))
     }
 }
+
+/// Makes the value on a `name: value` line stand out, for something the
+/// last step changed.
+pub fn highlight_change(line: &mut Line, theme: &Theme) {
+    if let Some(value) = line.spans.get_mut(1) {
+        value.style = value.style.fg(theme.dest).bold();
+    }
+}
+
+/// Draws an error box along the bottom of `area`.
+pub fn render_message(message: &str, theme: &Theme, area: Rect, buf: &mut Buffer) {
+    render_notice(" Error ", message, theme.error, area, buf);
+}
+
+/// Draws a box along the bottom of `area` with `value` as unsigned, signed,
+/// hex and binary, and each of its bytes as ASCII, most significant first.
+/// Only the low `word_bits` count for the signed value.
+pub fn render_value(value: u64, word_bits: u8, theme: &Theme, area: Rect, buf: &mut Buffer) {
+    let bytes = value.to_be_bytes();
+    let binary = bytes.map(|x| format!("{x:08b}"));
+    let ascii = bytes
+        .map(|x| {
+            if x == b' ' || x.is_ascii_graphic() {
+                x as char
+            } else {
+                '.'
+            }
+        })
+        .iter()
+        .collect::<String>();
+    let unused = 64 - word_bits as u32;
+
+    let lines = [
+        ("Unsigned", value.to_string()),
+        ("Signed", ((value << unused) as i64 >> unused).to_string()),
+        ("Hex", format!("0x{value:016X}")),
+        ("Binary", binary[..4].join(" ")),
+        ("", binary[4..].join(" ")),
+        ("ASCII", ascii),
+    ]
+    .map(|(name, value)| Line::from(vec![format!("{name:<9}").fg(theme.muted), value.into()]));
+
+    let block = Block::bordered()
+        .fg(theme.dialog)
+        .title(Title::from(" Value "))
+        .border_set(border::ROUNDED);
+
+    let bottom_bits = Layout::vertical([
+        Constraint::Fill(1),
+        Constraint::Length(lines.len() as u16 + 2),
+    ])
+    .areas::<2>(area)[1];
+
+    let inner = block.inner(bottom_bits);
+
+    block.render(bottom_bits, buf);
+
+    Clear.render(inner, buf);
+
+    Text::from(lines.to_vec()).render(inner, buf);
+}
+
+/// Draws a box titled `title` along the bottom of `area`.
+pub fn render_notice(title: &str, message: &str, color: Color, area: Rect, buf: &mut Buffer) {
+    let block = Block::bordered()
+        .fg(color)
+        .title(Title::from(title))
+        .border_set(border::ROUNDED);
+
+    let bottom_bits =
+        Layout::vertical([Constraint::Fill(1), Constraint::Length(3)]).areas::<2>(area)[1];
+
+    let inner = block.inner(bottom_bits);
+
+    block.render(bottom_bits, buf);
+
+    Clear.render(inner, buf);
+
+    message.fg(color).render(inner, buf);
+}
@@ -0,0 +1,90 @@
+use std::path::PathBuf;
+
+use color_eyre::{eyre::OptionExt, Result};
+use serde::{Deserialize, Serialize};
+
+/// How many files `Preferences::recent_files` remembers.
+pub const MAX_RECENT_FILES: usize = 8;
+
+/// How the UI was last set up to look, kept between sessions. None of the
+/// program's state goes in here, that's what saving is for.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Preferences {
+    pub signed: bool,
+    pub fix_branches: bool,
+    pub show_encoding: bool,
+    pub show_stack: bool,
+    /// Whether the problems overlay also lists warnings
+    pub lints: bool,
+    /// Whether the light theme was in use, ignored when `--theme` is given
+    pub light_theme: bool,
+    /// Memory slots shown around each used one
+    pub context: u64,
+    /// Instructions run at once by the burst key
+    pub burst: usize,
+    /// Instructions PC is remembered at, shown under the instructions
+    pub history: usize,
+    /// Files last loaded or saved, most recent first
+    pub recent_files: Vec<String>,
+}
+
+impl Default for Preferences {
+    fn default() -> Self {
+        Self {
+            signed: false,
+            fix_branches: true,
+            show_encoding: false,
+            show_stack: false,
+            lints: false,
+            light_theme: false,
+            context: 1,
+            burst: 10,
+            history: 10,
+            recent_files: Vec::new(),
+        }
+    }
+}
+
+/// Moves `path` to the front of `recent`, made absolute so it still works
+/// from elsewhere, forgetting the oldest past `MAX_RECENT_FILES`.
+pub fn remember_file(recent: &mut Vec<String>, path: &str) {
+    let path = std::path::absolute(path)
+        .map_or_else(|_| path.to_string(), |x| x.to_string_lossy().into_owned());
+
+    recent.retain(|x| *x != path);
+    recent.insert(0, path);
+    recent.truncate(MAX_RECENT_FILES);
+}
+
+impl Preferences {
+    /// `cs251simulator/preferences.toml` in the OS's config directory.
+    pub fn path() -> Option<PathBuf> {
+        Some(
+            dirs::config_dir()?
+                .join("cs251simulator")
+                .join("preferences.toml"),
+        )
+    }
+
+    /// The saved preferences, or the defaults if there aren't any or they
+    /// can't be read.
+    pub fn load() -> Self {
+        Self::path()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .and_then(|text| toml::from_str(&text).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path().ok_or_eyre("No config directory to save preferences in")?;
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        std::fs::write(path, toml::to_string(self)?)?;
+
+        Ok(())
+    }
+}
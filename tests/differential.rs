@@ -0,0 +1,119 @@
+//! Runs every program in `tests/programs` and compares where it ends up
+//! with the snapshot next to it, so a change to how instructions behave
+//! shows up as a diff against state someone has already checked by hand.
+//!
+//! `foo.s` is read as a listing, and `foo.json` holds its final state. Run
+//! `UPDATE_SNAPSHOTS=1 cargo test --test differential` to rewrite the
+//! snapshots from the current simulator, then check the diff before
+//! committing it.
+
+use std::{collections::BTreeMap, fs, path::Path};
+
+use cs251simulator::simulator::{register_name, RunningState, Simulator};
+use serde::{Deserialize, Serialize};
+
+/// Programs which loop forever stop here instead.
+const STEP_LIMIT: u64 = 10_000;
+
+/// Everything a program can leave behind. Zero registers and memory slots
+/// are left out, so snapshots only list what the program touched.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Snapshot {
+    /// `halted`, `finished`, `step limit` or the error which stopped it
+    stopped: String,
+    steps: u64,
+    cycles: u64,
+    /// byte address, like the register view shows it
+    pc: u64,
+    registers: BTreeMap<String, u64>,
+    /// byte address -> value
+    memory: BTreeMap<u64, u64>,
+}
+
+fn run(listing: &str) -> Snapshot {
+    let (mut sim, warnings) = Simulator::from_listing(listing).unwrap();
+    assert!(warnings.is_empty(), "{warnings:?}");
+
+    let mut steps = 0;
+
+    let stopped = loop {
+        if steps == STEP_LIMIT {
+            break "step limit".to_string();
+        }
+
+        if sim.is_finished() {
+            break "finished".to_string();
+        }
+
+        steps += 1;
+
+        match sim.tick() {
+            Ok(RunningState::ShouldStop) => break "halted".to_string(),
+            Ok(_) => {}
+            Err(e) => break e.to_string(),
+        }
+    };
+
+    let registers = (0..31)
+        .map(|reg| (register_name(reg, 64), sim.registers.get(reg).unwrap()))
+        .filter(|&(_, val)| val != 0)
+        .collect();
+
+    let memory = sim
+        .memory
+        .get_used()
+        .map(|slot| (slot * 8, sim.memory.get(slot * 8).unwrap()))
+        .filter(|&(_, val)| val != 0)
+        .collect();
+
+    Snapshot {
+        stopped,
+        steps,
+        cycles: sim.cycles,
+        pc: sim.registers.pc * 4,
+        registers,
+        memory,
+    }
+}
+
+#[test]
+fn programs_match_snapshots() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/programs");
+    let update = std::env::var_os("UPDATE_SNAPSHOTS").is_some();
+
+    let mut programs = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|x| x == "s"))
+        .collect::<Vec<_>>();
+    programs.sort();
+
+    assert!(!programs.is_empty(), "no programs in {}", dir.display());
+
+    let mut failures = Vec::new();
+
+    for program in programs {
+        let name = program.file_stem().unwrap().to_string_lossy().into_owned();
+        let got = run(&fs::read_to_string(&program).unwrap());
+        let snapshot = program.with_extension("json");
+
+        if update {
+            let json = serde_json::to_string_pretty(&got).unwrap();
+            fs::write(&snapshot, json + "\n").unwrap();
+            continue;
+        }
+
+        let Ok(expected) = fs::read_to_string(&snapshot) else {
+            failures.push(format!("{name}: no snapshot, got {got:#?}"));
+            continue;
+        };
+
+        let expected: Snapshot = serde_json::from_str(&expected).unwrap();
+
+        if got != expected {
+            failures.push(format!("{name}: expected {expected:#?}, got {got:#?}"));
+        }
+    }
+
+    assert!(failures.is_empty(), "{}", failures.join("\n\n"));
+}
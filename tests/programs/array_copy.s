@@ -0,0 +1,19 @@
+// Fill M[0..32] with 3, 1, 4, 1 and copy it to M[64..96]
+        ADDI X9, XZR, #3
+        STUR X9, [XZR, #0]
+        ADDI X9, XZR, #1
+        STUR X9, [XZR, #8]
+        ADDI X9, XZR, #4
+        STUR X9, [XZR, #16]
+        ADDI X9, XZR, #1
+        STUR X9, [XZR, #24]
+        ADDI X10, XZR, #0       // source
+        ADDI X11, XZR, #64      // destination
+        ADDI X12, XZR, #4       // slots left
+loop:   LDUR X9, [X10, #0]
+        STUR X9, [X11, #0]
+        ADDI X10, X10, #8
+        ADDI X11, X11, #8
+        SUBI X12, X12, #1
+        CBNZ X12, #-5
+        HALT
@@ -0,0 +1,15 @@
+// Sum an array from the data section into M[0]
+        .data 0x100
+arr:    .word 1, 2, 3
+        .word -1, 0x10          // not summed
+        .text
+        ADDI X9, XZR, #256      // address of arr
+        ADDI X10, XZR, #3       // slots left
+        ADD  X11, XZR, XZR      // sum
+loop:   LDUR X12, [X9, #0]
+        ADD  X11, X11, X12
+        ADDI X9, X9, #8
+        SUBI X10, X10, #1
+        CBNZ X10, #-4
+        STUR X11, [XZR, #0]
+        HALT
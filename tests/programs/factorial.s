@@ -0,0 +1,12 @@
+// X2 = 5!, multiplying by repeated addition
+        ADDI X1, XZR, #5
+        ADDI X2, XZR, #1
+outer:  ADD X3, XZR, XZR        // X3 = X2 * X1
+        ADD X4, X1, XZR
+inner:  ADD X3, X3, X2
+        SUBI X4, X4, #1
+        CBNZ X4, #-2
+        ADD X2, X3, XZR
+        SUBI X1, X1, #1
+        CBNZ X1, #-7
+        HALT
@@ -0,0 +1,7 @@
+// X2 = 10 + 9 + ... + 1
+        ADDI X1, XZR, #10
+        ADD X2, XZR, XZR
+loop:   ADD X2, X2, X1
+        SUBI X1, X1, #1
+        CBNZ X1, #-2
+        HALT
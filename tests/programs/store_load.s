@@ -0,0 +1,11 @@
+// Each load reads back the store right before it, with nothing in between
+        ADDI X1, XZR, #7
+        ADDI X2, XZR, #64       // address
+        STUR X1, [X2, #0]
+        LDUR X3, [X2, #0]       // 7
+        ADDI X1, X1, #1
+        STUR X1, [X2, #0]
+        LDUR X4, [X2, #0]       // 8, not the 7 from before
+        STUR X4, [XZR, #8]
+        LDUR X5, [X2, #-56]     // the same slot through another base
+        HALT